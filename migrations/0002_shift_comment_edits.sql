@@ -0,0 +1,2 @@
+-- Comments can be edited by their author or a schedule admin
+alter table shift_comment add column if not exists edited_at timestamptz null;
@@ -0,0 +1,2 @@
+-- Supports overlap checks when assigning a user to a shift
+create index if not exists idx_shift_assignee_time on shift(assigned_user_id, starts_at, ends_at);
@@ -0,0 +1,2 @@
+-- IANA time zone used to interpret template slots and local-date ranges
+alter table schedule add column if not exists timezone text not null default 'UTC';
@@ -0,0 +1,6 @@
+-- Per-user token for subscribing to iCalendar feeds (calendar apps can't send a JWT)
+create table if not exists calendar_feed_token (
+  user_id uuid primary key references app_user(id) on delete cascade,
+  token_hash text not null unique,
+  created_at timestamptz not null default now()
+);
@@ -0,0 +1,2 @@
+-- Round-robin position per template slot, so consecutive applications continue the rotation
+alter table rotation_template add column if not exists rotation_state jsonb not null default '{}'::jsonb;
@@ -0,0 +1,16 @@
+-- Tie generated shifts to the template and cycle week they came from
+alter table shift add column if not exists template_id uuid null references rotation_template(id) on delete set null;
+alter table shift add column if not exists template_week date null;
+create index if not exists idx_shift_template_week on shift(template_id, template_week);
+
+-- Responses to requests sent with an Idempotency-Key header, per user.
+-- A row with a null response marks a request still in progress.
+create table if not exists idempotency_key (
+  user_id uuid not null references app_user(id) on delete cascade,
+  key text not null,
+  scope text not null,
+  response_status int null,
+  response_body jsonb null,
+  created_at timestamptz not null default now(),
+  primary key (user_id, key)
+);
@@ -0,0 +1,21 @@
+-- Whether accepted swaps also need a schedule admin's approval
+alter table schedule add column if not exists swap_requires_approval boolean not null default false;
+
+-- Requests to give a shift away or trade it for another member's shift
+create table if not exists shift_swap (
+  id uuid primary key,
+  schedule_id uuid not null references schedule(id) on delete cascade,
+  shift_id uuid not null references shift(id) on delete cascade,
+  requester_id uuid not null references app_user(id) on delete cascade,
+  counterpart_id uuid not null references app_user(id) on delete cascade,
+  counterpart_shift_id uuid null references shift(id) on delete cascade, -- null = give away
+  message text null,
+  status text not null default 'pending', -- 'pending'|'accepted'|'declined'|'cancelled'|'expired'
+  counterpart_accepted_at timestamptz null,
+  decided_by uuid null references app_user(id) on delete set null,
+  expires_at timestamptz not null,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  constraint shift_swap_status_ok check (status in ('pending', 'accepted', 'declined', 'cancelled', 'expired'))
+);
+create index if not exists idx_shift_swap_schedule on shift_swap(schedule_id, status, created_at);
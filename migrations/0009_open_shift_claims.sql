@@ -0,0 +1,18 @@
+-- How members pick up unassigned shifts: 'open' | 'first_come' | 'approval'
+alter table schedule add column if not exists claim_policy text not null default 'open'
+  constraint schedule_claim_policy_ok check (claim_policy in ('open', 'first_come', 'approval'));
+
+-- Claims on open shifts waiting for (or decided by) an admin
+create table if not exists shift_claim (
+  id uuid primary key,
+  schedule_id uuid not null references schedule(id) on delete cascade,
+  shift_id uuid not null references shift(id) on delete cascade,
+  user_id uuid not null references app_user(id) on delete cascade,
+  status text not null default 'pending', -- 'pending'|'approved'|'rejected'
+  decided_by uuid null references app_user(id) on delete set null,
+  created_at timestamptz not null default now(),
+  decided_at timestamptz null,
+  constraint shift_claim_status_ok check (status in ('pending', 'approved', 'rejected'))
+);
+create unique index if not exists idx_shift_claim_pending on shift_claim(shift_id, user_id) where status = 'pending';
+create index if not exists idx_shift_claim_schedule on shift_claim(schedule_id, status, created_at);
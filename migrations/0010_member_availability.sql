@@ -0,0 +1,29 @@
+-- Recurring weekly unavailability per schedule member ("no Tuesday nights")
+create table if not exists member_unavailability (
+  id uuid primary key,
+  schedule_id uuid not null,
+  user_id uuid not null,
+  dow smallint not null, -- 0=Mon..6=Sun, in the schedule's time zone
+  period text not null, -- 'morning'|'afternoon'|'night'|'sleep'
+  note text null,
+  created_at timestamptz not null default now(),
+  foreign key (schedule_id, user_id) references schedule_member(schedule_id, user_id) on delete cascade,
+  constraint member_unavailability_dow_ok check (dow between 0 and 6),
+  unique (schedule_id, user_id, dow, period)
+);
+
+-- One-off date ranges (inclusive, local dates) that override the weekly pattern:
+-- away when available = false, available anyway when true
+create table if not exists member_availability_exception (
+  id uuid primary key,
+  schedule_id uuid not null,
+  user_id uuid not null,
+  starts_on date not null,
+  ends_on date not null,
+  available boolean not null default false,
+  note text null,
+  created_at timestamptz not null default now(),
+  foreign key (schedule_id, user_id) references schedule_member(schedule_id, user_id) on delete cascade,
+  constraint member_availability_exception_range_ok check (ends_on >= starts_on)
+);
+create index if not exists idx_member_availability_exception on member_availability_exception(schedule_id, user_id, starts_on);
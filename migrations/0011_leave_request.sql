@@ -0,0 +1,18 @@
+-- Time-off requests decided by schedule admins
+create table if not exists leave_request (
+  id uuid primary key,
+  schedule_id uuid not null,
+  user_id uuid not null,
+  starts_on date not null, -- inclusive, local dates in the schedule's time zone
+  ends_on date not null,
+  reason text not null,
+  status text not null default 'pending', -- 'pending'|'approved'|'rejected'|'cancelled'
+  decided_by uuid null references app_user(id) on delete set null,
+  decision_note text null,
+  decided_at timestamptz null,
+  created_at timestamptz not null default now(),
+  foreign key (schedule_id, user_id) references schedule_member(schedule_id, user_id) on delete cascade,
+  constraint leave_request_range_ok check (ends_on >= starts_on),
+  constraint leave_request_status_ok check (status in ('pending', 'approved', 'rejected', 'cancelled'))
+);
+create index if not exists idx_leave_request_schedule on leave_request(schedule_id, status, starts_on);
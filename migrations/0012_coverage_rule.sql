@@ -0,0 +1,12 @@
+-- Head-count each schedule needs per weekday and period ("someone every Sleep")
+create table if not exists coverage_rule (
+  id uuid primary key,
+  schedule_id uuid not null references schedule(id) on delete cascade,
+  dow smallint not null, -- 0=Mon..6=Sun, in the schedule's time zone
+  period text not null, -- 'morning'|'afternoon'|'night'|'sleep'
+  required smallint not null,
+  created_at timestamptz not null default now(),
+  constraint coverage_rule_dow_ok check (dow between 0 and 6),
+  constraint coverage_rule_required_ok check (required > 0),
+  unique (schedule_id, dow, period)
+);
@@ -0,0 +1,34 @@
+-- Shifts can need more than one member: assignments move to their own table,
+-- with a head-count per shift and an optional lead
+alter table shift add column if not exists capacity smallint not null default 1
+  constraint shift_capacity_ok check (capacity > 0);
+
+create table if not exists shift_assignee (
+  shift_id uuid not null references shift(id) on delete cascade,
+  user_id uuid not null references app_user(id) on delete cascade,
+  lead boolean not null default false,
+  assigned_at timestamptz not null default now(),
+  primary key (shift_id, user_id)
+);
+create unique index if not exists shift_assignee_one_lead on shift_assignee(shift_id) where lead;
+create index if not exists idx_shift_assignee_user on shift_assignee(user_id, shift_id);
+
+insert into shift_assignee (shift_id, user_id, assigned_at)
+select id, assigned_user_id, created_at from shift where assigned_user_id is not null
+on conflict do nothing;
+
+-- Also drops idx_shift_assignee_time
+alter table shift drop column if exists assigned_user_id;
+
+-- Shifts with their assignees as parallel arrays, lead first, then by
+-- assignment time
+create or replace view shift_with_assignees as
+select s.id, s.schedule_id, s.starts_at, s.ends_at, s.period, s.capacity,
+       s.template_id, s.template_week, s.created_by, s.created_at,
+       array(select a.user_id from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_ids,
+       array(select a.lead from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_leads,
+       array(select a.assigned_at from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_assigned_at
+from shift s;
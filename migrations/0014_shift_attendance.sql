@@ -0,0 +1,31 @@
+-- Minutes after a shift starts before it counts as missed when nobody checked in
+alter table schedule add column if not exists check_in_grace_minutes integer not null default 15
+  constraint schedule_check_in_grace_ok check (check_in_grace_minutes >= 0);
+
+-- When each assignee actually arrived and left
+create table if not exists shift_attendance (
+  shift_id uuid not null references shift(id) on delete cascade,
+  user_id uuid not null references app_user(id) on delete cascade,
+  checked_in_at timestamptz null,
+  checked_out_at timestamptz null,
+  updated_at timestamptz not null default now(),
+  primary key (shift_id, user_id),
+  constraint shift_attendance_order_ok check (
+    checked_out_at is null or (checked_in_at is not null and checked_out_at > checked_in_at)
+  )
+);
+
+-- Admin corrections to attendance, keeping the values they replaced
+create table if not exists shift_attendance_correction (
+  id uuid primary key,
+  shift_id uuid not null references shift(id) on delete cascade,
+  user_id uuid not null references app_user(id) on delete cascade,
+  corrected_by uuid null references app_user(id) on delete set null,
+  previous_checked_in_at timestamptz null,
+  previous_checked_out_at timestamptz null,
+  checked_in_at timestamptz null,
+  checked_out_at timestamptz null,
+  reason text not null,
+  created_at timestamptz not null default now()
+);
+create index if not exists idx_shift_attendance_correction_shift on shift_attendance_correction(shift_id, created_at);
@@ -0,0 +1,26 @@
+-- Duties every matching shift of a schedule gets, e.g. "give meds" at 09:00
+create table if not exists schedule_task (
+  id uuid primary key,
+  schedule_id uuid not null references schedule(id) on delete cascade,
+  title text not null,
+  due_time time null, -- local time in the schedule's zone; null = any time in the shift
+  period text null, -- only shifts of this period; null = every shift
+  created_at timestamptz not null default now(),
+  constraint schedule_task_period_ok check (period is null or period in ('morning', 'afternoon', 'night', 'sleep'))
+);
+create index if not exists idx_schedule_task_schedule on schedule_task(schedule_id);
+
+-- Checklist items copied onto a shift when it is created, ticked off by its assignees
+create table if not exists shift_task (
+  id uuid primary key,
+  shift_id uuid not null references shift(id) on delete cascade,
+  schedule_task_id uuid null references schedule_task(id) on delete set null,
+  title text not null,
+  due_at timestamptz null,
+  position integer not null,
+  done_at timestamptz null,
+  done_by uuid null references app_user(id) on delete set null,
+  created_at timestamptz not null default now()
+);
+create index if not exists idx_shift_task_shift on shift_task(shift_id, position);
+create index if not exists idx_shift_task_open on shift_task(shift_id) where done_at is null;
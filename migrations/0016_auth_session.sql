@@ -0,0 +1,19 @@
+-- One signed-in device; access tokens carry its id and stop working once it is revoked
+create table if not exists auth_session (
+  id uuid primary key,
+  user_id uuid not null references app_user(id) on delete cascade,
+  created_at timestamptz not null default now(),
+  last_refreshed_at timestamptz not null default now(),
+  revoked_at timestamptz null
+);
+create index if not exists idx_auth_session_user on auth_session(user_id) where revoked_at is null;
+
+-- Single-use refresh tokens (hashed); each refresh replaces the token with the next in its session's chain
+create table if not exists refresh_token (
+  token_hash text primary key,
+  session_id uuid not null references auth_session(id) on delete cascade,
+  created_at timestamptz not null default now(),
+  expires_at timestamptz not null,
+  used_at timestamptz null
+);
+create index if not exists idx_refresh_token_session on refresh_token(session_id);
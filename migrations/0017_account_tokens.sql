@@ -0,0 +1,15 @@
+-- Users prove they own their address by following a mailed link; existing accounts count as verified
+alter table app_user add column if not exists email_verified_at timestamptz null;
+update app_user set email_verified_at = created_at where email_verified_at is null;
+
+-- Single-use, expiring tokens (hashed) mailed for password resets and email verification
+create table if not exists account_token (
+  token_hash text primary key,
+  user_id uuid not null references app_user(id) on delete cascade,
+  purpose text not null,
+  expires_at timestamptz not null,
+  used_at timestamptz null,
+  created_at timestamptz not null default now(),
+  constraint account_token_purpose_ok check (purpose in ('password_reset', 'email_verification'))
+);
+create index if not exists idx_account_token_user on account_token(user_id, purpose) where used_at is null;
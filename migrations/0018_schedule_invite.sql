@@ -0,0 +1,17 @@
+-- Invitations to join a schedule, sent to one email address or shared as a link
+create table if not exists schedule_invite (
+  id uuid primary key,
+  schedule_id uuid not null references schedule(id) on delete cascade,
+  email text null, -- only this address may accept; null = anyone with the link
+  token_hash text not null unique,
+  role text not null,
+  max_uses integer null, -- null = unlimited until it expires
+  uses integer not null default 0,
+  expires_at timestamptz not null,
+  created_by uuid not null references app_user(id) on delete cascade,
+  created_at timestamptz not null default now(),
+  revoked_at timestamptz null,
+  constraint schedule_invite_role_ok check (role in ('admin', 'user')),
+  constraint schedule_invite_uses_ok check (max_uses is null or (max_uses >= 1 and uses <= max_uses))
+);
+create index if not exists idx_schedule_invite_schedule on schedule_invite(schedule_id) where revoked_at is null;
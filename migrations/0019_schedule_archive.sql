@@ -0,0 +1,2 @@
+-- Archived schedules are hidden from members' lists by default and read-only
+alter table schedule add column if not exists archived_at timestamptz null;
@@ -0,0 +1,19 @@
+-- When a shift's time, period or capacity last changed; calendar feeds report it
+-- as LAST-MODIFIED and derive SEQUENCE from it
+alter table shift add column if not exists updated_at timestamptz null;
+update shift set updated_at = created_at where updated_at is null;
+alter table shift alter column updated_at set default now();
+alter table shift alter column updated_at set not null;
+
+-- New columns can only go at the end of a replaced view
+create or replace view shift_with_assignees as
+select s.id, s.schedule_id, s.starts_at, s.ends_at, s.period, s.capacity,
+       s.template_id, s.template_week, s.created_by, s.created_at,
+       array(select a.user_id from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_ids,
+       array(select a.lead from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_leads,
+       array(select a.assigned_at from shift_assignee a where a.shift_id = s.id
+             order by a.lead desc, a.assigned_at, a.user_id) as assignee_assigned_at,
+       s.updated_at
+from shift s;
@@ -0,0 +1,256 @@
+//! Planned versus actual times for shifts, from their check-ins and check-outs.
+
+use crate::models::{Period, Shift, ShiftAttendance};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Grace period new schedules get, in minutes after a shift starts.
+pub const DEFAULT_CHECK_IN_GRACE_MINUTES: i32 = 15;
+
+/// How long before a shift starts its assignees may check in.
+pub const EARLY_CHECK_IN_MINUTES: i64 = 60;
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttendanceStatus {
+    /// Nobody has checked in yet, but the grace period hasn't run out.
+    Scheduled,
+    /// Someone checked in and hasn't checked out.
+    InProgress,
+    /// Everyone who checked in has checked out.
+    Completed,
+    /// Nobody checked in within the grace period after `starts_at`.
+    Missed,
+}
+
+/// A shift's state at `now`, given its attendance entries.
+pub fn status(
+    shift: &Shift,
+    entries: &[&ShiftAttendance],
+    grace_minutes: i32,
+    now: DateTime<Utc>,
+) -> AttendanceStatus {
+    let checked_in: Vec<_> = entries
+        .iter()
+        .filter(|e| e.checked_in_at.is_some())
+        .collect();
+    if checked_in.is_empty() {
+        if now < shift.starts_at + Duration::minutes(grace_minutes.into()) {
+            AttendanceStatus::Scheduled
+        } else {
+            AttendanceStatus::Missed
+        }
+    } else if checked_in.iter().all(|e| e.checked_out_at.is_some()) {
+        AttendanceStatus::Completed
+    } else {
+        AttendanceStatus::InProgress
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AssigneeAttendance {
+    pub user_id: Uuid,
+    /// False for someone who checked in but has since been taken off the shift.
+    pub assigned: bool,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+    /// Minutes between check-in and check-out, once both are known.
+    pub worked_minutes: Option<i64>,
+    /// Minutes after `starts_at` the check-in came; negative when early.
+    pub late_minutes: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftReport {
+    pub shift_id: Uuid,
+    pub period: Period,
+    pub planned_starts_at: DateTime<Utc>,
+    pub planned_ends_at: DateTime<Utc>,
+    pub planned_minutes: i64,
+    pub worked_minutes: i64,
+    pub status: AttendanceStatus,
+    pub assignees: Vec<AssigneeAttendance>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AttendanceReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Planned minutes count every place on a shift, filled or not.
+    pub planned_minutes: i64,
+    pub worked_minutes: i64,
+    pub missed: usize,
+    pub shifts: Vec<ShiftReport>,
+}
+
+/// Planned against actual time for one shift. Assignees come first in
+/// shift order, then anyone else with an entry.
+pub fn shift_report(
+    shift: &Shift,
+    entries: &[&ShiftAttendance],
+    grace_minutes: i32,
+    now: DateTime<Utc>,
+) -> ShiftReport {
+    let entry_for = |user_id: Uuid| entries.iter().find(|e| e.user_id == user_id).copied();
+    let row = |user_id: Uuid, assigned: bool| {
+        let entry = entry_for(user_id);
+        let checked_in_at = entry.and_then(|e| e.checked_in_at);
+        let checked_out_at = entry.and_then(|e| e.checked_out_at);
+        AssigneeAttendance {
+            user_id,
+            assigned,
+            checked_in_at,
+            checked_out_at,
+            worked_minutes: checked_in_at
+                .zip(checked_out_at)
+                .map(|(i, o)| (o - i).num_minutes()),
+            late_minutes: checked_in_at.map(|i| (i - shift.starts_at).num_minutes()),
+        }
+    };
+
+    let mut assignees: Vec<_> = shift
+        .assignees
+        .iter()
+        .map(|a| row(a.user_id, true))
+        .collect();
+    for e in entries.iter().filter(|e| !shift.is_assigned(e.user_id)) {
+        assignees.push(row(e.user_id, false));
+    }
+
+    ShiftReport {
+        shift_id: shift.id,
+        period: shift.period,
+        planned_starts_at: shift.starts_at,
+        planned_ends_at: shift.ends_at,
+        planned_minutes: (shift.ends_at - shift.starts_at).num_minutes()
+            * i64::from(shift.capacity),
+        worked_minutes: assignees.iter().filter_map(|a| a.worked_minutes).sum(),
+        status: status(shift, entries, grace_minutes, now),
+        assignees,
+    }
+}
+
+pub fn build(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    shifts: &[Shift],
+    entries: &[ShiftAttendance],
+    grace_minutes: i32,
+    now: DateTime<Utc>,
+) -> AttendanceReport {
+    let shifts: Vec<ShiftReport> = shifts
+        .iter()
+        .map(|shift| {
+            let mine: Vec<_> = entries.iter().filter(|e| e.shift_id == shift.id).collect();
+            shift_report(shift, &mine, grace_minutes, now)
+        })
+        .collect();
+    AttendanceReport {
+        from,
+        to,
+        planned_minutes: shifts.iter().map(|s| s.planned_minutes).sum(),
+        worked_minutes: shifts.iter().map(|s| s.worked_minutes).sum(),
+        missed: shifts
+            .iter()
+            .filter(|s| s.status == AttendanceStatus::Missed)
+            .count(),
+        shifts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ShiftAssignee;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 3, 3, h, m, 0).unwrap()
+    }
+
+    fn shift(assignees: &[Uuid]) -> Shift {
+        Shift {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::new_v4(),
+            starts_at: at(8, 0),
+            ends_at: at(12, 0),
+            period: Period::Morning,
+            capacity: 2,
+            assignees: assignees
+                .iter()
+                .map(|&user_id| ShiftAssignee {
+                    user_id,
+                    lead: false,
+                    assigned_at: at(0, 0),
+                })
+                .collect(),
+            template_id: None,
+            template_week: None,
+            created_by: Uuid::new_v4(),
+            created_at: at(0, 0),
+            updated_at: at(0, 0),
+        }
+    }
+
+    fn entry(
+        shift: &Shift,
+        user_id: Uuid,
+        checked_in_at: Option<DateTime<Utc>>,
+        checked_out_at: Option<DateTime<Utc>>,
+    ) -> ShiftAttendance {
+        ShiftAttendance {
+            shift_id: shift.id,
+            user_id,
+            checked_in_at,
+            checked_out_at,
+            updated_at: at(0, 0),
+        }
+    }
+
+    #[test]
+    fn missed_only_after_grace_period() {
+        let s = shift(&[Uuid::new_v4()]);
+        assert_eq!(status(&s, &[], 15, at(8, 14)), AttendanceStatus::Scheduled);
+        assert_eq!(status(&s, &[], 15, at(8, 15)), AttendanceStatus::Missed);
+        assert_eq!(status(&s, &[], 0, at(8, 0)), AttendanceStatus::Missed);
+    }
+
+    #[test]
+    fn completed_once_everyone_checked_out() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let s = shift(&[a, b]);
+        let done = entry(&s, a, Some(at(7, 55)), Some(at(12, 5)));
+        let working = entry(&s, b, Some(at(8, 20)), None);
+        assert_eq!(
+            status(&s, &[&done, &working], 15, at(10, 0)),
+            AttendanceStatus::InProgress
+        );
+        assert_eq!(
+            status(&s, &[&done], 15, at(13, 0)),
+            AttendanceStatus::Completed
+        );
+    }
+
+    #[test]
+    fn report_compares_planned_and_worked_minutes() {
+        let (a, b, gone) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let s = shift(&[a, b]);
+        let entries = [
+            entry(&s, a, Some(at(8, 10)), Some(at(12, 0))),
+            entry(&s, gone, Some(at(7, 50)), Some(at(8, 10))),
+        ];
+        let report = build(at(0, 0), at(23, 0), &[s], &entries, 15, at(13, 0));
+
+        assert_eq!(report.planned_minutes, 480);
+        assert_eq!(report.worked_minutes, 250);
+        assert_eq!(report.missed, 0);
+        let rows = &report.shifts[0].assignees;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].late_minutes, Some(10));
+        assert_eq!(rows[0].worked_minutes, Some(230));
+        assert_eq!(rows[1].checked_in_at, None);
+        assert!(!rows[2].assigned);
+        assert_eq!(rows[2].late_minutes, Some(-10));
+    }
+}
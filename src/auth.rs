@@ -5,8 +5,9 @@ use argon2::{
 };
 use chrono::{Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
-use rand::rngs::OsRng;
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 #[derive(Clone)]
@@ -24,9 +25,23 @@ impl JwtKeys {
     }
 }
 
+/// Lifetime of an access token; clients renew it with their refresh token.
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+
+/// How long a refresh token stays usable; each refresh starts a new one.
+pub const REFRESH_TOKEN_DAYS: i64 = 30;
+
+/// How long a mailed password reset link works.
+pub const PASSWORD_RESET_MINUTES: i64 = 60;
+
+/// How long a mailed email verification link works.
+pub const EMAIL_VERIFICATION_HOURS: i64 = 48;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
+    /// Session the token was issued for.
+    pub sid: String,
     pub exp: usize,
     pub is_superadmin: bool,
 }
@@ -48,10 +63,16 @@ pub fn verify_password(password: &str, password_hash: &str) -> AppResult<bool> {
         .is_ok())
 }
 
-pub fn issue_jwt(user_id: Uuid, is_superadmin: bool, keys: &JwtKeys) -> AppResult<String> {
-    let exp = (Utc::now() + Duration::hours(24)).timestamp() as usize;
+pub fn issue_jwt(
+    user_id: Uuid,
+    is_superadmin: bool,
+    session_id: Uuid,
+    keys: &JwtKeys,
+) -> AppResult<String> {
+    let exp = (Utc::now() + Duration::minutes(ACCESS_TOKEN_MINUTES)).timestamp() as usize;
     let claims = Claims {
         sub: user_id.to_string(),
+        sid: session_id.to_string(),
         exp,
         is_superadmin,
     };
@@ -65,6 +86,17 @@ pub fn decode_jwt(token: &str, keys: &JwtKeys) -> AppResult<Claims> {
     Ok(data.claims)
 }
 
+/// Random URL-safe token for links and feeds; only its hash is stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,10 +111,19 @@ mod tests {
     #[test]
     fn jwt_roundtrip() {
         let keys = JwtKeys::new("dev-secret");
-        let uid = Uuid::new_v4();
-        let token = issue_jwt(uid, true, &keys).unwrap();
+        let (uid, sid) = (Uuid::new_v4(), Uuid::new_v4());
+        let token = issue_jwt(uid, true, sid, &keys).unwrap();
         let claims = decode_jwt(&token, &keys).unwrap();
         assert_eq!(claims.sub, uid.to_string());
+        assert_eq!(claims.sid, sid.to_string());
         assert!(claims.is_superadmin);
     }
+
+    #[test]
+    fn token_hash_is_stable() {
+        let token = generate_token();
+        assert_eq!(token.len(), 64);
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+    }
 }
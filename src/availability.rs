@@ -0,0 +1,163 @@
+//! Checking shifts against a member's availability.
+
+use crate::models::{AvailabilityException, LeaveRequest, Shift, WeeklyUnavailability};
+use chrono::{Datelike, Duration, NaiveDate};
+use chrono_tz::Tz;
+
+/// Why the member can't work `shift`, or `None` if they can.
+///
+/// Days and periods are read in the schedule's `zone`. A shift clashes with
+/// an away exception if it touches any of its days. Otherwise an "available"
+/// exception covering the shift's start day overrides the weekly pattern,
+/// which is matched on the start day and the shift's period.
+pub fn unavailability_reason(
+    shift: &Shift,
+    zone: Tz,
+    weekly: &[WeeklyUnavailability],
+    exceptions: &[AvailabilityException],
+) -> Option<String> {
+    let start = shift.starts_at.with_timezone(&zone);
+    let (first_day, last_day) = local_days(shift, zone);
+
+    if let Some(away) = exceptions
+        .iter()
+        .find(|e| !e.available && e.starts_on <= last_day && e.ends_on >= first_day)
+    {
+        return Some(with_note(
+            format!("away {} to {}", away.starts_on, away.ends_on),
+            &away.note,
+        ));
+    }
+    if exceptions
+        .iter()
+        .any(|e| e.available && e.starts_on <= first_day && e.ends_on >= first_day)
+    {
+        return None;
+    }
+
+    let dow = start.weekday().num_days_from_monday() as i16;
+    weekly
+        .iter()
+        .find(|w| w.dow == dow && w.period == shift.period)
+        .map(|w| {
+            with_note(
+                format!("unavailable {} {}", start.weekday(), shift.period.as_str()),
+                &w.note,
+            )
+        })
+}
+
+/// The member's approved leave that `shift` touches any day of, as
+/// `approve_leave` does when it unassigns them.
+pub fn leave_reason(shift: &Shift, zone: Tz, leave: &[LeaveRequest]) -> Option<String> {
+    let (first_day, last_day) = local_days(shift, zone);
+    leave
+        .iter()
+        .find(|l| l.starts_on <= last_day && l.ends_on >= first_day)
+        .map(|l| format!("on leave {} to {}", l.starts_on, l.ends_on))
+}
+
+/// First and last local day the shift touches.
+fn local_days(shift: &Shift, zone: Tz) -> (NaiveDate, NaiveDate) {
+    let first_day = shift.starts_at.with_timezone(&zone).date_naive();
+    // A shift ending at midnight doesn't touch the next day.
+    let last_day = (shift.ends_at - Duration::nanoseconds(1))
+        .with_timezone(&zone)
+        .date_naive()
+        .max(first_day);
+    (first_day, last_day)
+}
+
+fn with_note(reason: String, note: &Option<String>) -> String {
+    match note {
+        Some(note) => format!("{reason} ({note})"),
+        None => reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Period;
+    use chrono::{DateTime, NaiveDate, Utc};
+    use uuid::Uuid;
+
+    fn shift(starts_at: &str, ends_at: &str, period: Period) -> Shift {
+        Shift {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            starts_at: starts_at.parse::<DateTime<Utc>>().unwrap(),
+            ends_at: ends_at.parse::<DateTime<Utc>>().unwrap(),
+            period,
+            capacity: 1,
+            assignees: Vec::new(),
+            template_id: None,
+            template_week: None,
+            created_by: Uuid::nil(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn exception(starts_on: &str, ends_on: &str, available: bool) -> AvailabilityException {
+        AvailabilityException {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            starts_on: starts_on.parse::<NaiveDate>().unwrap(),
+            ends_on: ends_on.parse::<NaiveDate>().unwrap(),
+            available,
+            note: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn weekly_pattern_uses_schedule_zone() {
+        let tuesday_nights = [WeeklyUnavailability {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            dow: 1,
+            period: Period::Night,
+            note: Some("choir".to_string()),
+            created_at: Utc::now(),
+        }];
+        // 19:00 on Tuesday in UTC is already Wednesday morning in Auckland.
+        let s = shift(
+            "2025-03-04T19:00:00Z",
+            "2025-03-04T22:00:00Z",
+            Period::Night,
+        );
+        assert_eq!(
+            unavailability_reason(&s, Tz::UTC, &tuesday_nights, &[]).as_deref(),
+            Some("unavailable Tue night (choir)")
+        );
+        let zone: Tz = "Pacific/Auckland".parse().unwrap();
+        assert!(unavailability_reason(&s, zone, &tuesday_nights, &[]).is_none());
+
+        // An "available" exception on that day overrides the pattern.
+        let available = [exception("2025-03-04", "2025-03-04", true)];
+        assert!(unavailability_reason(&s, Tz::UTC, &tuesday_nights, &available).is_none());
+    }
+
+    #[test]
+    fn away_range_catches_overnight_shift() {
+        let away = [exception("2025-03-03", "2025-03-10", false)];
+        let sleep = shift(
+            "2025-03-02T22:00:00Z",
+            "2025-03-03T08:00:00Z",
+            Period::Sleep,
+        );
+        assert_eq!(
+            unavailability_reason(&sleep, Tz::UTC, &[], &away).as_deref(),
+            Some("away 2025-03-03 to 2025-03-10")
+        );
+        let before = shift(
+            "2025-03-02T18:00:00Z",
+            "2025-03-03T00:00:00Z",
+            Period::Night,
+        );
+        assert!(unavailability_reason(&before, Tz::UTC, &[], &away).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+//! Copying schedule and template-slot checklists onto new shifts.
+
+use crate::{
+    error::{AppError, AppResult},
+    models::{Period, ScheduleTask},
+    repo::NewShiftTask,
+    tz,
+};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A task a template slot puts on each shift it generates.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SlotTask {
+    pub title: String,
+    /// HH:MM in the schedule's zone.
+    pub at: Option<String>,
+}
+
+/// Parses an HH:MM (or HH:MM:SS) local time of day.
+pub fn parse_time(value: &str, name: &str) -> AppResult<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .map_err(|_| AppError::BadRequest(format!("{name} must be HH:MM")))
+}
+
+pub fn check_title(title: &str) -> AppResult<()> {
+    if title.trim().is_empty() {
+        return Err(AppError::BadRequest("title is required".to_string()));
+    }
+    Ok(())
+}
+
+impl SlotTask {
+    pub fn validate(&self) -> AppResult<()> {
+        check_title(&self.title)?;
+        if let Some(at) = &self.at {
+            parse_time(at, "task.at")?;
+        }
+        Ok(())
+    }
+}
+
+/// Every instant in `[starts_at, ends_at)` whose local time in `zone` is `time`.
+fn occurrences(
+    time: NaiveTime,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    zone: Tz,
+) -> AppResult<Vec<DateTime<Utc>>> {
+    let first = starts_at.with_timezone(&zone).date_naive();
+    let last = ends_at.with_timezone(&zone).date_naive();
+    let mut out = Vec::new();
+    for day in first.iter_days().take_while(|d| *d <= last) {
+        let at = tz::localize(zone, day.and_time(time))?;
+        if at >= starts_at && at < ends_at {
+            out.push(at);
+        }
+    }
+    Ok(out)
+}
+
+/// Checklist for a new shift, ordered by due time with untimed tasks last.
+///
+/// Schedule tasks for the shift's period are added once for each time the
+/// shift covers their `due_time` (and skipped if it covers none), or once if
+/// they have no time. Slot tasks are always added, due at the first time the
+/// shift covers their `at`.
+pub fn instantiate(
+    schedule_tasks: &[ScheduleTask],
+    slot_tasks: &[SlotTask],
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    period: Period,
+    zone: Tz,
+) -> AppResult<Vec<NewShiftTask>> {
+    let mut out = Vec::new();
+    for task in schedule_tasks
+        .iter()
+        .filter(|t| t.period.is_none_or(|p| p == period))
+    {
+        let due = match task.due_time {
+            Some(time) => occurrences(time, starts_at, ends_at, zone)?
+                .into_iter()
+                .map(Some)
+                .collect(),
+            None => vec![None],
+        };
+        out.extend(due.into_iter().map(|due_at| NewShiftTask {
+            schedule_task_id: Some(task.id),
+            title: task.title.clone(),
+            due_at,
+        }));
+    }
+    for task in slot_tasks {
+        let due_at = match &task.at {
+            Some(at) => occurrences(parse_time(at, "task.at")?, starts_at, ends_at, zone)?
+                .into_iter()
+                .next(),
+            None => None,
+        };
+        out.push(NewShiftTask {
+            schedule_task_id: None,
+            title: task.title.trim().to_string(),
+            due_at,
+        });
+    }
+    // Stable, so tasks due together keep the order they were defined in.
+    out.sort_by_key(|t| (t.due_at.is_none(), t.due_at));
+    Ok(out)
+}
+
+/// `instantiate` for shifts created without a template slot.
+pub fn for_shift(
+    schedule_tasks: &[ScheduleTask],
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    period: Period,
+    zone: Tz,
+) -> AppResult<Vec<NewShiftTask>> {
+    instantiate(schedule_tasks, &[], starts_at, ends_at, period, zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use uuid::Uuid;
+
+    fn task(title: &str, due_time: Option<&str>, period: Option<Period>) -> ScheduleTask {
+        ScheduleTask {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::new_v4(),
+            title: title.to_string(),
+            due_time: due_time.map(|t| parse_time(t, "due_time").unwrap()),
+            period,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn timed_tasks_land_on_shifts_covering_their_time() {
+        let zone: Tz = "Europe/Lisbon".parse().unwrap();
+        let tasks = [
+            task("Feed", None, None),
+            task("Meds", Some("09:00"), None),
+            task("Walk", None, Some(Period::Afternoon)),
+        ];
+        // 08:00-12:00 local in summer time (UTC+1).
+        let starts_at = Utc.with_ymd_and_hms(2025, 7, 1, 7, 0, 0).unwrap();
+        let ends_at = Utc.with_ymd_and_hms(2025, 7, 1, 11, 0, 0).unwrap();
+        let out = for_shift(&tasks, starts_at, ends_at, Period::Morning, zone).unwrap();
+        let titles: Vec<_> = out.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Meds", "Feed"]);
+        assert_eq!(
+            out[0].due_at,
+            Some(Utc.with_ymd_and_hms(2025, 7, 1, 8, 0, 0).unwrap())
+        );
+
+        // The afternoon doesn't cover 09:00.
+        let out = for_shift(
+            &tasks,
+            starts_at + Duration::hours(4),
+            ends_at + Duration::hours(6),
+            Period::Afternoon,
+            zone,
+        )
+        .unwrap();
+        let titles: Vec<_> = out.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, ["Feed", "Walk"]);
+    }
+
+    #[test]
+    fn overnight_shift_gets_time_after_midnight() {
+        let zone: Tz = "UTC".parse().unwrap();
+        let starts_at = Utc.with_ymd_and_hms(2025, 3, 3, 22, 0, 0).unwrap();
+        let ends_at = starts_at + Duration::hours(10);
+        let slot = [SlotTask {
+            title: "Check oxygen".to_string(),
+            at: Some("02:30".to_string()),
+        }];
+        let out = instantiate(&[], &slot, starts_at, ends_at, Period::Sleep, zone).unwrap();
+        assert_eq!(
+            out[0].due_at,
+            Some(Utc.with_ymd_and_hms(2025, 3, 4, 2, 30, 0).unwrap())
+        );
+    }
+}
@@ -6,6 +6,13 @@ pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
     pub cors_origin: Option<String>,
+    /// Base URL links in emails point at.
+    pub public_url: String,
+    /// SMTP server to send mail through; without it mail is only logged.
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    /// File logged mail is also appended to.
+    pub mail_log_file: Option<String>,
 }
 
 impl Config {
@@ -20,12 +27,24 @@ impl Config {
         let jwt_secret =
             std::env::var("JWT_SECRET").map_err(|_| "Missing JWT_SECRET".to_string())?;
         let cors_origin = std::env::var("CORS_ORIGIN").ok();
+        let public_url = std::env::var("PUBLIC_URL")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string())
+            .trim_end_matches('/')
+            .to_string();
+        let smtp_url = std::env::var("SMTP_URL").ok();
+        let mail_from = std::env::var("MAIL_FROM")
+            .unwrap_or_else(|_| "Buddy Schedule <no-reply@localhost>".to_string());
+        let mail_log_file = std::env::var("MAIL_LOG_FILE").ok();
 
         Ok(Self {
             bind_addr,
             database_url,
             jwt_secret,
             cors_origin,
+            public_url,
+            smtp_url,
+            mail_from,
+            mail_log_file,
         })
     }
 }
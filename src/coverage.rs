@@ -0,0 +1,191 @@
+//! Comparing a schedule's coverage rules with its shifts.
+
+use crate::{
+    error::{AppError, AppResult},
+    models::{CoverageRule, Period, Shift},
+    tz,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Longest range gaps can be checked over in one request.
+pub const MAX_RANGE_DAYS: i64 = 366;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Gap {
+    pub date: NaiveDate,
+    pub dow: i16,
+    pub period: Period,
+    /// The period's default window on `date`, for display.
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub required: i16,
+    pub assigned: i64,
+    pub missing: i64,
+    /// Shifts in the window that still have a free place.
+    pub unassigned_shift_ids: Vec<Uuid>,
+}
+
+/// Rejects a `[from, to]` range that is backwards or longer than
+/// `MAX_RANGE_DAYS`.
+pub fn check_range(from: NaiveDate, to: NaiveDate) -> AppResult<()> {
+    if to < from {
+        return Err(AppError::BadRequest(
+            "to must not be before from".to_string(),
+        ));
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(AppError::BadRequest(format!(
+            "range must be at most {MAX_RANGE_DAYS} days"
+        )));
+    }
+    Ok(())
+}
+
+/// Every day and period in `[from, to]` (local dates in `zone`) where fewer
+/// members are assigned than a rule requires. A shift counts towards the
+/// day its local start falls on and its own period.
+pub fn find_gaps(
+    rules: &[CoverageRule],
+    shifts: &[Shift],
+    zone: Tz,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> AppResult<Vec<Gap>> {
+    check_range(from, to)?;
+
+    let mut gaps = Vec::new();
+    for date in from.iter_days().take_while(|d| *d <= to) {
+        let dow = date.weekday().num_days_from_monday() as i16;
+        for rule in rules.iter().filter(|r| r.dow == dow) {
+            let matching: Vec<&Shift> = shifts
+                .iter()
+                .filter(|s| {
+                    s.period == rule.period && s.starts_at.with_timezone(&zone).date_naive() == date
+                })
+                .collect();
+            let assigned = matching.iter().map(|s| s.assignees.len()).sum::<usize>() as i64;
+            if assigned >= i64::from(rule.required) {
+                continue;
+            }
+
+            let (start_h, end_h) = rule.period.default_hours();
+            let end_date = if end_h <= start_h {
+                date + Duration::days(1)
+            } else {
+                date
+            };
+            gaps.push(Gap {
+                date,
+                dow,
+                period: rule.period,
+                starts_at: tz::localize(
+                    zone,
+                    date.and_hms_opt(start_h, 0, 0).expect("valid hour"),
+                )?,
+                ends_at: tz::localize(
+                    zone,
+                    end_date.and_hms_opt(end_h, 0, 0).expect("valid hour"),
+                )?,
+                required: rule.required,
+                assigned,
+                missing: i64::from(rule.required) - assigned,
+                unassigned_shift_ids: matching
+                    .iter()
+                    .filter(|s| s.free_places() > 0)
+                    .map(|s| s.id)
+                    .collect(),
+            });
+        }
+    }
+    gaps.sort_by_key(|g| g.starts_at);
+    Ok(gaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ShiftAssignee;
+
+    fn rule(dow: i16, period: Period, required: i16) -> CoverageRule {
+        CoverageRule {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            dow,
+            period,
+            required,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn shift(starts_at: &str, period: Period, assigned: bool) -> Shift {
+        let starts_at = starts_at.parse::<DateTime<Utc>>().unwrap();
+        Shift {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            starts_at,
+            ends_at: starts_at + Duration::hours(8),
+            period,
+            capacity: 1,
+            assignees: assigned
+                .then(|| ShiftAssignee {
+                    user_id: Uuid::new_v4(),
+                    lead: false,
+                    assigned_at: Utc::now(),
+                })
+                .into_iter()
+                .collect(),
+            template_id: None,
+            template_week: None,
+            created_by: Uuid::nil(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn reports_missing_cover_per_day() {
+        let zone = tz::parse_tz("Europe/London").unwrap();
+        let rules: Vec<_> = (0..7)
+            .map(|dow| rule(dow, Period::Sleep, 1))
+            .chain([rule(5, Period::Morning, 2)])
+            .collect();
+        // Mon 2025-03-03 .. Sat 2025-03-08: sleep covered except Tuesday (open
+        // shift) and Wednesday (no shift); one of two Saturday mornings.
+        let open = shift("2025-03-04T22:00:00Z", Period::Sleep, false);
+        let mut shifts = vec![open.clone()];
+        for day in ["03", "06", "07", "08"] {
+            shifts.push(shift(
+                &format!("2025-03-{day}T22:00:00Z"),
+                Period::Sleep,
+                true,
+            ));
+        }
+        shifts.push(shift("2025-03-08T08:00:00Z", Period::Morning, true));
+
+        let gaps = find_gaps(
+            &rules,
+            &shifts,
+            zone,
+            NaiveDate::from_ymd_opt(2025, 3, 3).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 3, 8).unwrap(),
+        )
+        .unwrap();
+        let got: Vec<_> = gaps
+            .iter()
+            .map(|g| (g.date.day(), g.period, g.missing))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (4, Period::Sleep, 1),
+                (5, Period::Sleep, 1),
+                (8, Period::Morning, 1)
+            ]
+        );
+        assert_eq!(gaps[0].unassigned_shift_ids, [open.id]);
+        assert_eq!(gaps[0].ends_at.to_rfc3339(), "2025-03-05T08:00:00+00:00");
+    }
+}
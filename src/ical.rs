@@ -0,0 +1,674 @@
+//! Minimal iCalendar (RFC 5545) support: rendering shift feeds and parsing
+//! uploaded calendars into events that can become shifts.
+
+use crate::{
+    error::{AppError, AppResult},
+    models::{Shift, ShiftComment},
+    tz,
+};
+use chrono::{
+    DateTime, Datelike, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday,
+};
+use chrono_tz::Tz;
+
+const PRODID: &str = "-//Buddy Schedule//Shift Feed//EN";
+
+pub struct FeedEntry {
+    pub shift: Shift,
+    pub schedule_name: String,
+    pub comments: Vec<ShiftComment>,
+}
+
+/// Renders a feed generated at `now`. Each event's SEQUENCE is the seconds
+/// between the shift's creation and its last change, so it only grows.
+pub fn render_calendar(name: &str, entries: &[FeedEntry], now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{PRODID}"));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, "METHOD:PUBLISH");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape_text(name)));
+
+    for e in entries {
+        let period = e.shift.period.as_str();
+        let mut description = format!("Period: {period}\nSchedule: {}", e.schedule_name);
+        for c in &e.comments {
+            description.push_str(&format!("\n\n{}", c.body));
+        }
+
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(
+            &mut out,
+            &format!("UID:shift-{}@buddy-schedule", e.shift.id),
+        );
+        push_line(&mut out, &format!("DTSTAMP:{}", format_utc(now)));
+        push_line(
+            &mut out,
+            &format!("LAST-MODIFIED:{}", format_utc(e.shift.updated_at)),
+        );
+        let sequence = (e.shift.updated_at - e.shift.created_at)
+            .num_seconds()
+            .max(0);
+        push_line(&mut out, &format!("SEQUENCE:{sequence}"));
+        push_line(
+            &mut out,
+            &format!("DTSTART:{}", format_utc(e.shift.starts_at)),
+        );
+        push_line(&mut out, &format!("DTEND:{}", format_utc(e.shift.ends_at)));
+        push_line(
+            &mut out,
+            &format!(
+                "SUMMARY:{}",
+                escape_text(&format!("{} ({period})", e.schedule_name))
+            ),
+        );
+        push_line(
+            &mut out,
+            &format!("DESCRIPTION:{}", escape_text(&description)),
+        );
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+pub fn format_utc(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Appends a content line, folded at 75 octets as required by RFC 5545.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if width + len > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(ch);
+        width += len;
+    }
+    out.push_str("\r\n");
+}
+
+/// Upper bound on occurrences produced from one recurring event.
+const MAX_OCCURRENCES: usize = 5000;
+
+/// Longest event imported as a shift, in days.
+const MAX_EVENT_DAYS: i64 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Clone, Debug)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    pub by_day: Vec<Weekday>,
+}
+
+/// A timed VEVENT. Times are kept as wall-clock values in `zone` so that
+/// recurrences keep their local time across DST changes.
+#[derive(Clone, Debug)]
+pub struct ImportedEvent {
+    pub uid: Option<String>,
+    pub summary: String,
+    pub zone: Tz,
+    pub start: NaiveDateTime,
+    pub duration: Duration,
+    pub rrule: Option<RecurrenceRule>,
+    pub exdates: Vec<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Occurrence {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct ParsedCalendar {
+    pub events: Vec<ImportedEvent>,
+    /// Human-readable reasons for VEVENTs that were not imported.
+    pub skipped: Vec<String>,
+}
+
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn unfold(input: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in input.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (
+            raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')),
+            lines.last_mut(),
+        ) {
+            (Some(cont), Some(last)) => last.push_str(cont),
+            _ if raw.is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    let (head, value) = line.split_once(':')?;
+    let mut parts = head.split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"').to_string()))
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+fn unescape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
+
+enum DateValue {
+    /// Wall-clock time in the given zone (UTC for `Z` values).
+    DateTime(NaiveDateTime, Tz),
+    /// All-day value.
+    Date(NaiveDate),
+}
+
+fn parse_date_value(prop: &Property, default_zone: Tz) -> AppResult<DateValue> {
+    let bad = || AppError::BadRequest(format!("invalid {} value: {}", prop.name, prop.value));
+    let value = prop.value.trim();
+    if prop.param("VALUE") == Some("DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(DateValue::Date)
+            .map_err(|_| bad());
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| bad())?;
+        return Ok(DateValue::DateTime(naive, Tz::UTC));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| bad())?;
+    let zone = match prop.param("TZID") {
+        Some(name) => tz::parse_tz(name)?,
+        None => default_zone,
+    };
+    Ok(DateValue::DateTime(naive, zone))
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (negative, rest) = match value.strip_prefix('-') {
+        Some(r) => (true, r),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix('P')?;
+    let mut total = Duration::zero();
+    let mut num = String::new();
+    let mut in_time = false;
+    for ch in rest.chars() {
+        match ch {
+            'T' => in_time = true,
+            '0'..='9' => num.push(ch),
+            unit => {
+                let n: i64 = num.parse().ok()?;
+                num.clear();
+                let part = match (unit, in_time) {
+                    ('W', false) => Duration::try_weeks(n),
+                    ('D', false) => Duration::try_days(n),
+                    ('H', true) => Duration::try_hours(n),
+                    ('M', true) => Duration::try_minutes(n),
+                    ('S', true) => Duration::try_seconds(n),
+                    _ => return None,
+                }?;
+                total = total.checked_add(&part)?;
+            }
+        }
+    }
+    if !num.is_empty() {
+        return None;
+    }
+    Some(if negative { -total } else { total })
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_rrule(value: &str, zone: Tz) -> AppResult<RecurrenceRule> {
+    let unsupported =
+        |what: &str| AppError::BadRequest(format!("unsupported RRULE {what}: {value}"));
+    let mut freq = None;
+    let mut rule = RecurrenceRule {
+        freq: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+    };
+    for part in value.split(';') {
+        let (k, v) = part.split_once('=').ok_or_else(|| unsupported("part"))?;
+        match k.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                freq = Some(match v {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    _ => return Err(unsupported("FREQ")),
+                })
+            }
+            "INTERVAL" => {
+                rule.interval = v
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| unsupported("INTERVAL"))?
+            }
+            "COUNT" => rule.count = Some(v.parse().map_err(|_| unsupported("COUNT"))?),
+            "UNTIL" => {
+                let prop = Property {
+                    name: "UNTIL".to_string(),
+                    params: Vec::new(),
+                    value: v.to_string(),
+                };
+                rule.until = Some(match parse_date_value(&prop, zone)? {
+                    DateValue::DateTime(naive, z) => tz::localize(z, naive)?,
+                    // Date-only UNTIL includes that whole day.
+                    DateValue::Date(d) => {
+                        tz::start_of_day(zone, d.succ_opt().ok_or_else(|| unsupported("UNTIL"))?)?
+                    }
+                });
+            }
+            "BYDAY" => {
+                rule.by_day = v
+                    .split(',')
+                    .map(|d| parse_weekday(d).ok_or_else(|| unsupported("BYDAY")))
+                    .collect::<AppResult<_>>()?
+            }
+            "WKST" => {}
+            other => return Err(unsupported(other)),
+        }
+    }
+    rule.freq = freq.ok_or_else(|| unsupported("without FREQ"))?;
+    if !rule.by_day.is_empty() && rule.freq != Frequency::Weekly {
+        return Err(unsupported("BYDAY outside WEEKLY"));
+    }
+    Ok(rule)
+}
+
+/// Parses the VEVENTs of `input`. Floating times are read in `default_zone`.
+/// All-day, cancelled and over-long events are skipped and reported in `skipped`.
+pub fn parse_calendar(input: &str, default_zone: Tz) -> AppResult<ParsedCalendar> {
+    let mut out = ParsedCalendar::default();
+    let mut current: Option<Vec<Property>> = None;
+    let mut saw_calendar = false;
+
+    for line in unfold(input) {
+        let Some(prop) = parse_property(&line) else {
+            continue;
+        };
+        match (prop.name.as_str(), prop.value.trim()) {
+            ("BEGIN", "VCALENDAR") => saw_calendar = true,
+            ("BEGIN", "VEVENT") => current = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                if let Some(props) = current.take() {
+                    match build_event(props, default_zone)? {
+                        Ok(event) => out.events.push(event),
+                        Err(reason) => out.skipped.push(reason),
+                    }
+                }
+            }
+            _ => {
+                if let Some(props) = current.as_mut() {
+                    props.push(prop);
+                }
+            }
+        }
+    }
+    if !saw_calendar {
+        return Err(AppError::BadRequest("not an iCalendar file".to_string()));
+    }
+    Ok(out)
+}
+
+/// Outer error: malformed input. Inner error: event is valid but skipped.
+fn build_event(props: Vec<Property>, zone: Tz) -> AppResult<Result<ImportedEvent, String>> {
+    let find = |name: &str| props.iter().find(|p| p.name == name);
+    let uid = find("UID").map(|p| p.value.clone());
+    let summary = find("SUMMARY")
+        .map(|p| unescape_text(&p.value))
+        .unwrap_or_default();
+    let label = uid.clone().unwrap_or_else(|| summary.clone());
+
+    if find("STATUS").is_some_and(|p| p.value.eq_ignore_ascii_case("CANCELLED")) {
+        return Ok(Err(format!("{label}: cancelled")));
+    }
+    let Some(dtstart) = find("DTSTART") else {
+        return Ok(Err(format!("{label}: missing DTSTART")));
+    };
+    let (start, event_zone) = match parse_date_value(dtstart, zone)? {
+        DateValue::DateTime(naive, z) => (naive, z),
+        DateValue::Date(_) => return Ok(Err(format!("{label}: all-day events are not shifts"))),
+    };
+
+    let duration = if let Some(dtend) = find("DTEND") {
+        let end = match parse_date_value(dtend, zone)? {
+            DateValue::DateTime(naive, z) => tz::localize(z, naive)?,
+            DateValue::Date(_) => {
+                return Ok(Err(format!("{label}: all-day events are not shifts")))
+            }
+        };
+        end - tz::localize(event_zone, start)?
+    } else if let Some(d) = find("DURATION") {
+        parse_duration(d.value.trim())
+            .ok_or_else(|| AppError::BadRequest(format!("invalid DURATION: {}", d.value)))?
+    } else {
+        return Ok(Err(format!("{label}: missing DTEND/DURATION")));
+    };
+    if duration <= Duration::zero() {
+        return Ok(Err(format!("{label}: ends before it starts")));
+    }
+    if duration > Duration::days(MAX_EVENT_DAYS) {
+        return Ok(Err(format!(
+            "{label}: lasts longer than {MAX_EVENT_DAYS} days"
+        )));
+    }
+
+    let rrule = find("RRULE")
+        .map(|p| parse_rrule(p.value.trim(), event_zone))
+        .transpose()?;
+    let mut exdates = Vec::new();
+    for p in props.iter().filter(|p| p.name == "EXDATE") {
+        for v in p.value.split(',') {
+            let single = Property {
+                name: p.name.clone(),
+                params: p.params.clone(),
+                value: v.to_string(),
+            };
+            if let DateValue::DateTime(naive, z) = parse_date_value(&single, event_zone)? {
+                exdates.push(tz::localize(z, naive)?);
+            }
+        }
+    }
+
+    Ok(Ok(ImportedEvent {
+        uid,
+        summary,
+        zone: event_zone,
+        start,
+        duration,
+        rrule,
+        exdates,
+    }))
+}
+
+impl ImportedEvent {
+    /// Occurrences whose start falls in `[from, to)`. Recurring events need a
+    /// window; single events ignore a missing one.
+    pub fn occurrences(
+        &self,
+        window: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> AppResult<Vec<Occurrence>> {
+        let in_window = |t: DateTime<Utc>| window.is_none_or(|(from, to)| t >= from && t < to);
+        let occurrence = |start: NaiveDateTime| -> AppResult<Occurrence> {
+            let starts_at = tz::localize(self.zone, start)?;
+            let ends_at = starts_at
+                .checked_add_signed(self.duration)
+                .ok_or_else(|| AppError::BadRequest("event ends out of range".to_string()))?;
+            Ok(Occurrence { starts_at, ends_at })
+        };
+
+        let Some(rule) = &self.rrule else {
+            let o = occurrence(self.start)?;
+            return Ok(if in_window(o.starts_at) {
+                vec![o]
+            } else {
+                vec![]
+            });
+        };
+        let Some((_, to)) = window else {
+            return Err(AppError::BadRequest(format!(
+                "recurring event {} needs an import window (from/to)",
+                self.uid.as_deref().unwrap_or(&self.summary)
+            )));
+        };
+
+        let mut out = Vec::new();
+        // COUNT includes excluded and out-of-window occurrences (RFC 5545).
+        let count = rule.count.map_or(usize::MAX, |c| c as usize);
+        for start in self.candidate_starts(rule).take(count) {
+            let o = occurrence(start)?;
+            if o.starts_at >= to || rule.until.is_some_and(|u| o.starts_at > u) {
+                break;
+            }
+            if !self.exdates.contains(&o.starts_at) && in_window(o.starts_at) {
+                out.push(o);
+            }
+            if out.len() > MAX_OCCURRENCES {
+                return Err(AppError::BadRequest(format!(
+                    "recurring event expands to more than {MAX_OCCURRENCES} shifts"
+                )));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Unbounded, ordered local start times generated by `rule`.
+    fn candidate_starts(
+        &self,
+        rule: &RecurrenceRule,
+    ) -> Box<dyn Iterator<Item = NaiveDateTime> + '_> {
+        let date = self.start.date();
+        let time: NaiveTime = self.start.time();
+        let interval = rule.interval;
+        match rule.freq {
+            Frequency::Daily => Box::new(
+                (0u64..)
+                    .map_while(move |k| {
+                        date.checked_add_days(chrono::Days::new(k * interval as u64))
+                    })
+                    .map(move |d| d.and_time(time)),
+            ),
+            Frequency::Monthly => Box::new(
+                (0u32..)
+                    .map_while(move |k| {
+                        let first = date.with_day(1)?;
+                        first.checked_add_months(Months::new(k * interval))
+                    })
+                    // Months without this day (e.g. the 31st) have no occurrence.
+                    .filter_map(move |m| m.with_day(date.day()))
+                    .map(move |d| d.and_time(time)),
+            ),
+            Frequency::Weekly => {
+                let mut days: Vec<u32> = if rule.by_day.is_empty() {
+                    vec![date.weekday().num_days_from_monday()]
+                } else {
+                    rule.by_day
+                        .iter()
+                        .map(|d| d.num_days_from_monday())
+                        .collect()
+                };
+                days.sort_unstable();
+                days.dedup();
+                let week_start =
+                    date - Duration::days(date.weekday().num_days_from_monday().into());
+                Box::new(
+                    (0u64..)
+                        .map_while(move |k| {
+                            week_start.checked_add_days(chrono::Days::new(k * 7 * interval as u64))
+                        })
+                        .flat_map(move |w| {
+                            days.clone().into_iter().filter_map(move |d| {
+                                w.checked_add_days(chrono::Days::new(d.into()))
+                            })
+                        })
+                        .filter(move |d| *d >= date)
+                        .map(move |d| d.and_time(time)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Period;
+    use uuid::Uuid;
+
+    #[test]
+    fn renders_escaped_and_folded_events() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let shift = Shift {
+            id: Uuid::nil(),
+            schedule_id: Uuid::nil(),
+            starts_at: at("2025-01-06T08:00:00Z"),
+            ends_at: at("2025-01-06T12:00:00Z"),
+            period: Period::Morning,
+            capacity: 1,
+            assignees: Vec::new(),
+            template_id: None,
+            template_week: None,
+            created_by: Uuid::nil(),
+            created_at: at("2025-01-01T00:00:00Z"),
+            updated_at: at("2025-01-03T00:00:00Z"),
+        };
+        let comment = ShiftComment {
+            id: Uuid::nil(),
+            shift_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            body: "Meds; then walk, ".repeat(8),
+            created_at: at("2025-01-01T00:00:00Z"),
+            edited_at: None,
+        };
+        let ics = render_calendar(
+            "Care",
+            &[FeedEntry {
+                shift,
+                schedule_name: "Care".to_string(),
+                comments: vec![comment],
+            }],
+            at("2025-02-01T00:00:00Z"),
+        );
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains(&format!("UID:shift-{}@buddy-schedule\r\n", Uuid::nil())));
+        assert!(ics.contains("DTSTART:20250106T080000Z\r\n"));
+        assert!(ics.contains("DTSTAMP:20250201T000000Z\r\n"));
+        assert!(ics.contains("LAST-MODIFIED:20250103T000000Z\r\n"));
+        assert!(ics.contains("SEQUENCE:172800\r\n"));
+        assert!(ics.contains("Meds\\; then walk\\, "));
+        assert!(ics.lines().all(|l| l.trim_end_matches('\r').len() <= 75));
+    }
+
+    #[test]
+    fn parses_and_expands_weekly_events_in_local_time() {
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:walk-1\r\n\
+                   SUMMARY:Evening\r\n  walk\r\nDTSTART;TZID=Europe/Berlin:20250324T190000\r\n\
+                   DURATION:PT1H30M\r\nRRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4\r\n\
+                   EXDATE;TZID=Europe/Berlin:20250326T190000\r\nEND:VEVENT\r\n\
+                   BEGIN:VEVENT\r\nUID:holiday\r\nDTSTART;VALUE=DATE:20250401\r\nEND:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let parsed = parse_calendar(ics, Tz::UTC).unwrap();
+        assert_eq!(parsed.events.len(), 1);
+        assert_eq!(parsed.skipped.len(), 1);
+        let event = &parsed.events[0];
+        assert_eq!(event.summary, "Evening walk");
+
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let window = Some((at("2025-03-01T00:00:00Z"), at("2025-05-01T00:00:00Z")));
+        let starts: Vec<_> = event
+            .occurrences(window)
+            .unwrap()
+            .iter()
+            .map(|o| o.starts_at.to_rfc3339())
+            .collect();
+        // Mon 24th (CET), Wed 26th excluded, then CEST from the 30th on.
+        assert_eq!(
+            starts,
+            [
+                "2025-03-24T18:00:00+00:00",
+                "2025-03-31T17:00:00+00:00",
+                "2025-04-02T17:00:00+00:00",
+            ]
+        );
+        assert!(event.occurrences(None).is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_and_overlong_durations() {
+        assert_eq!(parse_duration("P1W2DT3H"), Some(Duration::hours(219)));
+        assert_eq!(parse_duration("P99999999999999W"), None);
+        assert_eq!(parse_duration("PT9223372036854775807S"), None);
+
+        let ics = |duration: &str| {
+            format!(
+                "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:long\r\n\
+                 DTSTART:20250324T190000Z\r\nDURATION:{duration}\r\nEND:VEVENT\r\n\
+                 END:VCALENDAR\r\n"
+            )
+        };
+        assert!(parse_calendar(&ics("P99999999999999W"), Tz::UTC).is_err());
+        let parsed = parse_calendar(&ics("P8D"), Tz::UTC).unwrap();
+        assert!(parsed.events.is_empty());
+        assert_eq!(parsed.skipped, ["long: lasts longer than 7 days"]);
+    }
+}
@@ -1,25 +1,52 @@
+pub mod attendance;
 pub mod auth;
+pub mod availability;
+pub mod checklist;
 pub mod config;
+pub mod coverage;
 pub mod error;
+pub mod ical;
+pub mod mailer;
 pub mod models;
 pub mod repo;
+pub mod rota;
+pub mod stats;
+pub mod template;
+pub mod tz;
 
 use crate::{
-    auth::{decode_jwt, hash_password, issue_jwt, verify_password, JwtKeys},
+    auth::{
+        decode_jwt, generate_token, hash_password, hash_token, issue_jwt, verify_password, JwtKeys,
+        ACCESS_TOKEN_MINUTES, EMAIL_VERIFICATION_HOURS, PASSWORD_RESET_MINUTES, REFRESH_TOKEN_DAYS,
+    },
     error::{AppError, AppResult},
-    models::{Period, ScheduleRole, User},
-    repo::{NewSchedule, NewShift, NewShiftComment, NewTemplate, NewUser, Repo},
+    ical::FeedEntry,
+    mailer::{Email, Mailer},
+    models::{
+        AttendanceCorrection, AvailabilityException, ClaimPolicy, ClaimStatus, CoverageRule,
+        LeaveRequest, LeaveStatus, Period, ScheduleInvite, ScheduleRole, ScheduleTask,
+        ScheduleWithRole, Shift, ShiftClaim, ShiftSwap, ShiftTask, SwapStatus, TokenPurpose, User,
+        WeeklyUnavailability,
+    },
+    repo::{
+        IdempotentRequest, NewAttendanceCorrection, NewAvailabilityException, NewClaim,
+        NewCoverageRule, NewInvite, NewLeaveRequest, NewSchedule, NewScheduleTask, NewShift,
+        NewShiftComment, NewShiftTask, NewSwap, NewTemplate, NewUser, NewWeeklyUnavailability,
+        Repo, UnitOfWork, UpdateAvailabilityException, UpdateCoverageRule, UpdateSchedule,
+        UpdateScheduleTask, UpdateShift, UpdateWeeklyUnavailability,
+    },
+    template::{default_anchor, RotationState, TemplateDef},
 };
 use axum::{
     extract::{Path, Query, State},
-    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, patch, post},
     Json, Router,
 };
-use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 use uuid::Uuid;
 
@@ -28,12 +55,17 @@ pub struct AppState {
     pub repo: Arc<dyn Repo>,
     pub jwt: JwtKeys,
     pub cors_origin: Option<String>,
+    pub mailer: Arc<dyn Mailer>,
+    /// Base URL for links in emails, without a trailing slash.
+    pub public_url: String,
 }
 
 #[derive(Clone, Debug)]
 pub struct AuthUser {
     pub id: Uuid,
     pub is_superadmin: bool,
+    /// `None` when authenticated by a calendar feed token.
+    pub session_id: Option<Uuid>,
 }
 
 impl AuthUser {
@@ -47,6 +79,14 @@ impl AuthUser {
             .ok_or(AppError::Unauthorized)?;
         let claims = decode_jwt(token, &state.jwt)?;
         let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)?;
+        let session_id = Uuid::parse_str(&claims.sid).map_err(|_| AppError::Unauthorized)?;
+        // Reject tokens from sessions that were logged out
+        state
+            .repo
+            .get_session(session_id)
+            .await?
+            .filter(|s| s.user_id == user_id && s.revoked_at.is_none())
+            .ok_or(AppError::Unauthorized)?;
         // Ensure user still exists
         let user = state
             .repo
@@ -56,14 +96,19 @@ impl AuthUser {
         Ok(Self {
             id: user.id,
             is_superadmin: user.is_superadmin,
+            session_id: Some(session_id),
         })
     }
 }
 
 pub fn build_router(state: AppState) -> Router {
     let mut cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+        ]);
 
     cors = match &state.cors_origin {
         None => cors.allow_origin(tower_http::cors::Any),
@@ -81,22 +126,157 @@ pub fn build_router(state: AppState) -> Router {
             Router::new()
                 .route("/auth/register", post(register))
                 .route("/auth/login", post(login))
+                .route("/auth/refresh", post(refresh))
+                .route("/auth/logout", post(logout))
+                .route("/auth/logout-all", post(logout_all))
+                .route("/auth/verify-email", post(verify_email))
+                .route("/auth/verify-email/resend", post(resend_verification))
+                .route("/auth/password-reset", post(request_password_reset))
+                .route("/auth/password-reset/confirm", post(confirm_password_reset))
                 .route("/me", get(me))
+                .route(
+                    "/me/calendar-token",
+                    post(rotate_calendar_token).delete(revoke_calendar_token),
+                )
+                .route("/calendar/me.ics", get(my_calendar_feed))
+                .route(
+                    "/calendar/schedules/:schedule_id/shifts.ics",
+                    get(schedule_calendar_feed),
+                )
                 .route("/schedules", get(list_schedules).post(create_schedule))
+                .route(
+                    "/schedules/:schedule_id",
+                    patch(update_schedule).delete(delete_schedule),
+                )
+                .route(
+                    "/schedules/:schedule_id/archive",
+                    post(archive_schedule).delete(unarchive_schedule),
+                )
                 .route(
                     "/schedules/:schedule_id/members",
                     get(list_members).post(add_member),
                 )
+                .route(
+                    "/schedules/:schedule_id/members/:user_id",
+                    delete(remove_member),
+                )
                 .route(
                     "/schedules/:schedule_id/members/:user_id/role",
                     post(set_member_role),
                 )
+                .route("/schedules/:schedule_id/membership", delete(leave_schedule))
+                .route(
+                    "/schedules/:schedule_id/invites",
+                    get(list_invites).post(create_invite),
+                )
+                .route(
+                    "/schedules/:schedule_id/invites/:invite_id",
+                    delete(revoke_invite),
+                )
+                .route("/invites/:token", get(get_invite))
+                .route("/invites/:token/accept", post(accept_invite))
                 .route(
                     "/schedules/:schedule_id/shifts",
                     get(list_shifts).post(create_shift),
                 )
+                .route(
+                    "/shifts/:shift_id",
+                    patch(update_shift).delete(delete_shift),
+                )
+                .route("/schedules/:schedule_id/stats", get(get_schedule_stats))
+                .route(
+                    "/schedules/:schedule_id/stats.csv",
+                    get(get_schedule_stats_csv),
+                )
                 .route("/shifts/:shift_id/assign", post(assign_shift))
-                .route("/shifts/:shift_id/comments", post(add_shift_comment))
+                .route("/shifts/:shift_id/assignees", post(add_shift_assignee))
+                .route(
+                    "/shifts/:shift_id/assignees/:user_id",
+                    delete(remove_shift_assignee),
+                )
+                .route("/shifts/:shift_id/check-in", post(check_in_shift))
+                .route("/shifts/:shift_id/check-out", post(check_out_shift))
+                .route("/shifts/:shift_id/attendance", get(get_shift_attendance))
+                .route(
+                    "/shifts/:shift_id/attendance/:user_id",
+                    patch(correct_attendance),
+                )
+                .route(
+                    "/schedules/:schedule_id/attendance",
+                    get(get_attendance_report),
+                )
+                .route("/shifts/:shift_id/claim", post(claim_shift))
+                .route("/open-shifts", get(list_open_shifts))
+                .route("/schedules/:schedule_id/claims", get(list_claims))
+                .route(
+                    "/schedules/:schedule_id/availability",
+                    get(get_availability),
+                )
+                .route(
+                    "/schedules/:schedule_id/availability/weekly",
+                    post(create_weekly_unavailability),
+                )
+                .route(
+                    "/schedules/:schedule_id/availability/weekly/:rule_id",
+                    patch(update_weekly_unavailability).delete(delete_weekly_unavailability),
+                )
+                .route(
+                    "/schedules/:schedule_id/availability/exceptions",
+                    post(create_availability_exception),
+                )
+                .route(
+                    "/schedules/:schedule_id/availability/exceptions/:exception_id",
+                    patch(update_availability_exception).delete(delete_availability_exception),
+                )
+                .route("/claims/:claim_id/approve", post(approve_claim))
+                .route("/claims/:claim_id/reject", post(reject_claim))
+                .route(
+                    "/schedules/:schedule_id/leave",
+                    get(list_leave_requests).post(create_leave_request),
+                )
+                .route("/leave/:leave_id/approve", post(approve_leave))
+                .route("/leave/:leave_id/reject", post(reject_leave))
+                .route("/leave/:leave_id/cancel", post(cancel_leave))
+                .route("/schedules/:schedule_id/rota/preview", post(preview_rota))
+                .route("/schedules/:schedule_id/rota/commit", post(commit_rota))
+                .route(
+                    "/schedules/:schedule_id/coverage",
+                    get(list_coverage_rules).post(create_coverage_rule),
+                )
+                .route(
+                    "/schedules/:schedule_id/coverage/:rule_id",
+                    patch(update_coverage_rule).delete(delete_coverage_rule),
+                )
+                .route("/schedules/:schedule_id/gaps", get(list_gaps))
+                .route(
+                    "/schedules/:schedule_id/tasks",
+                    get(list_schedule_tasks).post(create_schedule_task),
+                )
+                .route(
+                    "/schedules/:schedule_id/tasks/incomplete",
+                    get(list_incomplete_tasks),
+                )
+                .route(
+                    "/schedules/:schedule_id/tasks/:task_id",
+                    patch(update_schedule_task).delete(delete_schedule_task),
+                )
+                .route(
+                    "/shifts/:shift_id/tasks",
+                    get(list_shift_tasks).post(add_shift_task),
+                )
+                .route(
+                    "/shifts/:shift_id/tasks/:task_id",
+                    patch(update_shift_task).delete(delete_shift_task),
+                )
+                .route(
+                    "/shifts/:shift_id/comments",
+                    get(list_shift_comments).post(add_shift_comment),
+                )
+                .route(
+                    "/shifts/:shift_id/comments/:comment_id",
+                    patch(update_shift_comment).delete(delete_shift_comment),
+                )
+                .route("/schedules/:schedule_id/import/ics", post(import_ics))
                 .route(
                     "/schedules/:schedule_id/templates",
                     get(list_templates).post(create_template),
@@ -104,7 +284,15 @@ pub fn build_router(state: AppState) -> Router {
                 .route(
                     "/schedules/:schedule_id/templates/:template_id/apply",
                     post(apply_template),
-                ),
+                )
+                .route(
+                    "/schedules/:schedule_id/swaps",
+                    get(list_swaps).post(create_swap),
+                )
+                .route("/swaps/:swap_id/accept", post(accept_swap))
+                .route("/swaps/:swap_id/approve", post(approve_swap))
+                .route("/swaps/:swap_id/decline", post(decline_swap))
+                .route("/swaps/:swap_id/cancel", post(cancel_swap)),
         )
         .fallback_service(ServeDir::new("web"))
         .with_state(state)
@@ -124,7 +312,38 @@ struct AuthRequest {
 
 #[derive(Debug, Serialize)]
 struct AuthResponse {
+    /// Access token for the `Authorization` header.
     token: String,
+    /// Seconds until `token` expires.
+    expires_in: i64,
+    /// Single-use; trade it at `/auth/refresh` for a new pair.
+    refresh_token: String,
+}
+
+fn auth_response(
+    state: &AppState,
+    user: &User,
+    session_id: Uuid,
+    refresh_token: String,
+) -> AppResult<AuthResponse> {
+    Ok(AuthResponse {
+        token: issue_jwt(user.id, user.is_superadmin, session_id, &state.jwt)?,
+        expires_in: ACCESS_TOKEN_MINUTES * 60,
+        refresh_token,
+    })
+}
+
+async fn start_session(state: &AppState, user: &User) -> AppResult<AuthResponse> {
+    let refresh_token = generate_token();
+    let session = state
+        .repo
+        .create_session(
+            user.id,
+            hash_token(&refresh_token),
+            Utc::now() + chrono::Duration::days(REFRESH_TOKEN_DAYS),
+        )
+        .await?;
+    auth_response(state, user, session.id, refresh_token)
 }
 
 async fn register(
@@ -149,8 +368,12 @@ async fn register(
         })
         .await?;
 
-    let token = issue_jwt(user.id, user.is_superadmin, &state.jwt)?;
-    Ok(Json(AuthResponse { token }))
+    // A mail outage shouldn't stop anyone registering; they can ask for
+    // another link later.
+    if let Err(e) = send_verification_email(&state, &user).await {
+        tracing::warn!(user_id = %user.id, "verification email failed: {e:?}");
+    }
+    Ok(Json(start_session(&state, &user).await?))
 }
 
 async fn login(
@@ -164,455 +387,7246 @@ async fn login(
     if !verify_password(&req.password, &password_hash)? {
         return Err(AppError::Unauthorized);
     }
-    let token = issue_jwt(user.id, user.is_superadmin, &state.jwt)?;
-    Ok(Json(AuthResponse { token }))
+    Ok(Json(start_session(&state, &user).await?))
 }
 
-async fn me(State(state): State<AppState>, headers: HeaderMap) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// Trades a refresh token for a new access token and the next refresh token.
+async fn refresh(
+    State(state): State<AppState>,
+    Json(req): Json<RefreshRequest>,
+) -> AppResult<Json<AuthResponse>> {
+    let refresh_token = generate_token();
+    let session = state
+        .repo
+        .rotate_refresh_token(
+            &hash_token(req.refresh_token.trim()),
+            hash_token(&refresh_token),
+            Utc::now() + chrono::Duration::days(REFRESH_TOKEN_DAYS),
+        )
+        .await?;
     let user = state
         .repo
-        .get_user(au.id)
+        .get_user(session.user_id)
         .await?
         .ok_or(AppError::Unauthorized)?;
-    Ok(Json(user))
+    Ok(Json(auth_response(
+        &state,
+        &user,
+        session.id,
+        refresh_token,
+    )?))
 }
 
-async fn require_member_or_superadmin(
-    state: &AppState,
-    au: &AuthUser,
-    schedule_id: Uuid,
-) -> AppResult<ScheduleRole> {
-    if au.is_superadmin {
-        return Ok(ScheduleRole::Admin);
-    }
-    state
-        .repo
-        .get_schedule_role(schedule_id, au.id)
-        .await?
-        .ok_or(AppError::Forbidden)
+/// Ends the caller's session; its access and refresh tokens stop working.
+async fn logout(State(state): State<AppState>, headers: HeaderMap) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let session_id = au.session_id.ok_or(AppError::Unauthorized)?;
+    state.repo.revoke_session(session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn require_admin_or_superadmin(
+/// Mails a link to the web UI carrying a single-use token in a query
+/// parameter named after its purpose.
+async fn send_account_token(
     state: &AppState,
-    au: &AuthUser,
-    schedule_id: Uuid,
+    user: &User,
+    purpose: TokenPurpose,
+    expires_in: chrono::Duration,
+    subject: &str,
+    intro: &str,
 ) -> AppResult<()> {
-    if au.is_superadmin {
-        return Ok(());
-    }
-    let role = state
+    let token = generate_token();
+    state
         .repo
-        .get_schedule_role(schedule_id, au.id)
-        .await?
-        .ok_or(AppError::Forbidden)?;
-    if role != ScheduleRole::Admin {
-        return Err(AppError::Forbidden);
-    }
-    Ok(())
+        .create_account_token(
+            user.id,
+            purpose,
+            hash_token(&token),
+            Utc::now() + expires_in,
+        )
+        .await?;
+    let link = format!("{}/?{}={token}", state.public_url, purpose.as_str());
+    state
+        .mailer
+        .send(Email {
+            to: user.email.clone(),
+            subject: subject.to_string(),
+            body: format!("{intro}\n\n{link}\n\nIf you didn't ask for this, ignore this email."),
+        })
+        .await
 }
 
-async fn list_schedules(
+async fn send_verification_email(state: &AppState, user: &User) -> AppResult<()> {
+    send_account_token(
+        state,
+        user,
+        TokenPurpose::EmailVerification,
+        chrono::Duration::hours(EMAIL_VERIFICATION_HOURS),
+        "Confirm your email",
+        "Follow this link to confirm your Buddy Schedule email address:",
+    )
+    .await
+}
+
+async fn resend_verification(
     State(state): State<AppState>,
     headers: HeaderMap,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    let schedules = state.repo.list_schedules_for_user(au.id).await?;
-    Ok(Json(schedules))
+    let user = state
+        .repo
+        .get_user(au.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if user.email_verified_at.is_some() {
+        return Err(AppError::Conflict("email is already verified".to_string()));
+    }
+    send_verification_email(&state, &user).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Debug, Deserialize)]
-struct CreateScheduleRequest {
-    name: String,
-    subject_type: String,
-    subject_name: String,
+struct AccountTokenRequest {
+    token: String,
 }
 
-async fn create_schedule(
+async fn verify_email(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    Json(req): Json<CreateScheduleRequest>,
+    Json(req): Json<AccountTokenRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
-    if req.name.trim().is_empty() {
-        return Err(AppError::BadRequest("name is required".to_string()));
-    }
-    let schedule = state
+    let user_id = state
         .repo
-        .create_schedule(NewSchedule {
-            name: req.name.trim().to_string(),
-            subject_type: req.subject_type.trim().to_string(),
-            subject_name: req.subject_name.trim().to_string(),
-            created_by: au.id,
-        })
-        .await?;
-    Ok((StatusCode::CREATED, Json(schedule)))
+        .consume_account_token(
+            &hash_token(req.token.trim()),
+            TokenPurpose::EmailVerification,
+        )
+        .await?
+        .ok_or_else(|| AppError::BadRequest("link is invalid or has expired".to_string()))?;
+    state.repo.mark_email_verified(user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
 #[derive(Debug, Deserialize)]
-struct AddMemberRequest {
+struct PasswordResetRequest {
     email: String,
-    role: ScheduleRole,
-}
-
-#[derive(Debug, Serialize)]
-struct MemberWithRole {
-    user: User,
-    role: ScheduleRole,
 }
 
-async fn list_members(
+/// Always answers 204 so the response doesn't reveal who has an account.
+async fn request_password_reset(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<PasswordResetRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
-    require_member_or_superadmin(&state, &au, schedule_id).await?;
-    let members = state.repo.list_schedule_members(schedule_id).await?;
-    let response: Vec<MemberWithRole> = members
-        .into_iter()
-        .map(|(user, role)| MemberWithRole { user, role })
-        .collect();
-    Ok(Json(response))
+    let email = req.email.trim().to_lowercase();
+    if let Some((user, _)) = state.repo.find_user_by_email(&email).await? {
+        if let Err(e) = send_account_token(
+            &state,
+            &user,
+            TokenPurpose::PasswordReset,
+            chrono::Duration::minutes(PASSWORD_RESET_MINUTES),
+            "Reset your password",
+            "Follow this link to choose a new Buddy Schedule password:",
+        )
+        .await
+        {
+            tracing::warn!(user_id = %user.id, "password reset email failed: {e:?}");
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn add_member(
+#[derive(Debug, Deserialize)]
+struct ConfirmPasswordResetRequest {
+    token: String,
+    password: String,
+}
+
+/// Sets the new password and signs the user out everywhere. Following the
+/// link also proves they own the address.
+async fn confirm_password_reset(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    Path(schedule_id): Path<Uuid>,
-    Json(req): Json<AddMemberRequest>,
+    Json(req): Json<ConfirmPasswordResetRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
-    require_admin_or_superadmin(&state, &au, schedule_id).await?;
-
-    let email = req.email.trim().to_lowercase();
-    let Some((user, _)) = state.repo.find_user_by_email(&email).await? else {
-        return Err(AppError::BadRequest("user email not found".to_string()));
-    };
+    if req.password.len() < 8 {
+        return Err(AppError::BadRequest(
+            "password must be >= 8 chars".to_string(),
+        ));
+    }
+    let user_id = state
+        .repo
+        .consume_account_token(&hash_token(req.token.trim()), TokenPurpose::PasswordReset)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("link is invalid or has expired".to_string()))?;
     state
         .repo
-        .add_member(schedule_id, user.id, req.role)
+        .set_password(user_id, hash_password(&req.password)?)
         .await?;
+    state.repo.mark_email_verified(user_id).await?;
+    state.repo.revoke_user_sessions(user_id).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
-#[derive(Debug, Deserialize)]
-struct SetRoleRequest {
-    role: ScheduleRole,
+#[derive(Debug, Serialize)]
+struct LogoutAllResponse {
+    revoked: u64,
 }
 
-async fn set_member_role(
+/// Ends every session of the caller, including this one.
+async fn logout_all(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path((schedule_id, user_id)): Path<(Uuid, Uuid)>,
-    Json(req): Json<SetRoleRequest>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_admin_or_superadmin(&state, &au, schedule_id).await?;
-    state
+    let revoked = state.repo.revoke_user_sessions(au.id).await?;
+    Ok(Json(LogoutAllResponse { revoked }))
+}
+
+async fn me(State(state): State<AppState>, headers: HeaderMap) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let user = state
         .repo
-        .set_member_role(schedule_id, user_id, req.role)
-        .await?;
-    Ok(StatusCode::NO_CONTENT)
+        .get_user(au.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    Ok(Json(user))
 }
 
-#[derive(Debug, Deserialize)]
-struct CreateShiftRequest {
-    starts_at: DateTime<Utc>,
-    ends_at: DateTime<Utc>,
-    period: Period,
+#[derive(Debug, Serialize)]
+struct CalendarTokenResponse {
+    token: String,
+    my_shifts_url: String,
 }
 
-async fn create_shift(
+/// Issues a new calendar feed token, invalidating any previous one.
+async fn rotate_calendar_token(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(schedule_id): Path<Uuid>,
-    Json(req): Json<CreateShiftRequest>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_admin_or_superadmin(&state, &au, schedule_id).await?;
-
-    let shift = state
+    let token = generate_token();
+    state
         .repo
-        .create_shift(NewShift {
-            schedule_id,
-            starts_at: req.starts_at,
-            ends_at: req.ends_at,
-            period: req.period,
-            created_by: au.id,
-        })
+        .set_calendar_feed_token(au.id, Some(hash_token(&token)))
         .await?;
-    Ok((StatusCode::CREATED, Json(shift)))
-}
-
-#[derive(Debug, Deserialize)]
-struct ListShiftsQuery {
-    from: String,
-    to: String,
+    Ok((
+        StatusCode::CREATED,
+        Json(CalendarTokenResponse {
+            my_shifts_url: format!("/api/calendar/me.ics?token={token}"),
+            token,
+        }),
+    ))
 }
 
-async fn list_shifts(
+async fn revoke_calendar_token(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(schedule_id): Path<Uuid>,
-    Query(q): Query<ListShiftsQuery>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_member_or_superadmin(&state, &au, schedule_id).await?;
-
-    let from = DateTime::parse_from_rfc3339(&q.from)
-        .map_err(|_| AppError::BadRequest("invalid from (RFC3339 required)".to_string()))?
-        .with_timezone(&Utc);
-    let to = DateTime::parse_from_rfc3339(&q.to)
-        .map_err(|_| AppError::BadRequest("invalid to (RFC3339 required)".to_string()))?
-        .with_timezone(&Utc);
-
-    let shifts = state.repo.list_shifts(schedule_id, from, to).await?;
-    Ok(Json(shifts))
+    state.repo.set_calendar_feed_token(au.id, None).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+const FEED_PAST_DAYS: i64 = 90;
+const FEED_FUTURE_DAYS: i64 = 365;
+
 #[derive(Debug, Deserialize)]
-struct AssignShiftRequest {
-    assigned_user_id: Option<Uuid>,
+struct FeedQuery {
+    token: String,
 }
 
-async fn assign_shift(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    Path(shift_id): Path<Uuid>,
-    Json(req): Json<AssignShiftRequest>,
-) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
-    let shift = state
+async fn feed_user(state: &AppState, token: &str) -> AppResult<AuthUser> {
+    let user = state
         .repo
-        .get_shift(shift_id)
+        .find_user_by_feed_token(&hash_token(token))
         .await?
-        .ok_or(AppError::NotFound)?;
+        .ok_or(AppError::Unauthorized)?;
+    Ok(AuthUser {
+        id: user.id,
+        is_superadmin: user.is_superadmin,
+        session_id: None,
+    })
+}
 
-    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
-    let target = req.assigned_user_id.unwrap_or(au.id);
+fn feed_window() -> (DateTime<Utc>, DateTime<Utc>) {
+    let now = Utc::now();
+    (
+        now - chrono::Duration::days(FEED_PAST_DAYS),
+        now + chrono::Duration::days(FEED_FUTURE_DAYS),
+    )
+}
 
-    // Only admins can assign other users.
-    if target != au.id && !(au.is_superadmin || role == ScheduleRole::Admin) {
-        return Err(AppError::Forbidden);
+async fn feed_entries(
+    state: &AppState,
+    shifts: Vec<Shift>,
+    schedule_names: &HashMap<Uuid, String>,
+) -> AppResult<Vec<FeedEntry>> {
+    let ids: Vec<Uuid> = shifts.iter().map(|s| s.id).collect();
+    let mut comments: HashMap<Uuid, Vec<_>> = HashMap::new();
+    for c in state.repo.list_comments_for_shifts(&ids).await? {
+        comments.entry(c.shift_id).or_default().push(c);
     }
+    Ok(shifts
+        .into_iter()
+        .map(|shift| FeedEntry {
+            schedule_name: schedule_names
+                .get(&shift.schedule_id)
+                .cloned()
+                .unwrap_or_default(),
+            comments: comments.remove(&shift.id).unwrap_or_default(),
+            shift,
+        })
+        .collect())
+}
 
-    state.repo.assign_shift(shift_id, Some(target)).await?;
-    Ok(StatusCode::NO_CONTENT)
+fn calendar_response(body: String) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        body,
+    )
 }
 
-#[derive(Debug, Deserialize)]
-struct AddCommentRequest {
-    body: String,
+async fn my_calendar_feed(
+    State(state): State<AppState>,
+    Query(q): Query<FeedQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = feed_user(&state, &q.token).await?;
+    let (from, to) = feed_window();
+    let shifts = state.repo.list_assigned_shifts(au.id, from, to).await?;
+
+    let mut names = HashMap::new();
+    for sid in shifts.iter().map(|s| s.schedule_id) {
+        if let std::collections::hash_map::Entry::Vacant(e) = names.entry(sid) {
+            if let Some(schedule) = state.repo.get_schedule(sid).await? {
+                e.insert(schedule.name);
+            }
+        }
+    }
+    let entries = feed_entries(&state, shifts, &names).await?;
+    Ok(calendar_response(ical::render_calendar(
+        "My shifts",
+        &entries,
+        Utc::now(),
+    )))
 }
 
-async fn add_shift_comment(
+async fn schedule_calendar_feed(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    Path(shift_id): Path<Uuid>,
-    Json(req): Json<AddCommentRequest>,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<FeedQuery>,
 ) -> AppResult<impl IntoResponse> {
-    let au = AuthUser::from_headers(&state, &headers).await?;
-    let shift = state
+    let au = feed_user(&state, &q.token).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let schedule = state
         .repo
-        .get_shift(shift_id)
+        .get_schedule(schedule_id)
         .await?
         .ok_or(AppError::NotFound)?;
-    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
 
-    // Only assigned user or admins can comment.
-    if !au.is_superadmin && role != ScheduleRole::Admin && shift.assigned_user_id != Some(au.id) {
+    let (from, to) = feed_window();
+    let shifts = state.repo.list_shifts(schedule_id, from, to).await?;
+    let names = HashMap::from([(schedule.id, schedule.name.clone())]);
+    let entries = feed_entries(&state, shifts, &names).await?;
+    Ok(calendar_response(ical::render_calendar(
+        &schedule.name,
+        &entries,
+        Utc::now(),
+    )))
+}
+
+/// Read access to a schedule, archived or not.
+async fn require_member_to_read(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+) -> AppResult<ScheduleRole> {
+    if au.is_superadmin {
+        return Ok(ScheduleRole::Admin);
+    }
+    state
+        .repo
+        .get_schedule_role(schedule_id, au.id)
+        .await?
+        .ok_or(AppError::Forbidden)
+}
+
+async fn require_admin_to_read(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+) -> AppResult<()> {
+    let role = require_member_to_read(state, au, schedule_id).await?;
+    if role != ScheduleRole::Admin {
         return Err(AppError::Forbidden);
     }
-    if req.body.trim().is_empty() {
-        return Err(AppError::BadRequest("comment body is required".to_string()));
+    Ok(())
+}
+
+/// Archived schedules are read-only, even for superadmins.
+async fn require_active_schedule(state: &AppState, schedule_id: Uuid) -> AppResult<()> {
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if schedule.archived_at.is_some() {
+        return Err(AppError::Conflict(
+            "schedule is archived; unarchive it to make changes".to_string(),
+        ));
     }
-    let c = state
+    Ok(())
+}
+
+/// Access for changing the schedule, which must not be archived.
+async fn require_member_or_superadmin(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+) -> AppResult<ScheduleRole> {
+    let role = require_member_to_read(state, au, schedule_id).await?;
+    require_active_schedule(state, schedule_id).await?;
+    Ok(role)
+}
+
+async fn require_admin_or_superadmin(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+) -> AppResult<()> {
+    require_admin_to_read(state, au, schedule_id).await?;
+    require_active_schedule(state, schedule_id).await
+}
+
+#[derive(Debug, Deserialize)]
+struct ListSchedulesQuery {
+    #[serde(default)]
+    include_archived: bool,
+}
+
+async fn list_schedules(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<ListSchedulesQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let schedules = state
         .repo
-        .add_shift_comment(NewShiftComment {
-            shift_id,
-            user_id: au.id,
-            body: req.body.trim().to_string(),
-        })
+        .list_schedules_for_user(au.id, q.include_archived)
         .await?;
-    Ok((StatusCode::CREATED, Json(c)))
+    Ok(Json(schedules))
 }
 
 #[derive(Debug, Deserialize)]
-struct CreateTemplateRequest {
+struct CreateScheduleRequest {
     name: String,
-    definition: serde_json::Value,
+    subject_type: String,
+    subject_name: String,
+    /// IANA zone name, e.g. "Europe/Lisbon". Defaults to UTC.
+    timezone: Option<String>,
 }
 
-async fn create_template(
+async fn create_schedule(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(schedule_id): Path<Uuid>,
-    Json(req): Json<CreateTemplateRequest>,
+    Json(req): Json<CreateScheduleRequest>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_admin_or_superadmin(&state, &au, schedule_id).await?;
-    if req.name.trim().is_empty() {
-        return Err(AppError::BadRequest("name is required".to_string()));
-    }
-    let t = state
+    check_schedule_name(&req.name)?;
+    let timezone = req.timezone.as_deref().unwrap_or("UTC").trim();
+    tz::parse_tz(timezone)?;
+    let schedule = state
         .repo
-        .create_template(NewTemplate {
-            schedule_id,
+        .create_schedule(NewSchedule {
             name: req.name.trim().to_string(),
-            definition: req.definition,
+            subject_type: req.subject_type.trim().to_string(),
+            subject_name: req.subject_name.trim().to_string(),
+            timezone: timezone.to_string(),
             created_by: au.id,
         })
         .await?;
-    Ok((StatusCode::CREATED, Json(t)))
+    Ok((StatusCode::CREATED, Json(schedule)))
 }
 
-async fn list_templates(
+fn check_schedule_name(name: &str) -> AppResult<()> {
+    if name.trim().is_empty() {
+        return Err(AppError::BadRequest("name is required".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateScheduleRequest {
+    name: Option<String>,
+    subject_type: Option<String>,
+    subject_name: Option<String>,
+    timezone: Option<String>,
+    swap_requires_approval: Option<bool>,
+    claim_policy: Option<ClaimPolicy>,
+    check_in_grace_minutes: Option<i32>,
+}
+
+/// Longest grace period a schedule can give before a shift counts as missed.
+const MAX_CHECK_IN_GRACE_MINUTES: i32 = 24 * 60;
+
+async fn update_schedule(
     State(state): State<AppState>,
     headers: HeaderMap,
     Path(schedule_id): Path<Uuid>,
+    Json(req): Json<UpdateScheduleRequest>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_member_or_superadmin(&state, &au, schedule_id).await?;
-    Ok(Json(state.repo.list_templates(schedule_id).await?))
-}
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
 
-#[derive(Debug, Deserialize)]
-struct ApplyTemplateRequest {
-    week_start: String, // YYYY-MM-DD (UTC Monday recommended)
+    let name = req.name.map(|n| n.trim().to_string());
+    if let Some(name) = &name {
+        check_schedule_name(name)?;
+    }
+    let timezone = match req.timezone {
+        Some(t) => {
+            let t = t.trim().to_string();
+            tz::parse_tz(&t)?;
+            t
+        }
+        None => schedule.timezone,
+    };
+    let check_in_grace_minutes = req
+        .check_in_grace_minutes
+        .unwrap_or(schedule.check_in_grace_minutes);
+    if !(0..=MAX_CHECK_IN_GRACE_MINUTES).contains(&check_in_grace_minutes) {
+        return Err(AppError::BadRequest(format!(
+            "check_in_grace_minutes must be between 0 and {MAX_CHECK_IN_GRACE_MINUTES}"
+        )));
+    }
+    let schedule = state
+        .repo
+        .update_schedule(
+            schedule_id,
+            UpdateSchedule {
+                name: name.unwrap_or(schedule.name),
+                subject_type: req
+                    .subject_type
+                    .map(|t| t.trim().to_string())
+                    .unwrap_or(schedule.subject_type),
+                subject_name: req
+                    .subject_name
+                    .map(|n| n.trim().to_string())
+                    .unwrap_or(schedule.subject_name),
+                timezone,
+                swap_requires_approval: req
+                    .swap_requires_approval
+                    .unwrap_or(schedule.swap_requires_approval),
+                claim_policy: req.claim_policy.unwrap_or(schedule.claim_policy),
+                check_in_grace_minutes,
+            },
+        )
+        .await?;
+    Ok(Json(schedule))
 }
 
-#[derive(Debug, Deserialize)]
-struct TemplateSlot {
-    dow: i64,       // 0=Mon..6=Sun
-    period: Period, // morning/afternoon/night/sleep
-    start: String,  // HH:MM
-    end: String,    // HH:MM
+async fn archive_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_to_read(&state, &au, schedule_id).await?;
+    let schedule = state.repo.set_schedule_archived(schedule_id, true).await?;
+    Ok(Json(schedule))
 }
 
-#[derive(Debug, Deserialize)]
-struct TemplateDef {
-    slots: Vec<TemplateSlot>,
+async fn unarchive_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_to_read(&state, &au, schedule_id).await?;
+    let schedule = state.repo.set_schedule_archived(schedule_id, false).await?;
+    Ok(Json(schedule))
 }
 
-async fn apply_template(
+/// Deletes the schedule for good, archived or not. Only its creator and
+/// superadmins may, whatever their role in it.
+async fn delete_schedule(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path((schedule_id, template_id)): Path<(Uuid, Uuid)>,
-    Json(req): Json<ApplyTemplateRequest>,
+    Path(schedule_id): Path<Uuid>,
 ) -> AppResult<impl IntoResponse> {
     let au = AuthUser::from_headers(&state, &headers).await?;
-    require_admin_or_superadmin(&state, &au, schedule_id).await?;
-
-    let template = state
+    let schedule = state
         .repo
-        .get_template(template_id)
+        .get_schedule(schedule_id)
         .await?
         .ok_or(AppError::NotFound)?;
-    if template.schedule_id != schedule_id {
+    if !au.is_superadmin && schedule.created_by != au.id {
         return Err(AppError::Forbidden);
     }
+    let deleted = state.repo.delete_schedule(schedule_id).await?;
+    Ok(Json(deleted))
+}
 
-    let week_start = NaiveDate::parse_from_str(&req.week_start, "%Y-%m-%d")
-        .map_err(|_| AppError::BadRequest("week_start must be YYYY-MM-DD".to_string()))?;
-
-    let def: TemplateDef = serde_json::from_value(template.definition)
-        .map_err(|_| AppError::BadRequest("invalid template definition".to_string()))?;
+#[derive(Debug, Deserialize)]
+struct AddMemberRequest {
+    email: String,
+    role: ScheduleRole,
+}
 
-    let mut created = Vec::with_capacity(def.slots.len());
-    for slot in def.slots {
-        if !(0..=6).contains(&slot.dow) {
-            return Err(AppError::BadRequest("slot.dow must be 0..6".to_string()));
-        }
-        let start_t = NaiveTime::parse_from_str(&slot.start, "%H:%M")
-            .map_err(|_| AppError::BadRequest("slot.start must be HH:MM".to_string()))?;
-        let end_t = NaiveTime::parse_from_str(&slot.end, "%H:%M")
-            .map_err(|_| AppError::BadRequest("slot.end must be HH:MM".to_string()))?;
+#[derive(Debug, Serialize)]
+struct MemberWithRole {
+    user: User,
+    role: ScheduleRole,
+}
 
-        let day = week_start
-            .checked_add_signed(chrono::Duration::days(slot.dow))
-            .ok_or_else(|| AppError::BadRequest("invalid date".to_string()))?;
+async fn list_members(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let members = state.repo.list_schedule_members(schedule_id).await?;
+    let response: Vec<MemberWithRole> = members
+        .into_iter()
+        .map(|(user, role)| MemberWithRole { user, role })
+        .collect();
+    Ok(Json(response))
+}
 
-        let start_naive = day.and_time(start_t);
-        let mut end_naive = day.and_time(end_t);
-        if end_naive <= start_naive {
-            end_naive += chrono::Duration::days(1);
-        }
+/// Schedules someone else created that an unverified user can be added to.
+const UNVERIFIED_SCHEDULE_LIMIT: usize = 1;
 
-        let starts_at = DateTime::<Utc>::from_naive_utc_and_offset(start_naive, Utc);
-        let ends_at = DateTime::<Utc>::from_naive_utc_and_offset(end_naive, Utc);
+async fn add_member(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<AddMemberRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
 
-        let shift = state
+    let email = req.email.trim().to_lowercase();
+    let Some((user, _)) = state.repo.find_user_by_email(&email).await? else {
+        return Err(AppError::BadRequest("user email not found".to_string()));
+    };
+    if user.email_verified_at.is_none() {
+        // Anyone can register an address they don't own, so an unverified
+        // account only gets into a few schedules before proving it.
+        let joined = state
             .repo
-            .create_shift(NewShift {
-                schedule_id,
-                starts_at,
-                ends_at,
-                period: slot.period,
-                created_by: au.id,
-            })
-            .await?;
-        created.push(shift);
+            .list_schedules_for_user(user.id, true)
+            .await?
+            .iter()
+            .filter(|s| s.schedule.created_by != user.id)
+            .count();
+        if joined >= UNVERIFIED_SCHEDULE_LIMIT {
+            return Err(AppError::BadRequest(format!(
+                "{email} must verify their email before joining more schedules"
+            )));
+        }
     }
-
-    Ok((StatusCode::CREATED, Json(created)))
+    state
+        .repo
+        .add_member(schedule_id, user.id, req.role)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
-#[cfg(test)]
-mod api_tests {
-    use super::*;
-    use crate::repo::MemRepo;
-    use http_body_util::BodyExt;
-    use tower::ServiceExt;
-
-    fn router() -> Router {
-        build_router(AppState {
-            repo: Arc::new(MemRepo::new()),
-            jwt: JwtKeys::new("test-secret"),
-            cors_origin: None,
-        })
-    }
-
-    #[tokio::test]
-    async fn register_login_and_create_schedule() {
-        let app = router();
+#[derive(Debug, Deserialize)]
+struct SetRoleRequest {
+    role: ScheduleRole,
+}
+
+async fn set_member_role(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<SetRoleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    state
+        .repo
+        .set_member_role(schedule_id, user_id, req.role)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// What happens to a departing member's upcoming shifts.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum FutureShifts {
+    #[default]
+    Unassign,
+    Transfer,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RemoveMemberRequest {
+    #[serde(default)]
+    future_shifts: FutureShifts,
+    /// Member who takes the shifts over; required for `transfer`.
+    transfer_to: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct SkippedTransfer {
+    shift_id: Uuid,
+    reason: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RemoveMemberResponse {
+    unassigned_shift_ids: Vec<Uuid>,
+    transferred_shift_ids: Vec<Uuid>,
+    /// Shifts the new member couldn't take, also in `unassigned_shift_ids`.
+    skipped_transfers: Vec<SkippedTransfer>,
+}
+
+/// Takes `user_id` out of the schedule and, in the same transaction, off
+/// every shift of it that hasn't started yet, noting why on each shift.
+/// Their pending claims are rejected and pending swaps cancelled.
+/// Transferred shifts the new member is already on, or would clash with
+/// their other shifts or availability, are just unassigned.
+async fn remove_from_schedule(
+    state: &AppState,
+    actor_id: Uuid,
+    schedule_id: Uuid,
+    user_id: Uuid,
+    req: RemoveMemberRequest,
+) -> AppResult<RemoveMemberResponse> {
+    if state
+        .repo
+        .get_schedule_role(schedule_id, user_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::NotFound);
+    }
+    let target = match req.future_shifts {
+        FutureShifts::Unassign => None,
+        FutureShifts::Transfer => {
+            let Some(target) = req.transfer_to else {
+                return Err(AppError::BadRequest(
+                    "transfer_to is required to transfer shifts".to_string(),
+                ));
+            };
+            if target == user_id
+                || state
+                    .repo
+                    .get_schedule_role(schedule_id, target)
+                    .await?
+                    .is_none()
+            {
+                return Err(AppError::BadRequest(
+                    "transfer_to must be another member of the schedule".to_string(),
+                ));
+            }
+            Some(target)
+        }
+    };
+    let shifts: Vec<Shift> = state
+        .repo
+        .list_assigned_shifts(user_id, Utc::now(), DateTime::<Utc>::MAX_UTC)
+        .await?
+        .into_iter()
+        .filter(|s| s.schedule_id == schedule_id)
+        .collect();
+
+    let mut uow = UnitOfWork::new();
+    let mut response = RemoveMemberResponse {
+        unassigned_shift_ids: Vec::new(),
+        transferred_shift_ids: Vec::new(),
+        skipped_transfers: Vec::new(),
+    };
+    let mut transferred: Vec<&Shift> = Vec::new();
+    for shift in &shifts {
+        let mut to = target.filter(|t| !shift.is_assigned(*t));
+        if let Some(t) = to {
+            let reason = match transfer_clash(state, t, shift, &transferred).await {
+                Ok(()) => None,
+                Err(AppError::Conflict(reason)) => Some(reason),
+                Err(e) => return Err(e),
+            };
+            if let Some(reason) = reason {
+                response.skipped_transfers.push(SkippedTransfer {
+                    shift_id: shift.id,
+                    reason,
+                });
+                to = None;
+            }
+        }
+        uow.reassign_shift(shift.id, Some(user_id), to);
+        let body = if to.is_some() {
+            transferred.push(shift);
+            response.transferred_shift_ids.push(shift.id);
+            "Transferred: previous member left the schedule"
+        } else {
+            response.unassigned_shift_ids.push(shift.id);
+            "Unassigned: member left the schedule"
+        };
+        uow.add_shift_comment(NewShiftComment {
+            shift_id: shift.id,
+            user_id: actor_id,
+            body: body.to_string(),
+        });
+    }
+    uow.close_member_requests(schedule_id, user_id, actor_id);
+    uow.remove_member(schedule_id, user_id);
+    state.repo.commit(uow).await?;
+    Ok(response)
+}
+
+/// Checks `target` can take over `shift` as `assign` would, including the
+/// shifts already handed to them in the same removal.
+async fn transfer_clash(
+    state: &AppState,
+    target: Uuid,
+    shift: &Shift,
+    transferred: &[&Shift],
+) -> AppResult<()> {
+    ensure_no_overlaps(state, target, shift).await?;
+    ensure_available(state, target, shift).await?;
+    let clashes: Vec<Shift> = transferred
+        .iter()
+        .filter(|other| {
+            rota::too_close(
+                other,
+                shift.starts_at,
+                shift.ends_at,
+                chrono::Duration::zero(),
+            )
+        })
+        .map(|other| (*other).clone())
+        .collect();
+    if !clashes.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "user already assigned to overlapping shifts: {}",
+            describe_shifts(&clashes)
+        )));
+    }
+    Ok(())
+}
+
+async fn remove_member(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, user_id)): Path<(Uuid, Uuid)>,
+    body: Option<Json<RemoveMemberRequest>>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let response = remove_from_schedule(&state, au.id, schedule_id, user_id, req).await?;
+    Ok(Json(response))
+}
+
+/// Leaves a schedule, unassigning or handing off future shifts as an
+/// admin's removal would.
+async fn leave_schedule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    body: Option<Json<RemoveMemberRequest>>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    state
+        .repo
+        .get_schedule_role(schedule_id, au.id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_active_schedule(&state, schedule_id).await?;
+    let req = body.map(|Json(b)| b).unwrap_or_default();
+    let response = remove_from_schedule(&state, au.id, schedule_id, au.id, req).await?;
+    Ok(Json(response))
+}
+
+/// Lifetime of an invite created without `expires_at`.
+const INVITE_DEFAULT_DAYS: i64 = 7;
+/// Longest an invite may stay open.
+const INVITE_MAX_DAYS: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct CreateInviteRequest {
+    /// Omit for a link anyone can use.
+    email: Option<String>,
+    role: ScheduleRole,
+    expires_at: Option<DateTime<Utc>>,
+    /// Links only; omit for unlimited. Email invites work once.
+    max_uses: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreatedInvite {
+    #[serde(flatten)]
+    invite: ScheduleInvite,
+    /// Link to accept the invite; only shown now, as just its hash is stored.
+    url: String,
+}
+
+/// Invites someone by email (mailing them the link) or makes a shareable
+/// link, for people who may not have registered yet.
+async fn create_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateInviteRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let email = req
+        .email
+        .map(|e| e.trim().to_lowercase())
+        .filter(|e| !e.is_empty());
+    let max_uses = match (&email, req.max_uses) {
+        (Some(_), None | Some(1)) => Some(1),
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest(
+                "email invites can only be used once".to_string(),
+            ))
+        }
+        (None, Some(n)) if n < 1 => {
+            return Err(AppError::BadRequest(
+                "max_uses must be at least 1".to_string(),
+            ))
+        }
+        (None, max_uses) => max_uses,
+    };
+    let now = Utc::now();
+    let expires_at = req
+        .expires_at
+        .unwrap_or(now + chrono::Duration::days(INVITE_DEFAULT_DAYS));
+    if expires_at <= now || expires_at > now + chrono::Duration::days(INVITE_MAX_DAYS) {
+        return Err(AppError::BadRequest(format!(
+            "expires_at must be within the next {INVITE_MAX_DAYS} days"
+        )));
+    }
+
+    let token = generate_token();
+    let invite = state
+        .repo
+        .create_invite(NewInvite {
+            schedule_id,
+            email: email.clone(),
+            token_hash: hash_token(&token),
+            role: req.role,
+            max_uses,
+            expires_at,
+            created_by: au.id,
+        })
+        .await?;
+    let url = format!("{}/?invite={token}", state.public_url);
+
+    if let Some(email) = email {
+        let sent = state
+            .mailer
+            .send(Email {
+                to: email,
+                subject: format!("You're invited to {}", schedule.name),
+                body: format!(
+                    "You've been invited to join the schedule \"{}\" on Buddy Schedule. \
+                     Follow this link to register or log in and accept:\n\n{url}\n\n\
+                     The invite expires on {}.",
+                    schedule.name,
+                    expires_at.format("%Y-%m-%d %H:%M UTC"),
+                ),
+            })
+            .await;
+        if let Err(e) = sent {
+            state.repo.revoke_invite(invite.id).await?;
+            return Err(e);
+        }
+    }
+    Ok((StatusCode::CREATED, Json(CreatedInvite { invite, url })))
+}
+
+async fn list_invites(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_to_read(&state, &au, schedule_id).await?;
+    let invites = state.repo.list_pending_invites(schedule_id).await?;
+    Ok(Json(invites))
+}
+
+async fn revoke_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, invite_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    state
+        .repo
+        .get_invite(invite_id)
+        .await?
+        .filter(|i| i.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)?;
+
+    state.repo.revoke_invite(invite_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn load_pending_invite(state: &AppState, token: &str) -> AppResult<ScheduleInvite> {
+    let invite = state
+        .repo
+        .find_invite_by_token(&hash_token(token.trim()))
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if !invite.is_pending(Utc::now()) {
+        return Err(AppError::Conflict("invite is no longer valid".to_string()));
+    }
+    Ok(invite)
+}
+
+#[derive(Debug, Serialize)]
+struct InvitePreview {
+    schedule_name: String,
+    subject_type: String,
+    subject_name: String,
+    role: ScheduleRole,
+    email: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+/// What an invite is for, so the web UI can show it before the visitor
+/// registers or logs in.
+async fn get_invite(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let invite = load_pending_invite(&state, &token).await?;
+    let schedule = state
+        .repo
+        .get_schedule(invite.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(InvitePreview {
+        schedule_name: schedule.name,
+        subject_type: schedule.subject_type,
+        subject_name: schedule.subject_name,
+        role: invite.role,
+        email: invite.email,
+        expires_at: invite.expires_at,
+    }))
+}
+
+/// Joins the invite's schedule. Email invites only work for the account with
+/// that address, and accepting one also verifies it, since the link was
+/// mailed there.
+async fn accept_invite(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let invite = load_pending_invite(&state, &token).await?;
+    require_active_schedule(&state, invite.schedule_id).await?;
+    let user = state
+        .repo
+        .get_user(au.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if invite.email.as_ref().is_some_and(|e| *e != user.email) {
+        return Err(AppError::BadRequest(
+            "this invite is for a different email address".to_string(),
+        ));
+    }
+
+    let invite = state.repo.accept_invite(invite.id, au.id).await?;
+    if invite.email.is_some() {
+        state.repo.mark_email_verified(au.id).await?;
+    }
+    let schedule = state
+        .repo
+        .get_schedule(invite.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(ScheduleWithRole {
+        schedule,
+        role: invite.role,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateShiftRequest {
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    period: Period,
+    /// How many people work the shift.
+    #[serde(default = "default_capacity")]
+    capacity: i16,
+}
+
+fn default_capacity() -> i16 {
+    1
+}
+
+async fn create_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateShiftRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let schedule_tasks = state.repo.list_schedule_tasks(schedule_id).await?;
+
+    let shift = state
+        .repo
+        .create_shift(NewShift {
+            schedule_id,
+            starts_at: req.starts_at,
+            ends_at: req.ends_at,
+            period: req.period,
+            capacity: req.capacity,
+            assigned_user_id: None,
+            template_id: None,
+            template_week: None,
+            tasks: checklist::for_shift(
+                &schedule_tasks,
+                req.starts_at,
+                req.ends_at,
+                req.period,
+                zone,
+            )?,
+            created_by: au.id,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(shift)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListShiftsQuery {
+    /// RFC3339 instant, or a YYYY-MM-DD local date in the schedule's zone.
+    from: String,
+    /// RFC3339 instant (exclusive), or a YYYY-MM-DD local date (inclusive).
+    to: String,
+}
+
+/// Parses a range bound as either an RFC3339 instant or a local date in `zone`.
+/// Dates resolve to local midnight; an `inclusive_end` date resolves to the
+/// following midnight so the whole day is covered.
+fn parse_range_bound(
+    value: &str,
+    name: &str,
+    zone: chrono_tz::Tz,
+    inclusive_end: bool,
+) -> AppResult<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let mut date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        AppError::BadRequest(format!("invalid {name} (RFC3339 or YYYY-MM-DD required)"))
+    })?;
+    if inclusive_end {
+        date = date
+            .succ_opt()
+            .ok_or_else(|| AppError::BadRequest(format!("invalid {name}")))?;
+    }
+    tz::start_of_day(zone, date)
+}
+
+async fn list_shifts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+
+    let from = parse_range_bound(&q.from, "from", zone, false)?;
+    let to = parse_range_bound(&q.to, "to", zone, true)?;
+
+    let shifts = state.repo.list_shifts(schedule_id, from, to).await?;
+    Ok(Json(shifts))
+}
+
+async fn schedule_stats(
+    state: &AppState,
+    headers: &HeaderMap,
+    schedule_id: Uuid,
+    q: &ListShiftsQuery,
+) -> AppResult<stats::ScheduleStats> {
+    let au = AuthUser::from_headers(state, headers).await?;
+    require_member_to_read(state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = parse_range_bound(&q.from, "from", zone, false)?;
+    let to = parse_range_bound(&q.to, "to", zone, true)?;
+
+    let tallies = state.repo.tally_shifts(schedule_id, from, to, zone).await?;
+    let mut members: Vec<(Uuid, String)> = state
+        .repo
+        .list_schedule_members(schedule_id)
+        .await?
+        .into_iter()
+        .map(|(user, _)| (user.id, user.email))
+        .collect();
+    // Former members still get a row for the shifts they worked.
+    for user_id in tallies.iter().filter_map(|t| t.user_id) {
+        if members.iter().any(|(id, _)| *id == user_id) {
+            continue;
+        }
+        if let Some(user) = state.repo.get_user(user_id).await? {
+            members.push((user.id, user.email));
+        }
+    }
+    Ok(stats::build(from, to, members, &tallies))
+}
+
+/// Per-member workload for shifts starting in the range.
+async fn get_schedule_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let stats = schedule_stats(&state, &headers, schedule_id, &q).await?;
+    Ok(Json(stats))
+}
+
+async fn get_schedule_stats_csv(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let stats = schedule_stats(&state, &headers, schedule_id, &q).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+        stats::to_csv(&stats),
+    ))
+}
+
+const MAX_IMPORT_SHIFTS: usize = 2000;
+
+#[derive(Debug, Deserialize)]
+struct ImportIcsRequest {
+    /// Raw iCalendar text.
+    ics: String,
+    #[serde(default)]
+    dry_run: bool,
+    /// Import window, same formats as `list_shifts`. Required for recurring events.
+    from: Option<String>,
+    to: Option<String>,
+    /// Event SUMMARY (case-insensitive) to period. Unmapped events get a
+    /// period inferred from their local start time.
+    #[serde(default)]
+    period_map: HashMap<String, Period>,
+}
+
+/// Options for a raw `text/calendar` upload, which has no JSON body to carry them.
+#[derive(Debug, Deserialize)]
+struct ImportIcsQuery {
+    #[serde(default)]
+    dry_run: bool,
+    from: Option<String>,
+    to: Option<String>,
+    /// `period_map` as a JSON object, e.g. `{"Vet":"afternoon"}`.
+    period_map: Option<String>,
+}
+
+/// Reads the import from a JSON body, or from a `text/calendar` upload with
+/// its options in the query string.
+fn import_ics_request(
+    headers: &HeaderMap,
+    q: ImportIcsQuery,
+    body: &[u8],
+) -> AppResult<ImportIcsRequest> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if content_type.starts_with("application/json") {
+        return serde_json::from_slice(body)
+            .map_err(|e| AppError::BadRequest(format!("invalid import request: {e}")));
+    }
+    if !content_type.starts_with("text/calendar") {
+        return Err(AppError::BadRequest(
+            "send text/calendar, or JSON with an ics field".to_string(),
+        ));
+    }
+    let ics = String::from_utf8(body.to_vec())
+        .map_err(|_| AppError::BadRequest("calendar must be UTF-8".to_string()))?;
+    let period_map = match q.period_map {
+        Some(map) => serde_json::from_str(&map).map_err(|_| {
+            AppError::BadRequest("period_map must be a JSON object of periods".to_string())
+        })?,
+        None => HashMap::new(),
+    };
+    Ok(ImportIcsRequest {
+        ics,
+        dry_run: q.dry_run,
+        from: q.from,
+        to: q.to,
+        period_map,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct ImportedShift {
+    summary: String,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    period: Period,
+    /// Existing shifts in the schedule that overlap this one.
+    overlaps: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportIcsResponse {
+    dry_run: bool,
+    shifts: Vec<ImportedShift>,
+    created: Vec<Shift>,
+    skipped: Vec<String>,
+}
+
+async fn import_ics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ImportIcsQuery>,
+    body: axum::body::Bytes,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let req = import_ics_request(&headers, q, &body)?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+
+    let window = match (&req.from, &req.to) {
+        (Some(from), Some(to)) => Some((
+            parse_range_bound(from, "from", zone, false)?,
+            parse_range_bound(to, "to", zone, true)?,
+        )),
+        (None, None) => None,
+        _ => {
+            return Err(AppError::BadRequest(
+                "from and to must be given together".to_string(),
+            ))
+        }
+    };
+    let period_map: HashMap<String, Period> = req
+        .period_map
+        .into_iter()
+        .map(|(k, v)| (k.trim().to_lowercase(), v))
+        .collect();
+
+    let parsed = ical::parse_calendar(&req.ics, zone)?;
+    let mut shifts = Vec::new();
+    for event in &parsed.events {
+        let mapped = period_map
+            .get(&event.summary.trim().to_lowercase())
+            .copied();
+        for o in event.occurrences(window)? {
+            let period = mapped
+                .unwrap_or_else(|| Period::for_start_hour(o.starts_at.with_timezone(&zone).hour()));
+            shifts.push(ImportedShift {
+                summary: event.summary.clone(),
+                starts_at: o.starts_at,
+                ends_at: o.ends_at,
+                period,
+                overlaps: Vec::new(),
+            });
+        }
+        if shifts.len() > MAX_IMPORT_SHIFTS {
+            return Err(AppError::BadRequest(format!(
+                "import would create more than {MAX_IMPORT_SHIFTS} shifts"
+            )));
+        }
+    }
+    shifts.sort_by_key(|s| s.starts_at);
+
+    if let (Some(first), Some(last_end)) = (shifts.first(), shifts.iter().map(|s| s.ends_at).max())
+    {
+        // `list_shifts` filters on start time; the margin catches existing
+        // shifts that began the day before and run into the import range.
+        let existing = state
+            .repo
+            .list_shifts(
+                schedule_id,
+                first.starts_at - chrono::Duration::days(1),
+                last_end,
+            )
+            .await?;
+        for s in shifts.iter_mut() {
+            s.overlaps = existing
+                .iter()
+                .filter(|e| e.starts_at < s.ends_at && e.ends_at > s.starts_at)
+                .map(|e| e.id)
+                .collect();
+        }
+    }
+
+    let mut created = Vec::new();
+    if !req.dry_run {
+        let schedule_tasks = state.repo.list_schedule_tasks(schedule_id).await?;
+        let mut uow = UnitOfWork::new();
+        for s in &shifts {
+            uow.create_shift(NewShift {
+                schedule_id,
+                starts_at: s.starts_at,
+                ends_at: s.ends_at,
+                period: s.period,
+                capacity: 1,
+                assigned_user_id: None,
+                template_id: None,
+                template_week: None,
+                tasks: checklist::for_shift(
+                    &schedule_tasks,
+                    s.starts_at,
+                    s.ends_at,
+                    s.period,
+                    zone,
+                )?,
+                created_by: au.id,
+            });
+        }
+        created = state.repo.commit(uow).await?.shifts;
+    }
+
+    let status = if req.dry_run {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((
+        status,
+        Json(ImportIcsResponse {
+            dry_run: req.dry_run,
+            shifts,
+            created,
+            skipped: parsed.skipped,
+        }),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateShiftRequest {
+    starts_at: Option<DateTime<Utc>>,
+    ends_at: Option<DateTime<Utc>>,
+    period: Option<Period>,
+    capacity: Option<i16>,
+    /// Move the shift even if an assignee already has an overlapping shift
+    /// or is unavailable at the new time.
+    #[serde(default)]
+    force: bool,
+}
+
+async fn update_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Json(req): Json<UpdateShiftRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+
+    let capacity = req.capacity.unwrap_or(shift.capacity);
+    if usize::try_from(capacity).unwrap_or(0) < shift.assignees.len() {
+        return Err(AppError::BadRequest(
+            "capacity is below the number of assignees".to_string(),
+        ));
+    }
+    let starts_at = req.starts_at.unwrap_or(shift.starts_at);
+    let ends_at = req.ends_at.unwrap_or(shift.ends_at);
+    if !req.force && (starts_at != shift.starts_at || ends_at != shift.ends_at) {
+        let moved = Shift {
+            starts_at,
+            ends_at,
+            ..shift.clone()
+        };
+        for a in &shift.assignees {
+            ensure_no_overlaps(&state, a.user_id, &moved).await?;
+            ensure_available(&state, a.user_id, &moved).await?;
+        }
+    }
+    let shift = state
+        .repo
+        .update_shift(
+            shift_id,
+            UpdateShift {
+                starts_at,
+                ends_at,
+                period: req.period.unwrap_or(shift.period),
+                capacity,
+            },
+        )
+        .await?;
+    Ok(Json(shift))
+}
+
+async fn delete_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+
+    state.repo.delete_shift(shift_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct AssignShiftRequest {
+    assigned_user_id: Option<Uuid>,
+    /// Admin-only: assign even if the user already has an overlapping shift
+    /// or is unavailable.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Kept for clients written before shifts could have several people; new
+/// clients use `/assignees`. Admins on a single-place shift make the user its
+/// only assignee; everyone else only takes a free place, as `/assignees` does.
+async fn assign_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Json(req): Json<AssignShiftRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+    let target = req.assigned_user_id.unwrap_or(au.id);
+
+    // Only admins can assign other users or override conflicts.
+    let is_admin = au.is_superadmin || role == ScheduleRole::Admin;
+    if (target != au.id || req.force) && !is_admin {
+        return Err(AppError::Forbidden);
+    }
+    if state
+        .repo
+        .get_schedule_role(shift.schedule_id, target)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "user is not a member of this schedule".to_string(),
+        ));
+    }
+
+    if !req.force {
+        ensure_no_overlaps(&state, target, &shift).await?;
+        ensure_available(&state, target, &shift).await?;
+    }
+
+    if is_admin && shift.capacity <= 1 {
+        state.repo.assign_shift(shift_id, Some(target)).await?;
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    // Members picking up shifts themselves follow the schedule's claim policy.
+    if !is_admin {
+        let schedule = state
+            .repo
+            .get_schedule(shift.schedule_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        if schedule.claim_policy == ClaimPolicy::Approval {
+            return Err(AppError::Forbidden);
+        }
+    }
+    if shift.is_assigned(target) {
+        return Err(AppError::Conflict(
+            "user is already assigned to this shift".to_string(),
+        ));
+    }
+    take_open_shift(&state, shift_id, target).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct AddAssigneeRequest {
+    user_id: Option<Uuid>,
+    /// Admin-only: make this assignee the shift's lead.
+    #[serde(default)]
+    lead: bool,
+    /// Admin-only, as for `assign`.
+    #[serde(default)]
+    force: bool,
+}
+
+/// Takes one of the shift's free places, or with `lead` on someone already
+/// assigned, only moves the lead to them.
+async fn add_shift_assignee(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Json(req): Json<AddAssigneeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+    let target = req.user_id.unwrap_or(au.id);
+    let is_admin = au.is_superadmin || role == ScheduleRole::Admin;
+    if (target != au.id || req.force || req.lead) && !is_admin {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut uow = UnitOfWork::new();
+    if shift.is_assigned(target) {
+        if !req.lead {
+            return Err(AppError::Conflict(
+                "user is already assigned to this shift".to_string(),
+            ));
+        }
+    } else {
+        if state
+            .repo
+            .get_schedule_role(shift.schedule_id, target)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::BadRequest(
+                "user is not a member of this schedule".to_string(),
+            ));
+        }
+        if !req.force {
+            ensure_no_overlaps(&state, target, &shift).await?;
+            ensure_available(&state, target, &shift).await?;
+        }
+        if !is_admin {
+            let schedule = state
+                .repo
+                .get_schedule(shift.schedule_id)
+                .await?
+                .ok_or(AppError::NotFound)?;
+            if schedule.claim_policy == ClaimPolicy::Approval {
+                return Err(AppError::Forbidden);
+            }
+        }
+        uow.reassign_shift(shift_id, None, Some(target));
+    }
+    if req.lead {
+        uow.set_shift_lead(shift_id, target);
+    }
+    state.repo.commit(uow).await?;
+
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(shift))
+}
+
+/// Members may take themselves off a shift; admins anyone.
+async fn remove_shift_assignee(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+    if user_id != au.id && !au.is_superadmin && role != ScheduleRole::Admin {
+        return Err(AppError::Forbidden);
+    }
+    if !shift.is_assigned(user_id) {
+        return Err(AppError::NotFound);
+    }
+
+    let mut uow = UnitOfWork::new();
+    uow.reassign_shift(shift_id, Some(user_id), None);
+    state.repo.commit(uow).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn ensure_no_overlaps(state: &AppState, user_id: Uuid, shift: &Shift) -> AppResult<()> {
+    let clashes = state
+        .repo
+        .list_overlapping_shifts(user_id, shift.starts_at, shift.ends_at, shift.id)
+        .await?;
+    if !clashes.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "user already assigned to overlapping shifts: {}",
+            describe_shifts(&clashes)
+        )));
+    }
+    Ok(())
+}
+
+fn describe_shifts(shifts: &[Shift]) -> String {
+    shifts
+        .iter()
+        .map(|s| {
+            format!(
+                "{} ({} {}..{})",
+                s.id,
+                s.period.as_str(),
+                s.starts_at.to_rfc3339(),
+                s.ends_at.to_rfc3339()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Gives `user_id` a free place on the shift, failing with a conflict if
+/// others filled it first.
+async fn take_open_shift(state: &AppState, shift_id: Uuid, user_id: Uuid) -> AppResult<()> {
+    let mut uow = UnitOfWork::new();
+    uow.reassign_shift(shift_id, None, Some(user_id));
+    state.repo.commit(uow).await.map_err(|e| match e {
+        AppError::Conflict(_) => AppError::Conflict("shift has already been taken".to_string()),
+        e => e,
+    })?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenShiftsQuery {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenShift {
+    #[serde(flatten)]
+    shift: Shift,
+    schedule_name: String,
+    claim_policy: ClaimPolicy,
+}
+
+/// Days ahead the open shift board covers by default.
+const OPEN_SHIFTS_DEFAULT_DAYS: i64 = 28;
+
+/// Unassigned shifts across every schedule the caller belongs to.
+async fn list_open_shifts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<OpenShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let from = q.from.unwrap_or_else(Utc::now);
+    let to = match q.to {
+        Some(to) => to,
+        None => from
+            .checked_add_signed(chrono::Duration::days(OPEN_SHIFTS_DEFAULT_DAYS))
+            .ok_or_else(|| AppError::BadRequest("from is out of range".to_string()))?,
+    };
+    if to <= from {
+        return Err(AppError::BadRequest("to must be after from".to_string()));
+    }
+
+    let schedules: HashMap<Uuid, _> = state
+        .repo
+        .list_schedules_for_user(au.id, false)
+        .await?
+        .into_iter()
+        .map(|s| (s.schedule.id, s.schedule))
+        .collect();
+    let shifts = state.repo.list_open_shifts(au.id, from, to).await?;
+    let out: Vec<OpenShift> = shifts
+        .into_iter()
+        .filter_map(|shift| {
+            let schedule = schedules.get(&shift.schedule_id)?;
+            Some(OpenShift {
+                schedule_name: schedule.name.clone(),
+                claim_policy: schedule.claim_policy,
+                shift,
+            })
+        })
+        .collect();
+    Ok(Json(out))
+}
+
+/// Takes an open shift for the caller, or under the `approval` policy files
+/// a claim for an admin to decide (202).
+async fn claim_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<axum::response::Response> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+    if shift.free_places() == 0 || shift.is_assigned(au.id) {
+        return Err(AppError::Conflict(
+            "shift has already been taken".to_string(),
+        ));
+    }
+    ensure_no_overlaps(&state, au.id, &shift).await?;
+    ensure_available(&state, au.id, &shift).await?;
+
+    let schedule = state
+        .repo
+        .get_schedule(shift.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let is_admin = au.is_superadmin || role == ScheduleRole::Admin;
+    if schedule.claim_policy == ClaimPolicy::Approval && !is_admin {
+        let claim = state
+            .repo
+            .create_claim(NewClaim {
+                schedule_id: shift.schedule_id,
+                shift_id,
+                user_id: au.id,
+            })
+            .await?;
+        return Ok((StatusCode::ACCEPTED, Json(claim)).into_response());
+    }
+
+    take_open_shift(&state, shift_id, au.id).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(shift).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ListClaimsQuery {
+    status: Option<ClaimStatus>,
+}
+
+/// Admins see every claim in the schedule; members only their own.
+async fn list_claims(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListClaimsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let role = require_member_to_read(&state, &au, schedule_id).await?;
+    let user_id = if au.is_superadmin || role == ScheduleRole::Admin {
+        None
+    } else {
+        Some(au.id)
+    };
+    let claims = state
+        .repo
+        .list_claims(schedule_id, user_id, q.status)
+        .await?;
+    Ok(Json(claims))
+}
+
+async fn load_pending_claim(
+    state: &AppState,
+    au: &AuthUser,
+    claim_id: Uuid,
+) -> AppResult<ShiftClaim> {
+    let claim = state
+        .repo
+        .get_claim(claim_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_admin_or_superadmin(state, au, claim.schedule_id).await?;
+    if claim.status != ClaimStatus::Pending {
+        return Err(AppError::Conflict(format!(
+            "claim is already {}",
+            claim.status.as_str()
+        )));
+    }
+    Ok(claim)
+}
+
+/// Gives the claimant a place on the shift and, once that fills the shift,
+/// rejects the other pending claims on it.
+async fn approve_claim(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(claim_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let claim = load_pending_claim(&state, &au, claim_id).await?;
+    let shift = state
+        .repo
+        .get_shift(claim.shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if state
+        .repo
+        .get_schedule_role(claim.schedule_id, claim.user_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::Conflict(
+            "claimant is no longer a member of this schedule".to_string(),
+        ));
+    }
+    ensure_no_overlaps(&state, claim.user_id, &shift).await?;
+    ensure_available(&state, claim.user_id, &shift).await?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_claim_status(claim.id, ClaimStatus::Approved, au.id);
+    if shift.free_places() <= 1 {
+        uow.reject_pending_claims(claim.shift_id, au.id);
+    }
+    uow.reassign_shift(claim.shift_id, None, Some(claim.user_id));
+    state.repo.commit(uow).await?;
+
+    let claim = state
+        .repo
+        .get_claim(claim_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(claim))
+}
+
+async fn reject_claim(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(claim_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let claim = load_pending_claim(&state, &au, claim_id).await?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_claim_status(claim.id, ClaimStatus::Rejected, au.id);
+    state.repo.commit(uow).await?;
+
+    let claim = state
+        .repo
+        .get_claim(claim_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(claim))
+}
+
+/// Rejects assigning `user_id` to a shift that falls in their unavailability.
+async fn ensure_available(state: &AppState, user_id: Uuid, shift: &Shift) -> AppResult<()> {
+    let schedule = state
+        .repo
+        .get_schedule(shift.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let weekly = state
+        .repo
+        .list_weekly_unavailability(shift.schedule_id, Some(user_id))
+        .await?;
+    let exceptions = state
+        .repo
+        .list_availability_exceptions(shift.schedule_id, Some(user_id))
+        .await?;
+    let leave = state
+        .repo
+        .list_leave_requests(
+            shift.schedule_id,
+            Some(user_id),
+            Some(LeaveStatus::Approved),
+        )
+        .await?;
+    let reason = availability::leave_reason(shift, zone, &leave)
+        .or_else(|| availability::unavailability_reason(shift, zone, &weekly, &exceptions));
+    if let Some(reason) = reason {
+        return Err(AppError::Conflict(format!(
+            "user is unavailable for this shift: {reason}"
+        )));
+    }
+    Ok(())
+}
+
+/// Members manage their own availability; admins anyone's in the schedule.
+async fn require_self_or_admin(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    user_id: Uuid,
+) -> AppResult<()> {
+    let role = require_member_or_superadmin(state, au, schedule_id).await?;
+    if user_id != au.id && !au.is_superadmin && role != ScheduleRole::Admin {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+fn clean_note(note: Option<String>) -> Option<String> {
+    note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+fn check_dow(dow: i16) -> AppResult<()> {
+    if !(0..=6).contains(&dow) {
+        return Err(AppError::BadRequest("dow must be 0..6".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct AvailabilityQuery {
+    user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct AvailabilityResponse {
+    weekly: Vec<WeeklyUnavailability>,
+    exceptions: Vec<AvailabilityException>,
+}
+
+/// Without `user_id`, admins get every member's entries and members their own.
+async fn get_availability(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<AvailabilityQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let role = require_member_to_read(&state, &au, schedule_id).await?;
+    let is_admin = au.is_superadmin || role == ScheduleRole::Admin;
+    let user_id = match q.user_id {
+        Some(user_id) if user_id != au.id && !is_admin => return Err(AppError::Forbidden),
+        Some(user_id) => Some(user_id),
+        None => (!is_admin).then_some(au.id),
+    };
+
+    let weekly = state
+        .repo
+        .list_weekly_unavailability(schedule_id, user_id)
+        .await?;
+    let exceptions = state
+        .repo
+        .list_availability_exceptions(schedule_id, user_id)
+        .await?;
+    Ok(Json(AvailabilityResponse { weekly, exceptions }))
+}
+
+/// Resolves who an availability entry is for, defaulting to the caller.
+async fn availability_target(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    user_id: Option<Uuid>,
+) -> AppResult<Uuid> {
+    let user_id = user_id.unwrap_or(au.id);
+    require_self_or_admin(state, au, schedule_id, user_id).await?;
+    if state
+        .repo
+        .get_schedule_role(schedule_id, user_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "user is not a member of this schedule".to_string(),
+        ));
+    }
+    Ok(user_id)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateWeeklyUnavailabilityRequest {
+    /// Defaults to the caller.
+    user_id: Option<Uuid>,
+    dow: i16, // 0=Mon..6=Sun
+    period: Period,
+    note: Option<String>,
+}
+
+async fn create_weekly_unavailability(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateWeeklyUnavailabilityRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let user_id = availability_target(&state, &au, schedule_id, req.user_id).await?;
+    check_dow(req.dow)?;
+
+    let rule = state
+        .repo
+        .create_weekly_unavailability(NewWeeklyUnavailability {
+            schedule_id,
+            user_id,
+            dow: req.dow,
+            period: req.period,
+            note: clean_note(req.note),
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+async fn load_weekly_unavailability(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    rule_id: Uuid,
+) -> AppResult<WeeklyUnavailability> {
+    let rule = state
+        .repo
+        .get_weekly_unavailability(rule_id)
+        .await?
+        .filter(|r| r.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)?;
+    require_self_or_admin(state, au, schedule_id, rule.user_id).await?;
+    Ok(rule)
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateWeeklyUnavailabilityRequest {
+    dow: Option<i16>,
+    period: Option<Period>,
+    note: Option<String>,
+}
+
+async fn update_weekly_unavailability(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, rule_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateWeeklyUnavailabilityRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let rule = load_weekly_unavailability(&state, &au, schedule_id, rule_id).await?;
+    let dow = req.dow.unwrap_or(rule.dow);
+    check_dow(dow)?;
+
+    let rule = state
+        .repo
+        .update_weekly_unavailability(
+            rule_id,
+            UpdateWeeklyUnavailability {
+                dow,
+                period: req.period.unwrap_or(rule.period),
+                note: match req.note {
+                    Some(note) => clean_note(Some(note)),
+                    None => rule.note,
+                },
+            },
+        )
+        .await?;
+    Ok(Json(rule))
+}
+
+async fn delete_weekly_unavailability(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, rule_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    load_weekly_unavailability(&state, &au, schedule_id, rule_id).await?;
+
+    state.repo.delete_weekly_unavailability(rule_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateAvailabilityExceptionRequest {
+    /// Defaults to the caller.
+    user_id: Option<Uuid>,
+    starts_on: NaiveDate,
+    ends_on: NaiveDate,
+    /// `false` (default) marks the member away; `true` available despite
+    /// their weekly pattern.
+    #[serde(default)]
+    available: bool,
+    note: Option<String>,
+}
+
+async fn create_availability_exception(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateAvailabilityExceptionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let user_id = availability_target(&state, &au, schedule_id, req.user_id).await?;
+
+    let exception = state
+        .repo
+        .create_availability_exception(NewAvailabilityException {
+            schedule_id,
+            user_id,
+            starts_on: req.starts_on,
+            ends_on: req.ends_on,
+            available: req.available,
+            note: clean_note(req.note),
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(exception)))
+}
+
+async fn load_availability_exception(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    exception_id: Uuid,
+) -> AppResult<AvailabilityException> {
+    let exception = state
+        .repo
+        .get_availability_exception(exception_id)
+        .await?
+        .filter(|e| e.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)?;
+    require_self_or_admin(state, au, schedule_id, exception.user_id).await?;
+    Ok(exception)
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateAvailabilityExceptionRequest {
+    starts_on: Option<NaiveDate>,
+    ends_on: Option<NaiveDate>,
+    available: Option<bool>,
+    note: Option<String>,
+}
+
+async fn update_availability_exception(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, exception_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateAvailabilityExceptionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let exception = load_availability_exception(&state, &au, schedule_id, exception_id).await?;
+
+    let exception = state
+        .repo
+        .update_availability_exception(
+            exception_id,
+            UpdateAvailabilityException {
+                starts_on: req.starts_on.unwrap_or(exception.starts_on),
+                ends_on: req.ends_on.unwrap_or(exception.ends_on),
+                available: req.available.unwrap_or(exception.available),
+                note: match req.note {
+                    Some(note) => clean_note(Some(note)),
+                    None => exception.note,
+                },
+            },
+        )
+        .await?;
+    Ok(Json(exception))
+}
+
+async fn delete_availability_exception(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, exception_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    load_availability_exception(&state, &au, schedule_id, exception_id).await?;
+
+    state
+        .repo
+        .delete_availability_exception(exception_id)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateLeaveRequest {
+    starts_on: NaiveDate,
+    ends_on: NaiveDate,
+    reason: String,
+}
+
+/// Members request leave for themselves; an admin decides it.
+async fn create_leave_request(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateLeaveRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    if state
+        .repo
+        .get_schedule_role(schedule_id, au.id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::Forbidden);
+    }
+    require_active_schedule(&state, schedule_id).await?;
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::BadRequest("reason is required".to_string()));
+    }
+
+    let leave = state
+        .repo
+        .create_leave_request(NewLeaveRequest {
+            schedule_id,
+            user_id: au.id,
+            starts_on: req.starts_on,
+            ends_on: req.ends_on,
+            reason: reason.to_string(),
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(leave)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListLeaveQuery {
+    status: Option<LeaveStatus>,
+}
+
+/// Admins see every leave request in the schedule; members only their own.
+async fn list_leave_requests(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListLeaveQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let role = require_member_to_read(&state, &au, schedule_id).await?;
+    let user_id = (!au.is_superadmin && role != ScheduleRole::Admin).then_some(au.id);
+    let leave = state
+        .repo
+        .list_leave_requests(schedule_id, user_id, q.status)
+        .await?;
+    Ok(Json(leave))
+}
+
+async fn load_leave(state: &AppState, leave_id: Uuid) -> AppResult<LeaveRequest> {
+    state
+        .repo
+        .get_leave_request(leave_id)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+fn ensure_leave_pending(leave: &LeaveRequest) -> AppResult<()> {
+    if leave.status != LeaveStatus::Pending {
+        return Err(AppError::Conflict(format!(
+            "leave request is already {}",
+            leave.status.as_str()
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DecideLeaveRequest {
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ApproveLeaveResponse {
+    leave: LeaveRequest,
+    /// Shifts the member was taken off because they fall in the leave.
+    unassigned_shift_ids: Vec<Uuid>,
+}
+
+/// Approves leave and, in the same transaction, unassigns the member from
+/// every shift of this schedule that overlaps it, noting why on each shift.
+async fn approve_leave(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(leave_id): Path<Uuid>,
+    body: Option<Json<DecideLeaveRequest>>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let leave = load_leave(&state, leave_id).await?;
+    require_admin_or_superadmin(&state, &au, leave.schedule_id).await?;
+    ensure_leave_pending(&leave)?;
+    let note = clean_note(body.and_then(|Json(b)| b.note));
+
+    let schedule = state
+        .repo
+        .get_schedule(leave.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = tz::start_of_day(zone, leave.starts_on)?;
+    let day_after = leave
+        .ends_on
+        .succ_opt()
+        .ok_or_else(|| AppError::BadRequest("ends_on is out of range".to_string()))?;
+    let to = tz::start_of_day(zone, day_after)?;
+    let shifts: Vec<Shift> = state
+        .repo
+        .list_overlapping_shifts(leave.user_id, from, to, Uuid::nil())
+        .await?
+        .into_iter()
+        .filter(|s| s.schedule_id == leave.schedule_id)
+        .collect();
+
+    let mut uow = UnitOfWork::new();
+    uow.set_leave_status(leave.id, LeaveStatus::Approved, au.id, note);
+    for shift in &shifts {
+        uow.reassign_shift(shift.id, Some(leave.user_id), None);
+        uow.add_shift_comment(NewShiftComment {
+            shift_id: shift.id,
+            user_id: au.id,
+            body: format!(
+                "Unassigned: leave {} to {} approved ({})",
+                leave.starts_on, leave.ends_on, leave.reason
+            ),
+        });
+    }
+    state.repo.commit(uow).await?;
+
+    let leave = state
+        .repo
+        .get_leave_request(leave_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(ApproveLeaveResponse {
+        leave,
+        unassigned_shift_ids: shifts.iter().map(|s| s.id).collect(),
+    }))
+}
+
+async fn reject_leave(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(leave_id): Path<Uuid>,
+    body: Option<Json<DecideLeaveRequest>>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let leave = load_leave(&state, leave_id).await?;
+    require_admin_or_superadmin(&state, &au, leave.schedule_id).await?;
+    ensure_leave_pending(&leave)?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_leave_status(
+        leave.id,
+        LeaveStatus::Rejected,
+        au.id,
+        clean_note(body.and_then(|Json(b)| b.note)),
+    );
+    state.repo.commit(uow).await?;
+
+    let leave = state
+        .repo
+        .get_leave_request(leave_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(leave))
+}
+
+/// Only the requester can withdraw their own pending request.
+async fn cancel_leave(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(leave_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let leave = load_leave(&state, leave_id).await?;
+    if leave.user_id != au.id {
+        return Err(AppError::Forbidden);
+    }
+    require_active_schedule(&state, leave.schedule_id).await?;
+    ensure_leave_pending(&leave)?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_leave_status(leave.id, LeaveStatus::Cancelled, au.id, None);
+    state.repo.commit(uow).await?;
+
+    let leave = state
+        .repo
+        .get_leave_request(leave_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(leave))
+}
+
+#[derive(Debug, Deserialize)]
+struct RotaPreviewRequest {
+    /// Same formats as `list_shifts`.
+    from: String,
+    to: String,
+    #[serde(default = "default_min_rest_hours")]
+    min_rest_hours: i64,
+    /// Roles eligible for assignment. Defaults to every member.
+    roles: Option<Vec<ScheduleRole>>,
+}
+
+fn default_min_rest_hours() -> i64 {
+    rota::DEFAULT_MIN_REST_HOURS
+}
+
+fn check_min_rest_hours(hours: i64) -> AppResult<chrono::Duration> {
+    let out_of_range = || {
+        AppError::BadRequest(format!(
+            "min_rest_hours must be 0..{}",
+            rota::MAX_MIN_REST_HOURS
+        ))
+    };
+    if !(0..=rota::MAX_MIN_REST_HOURS).contains(&hours) {
+        return Err(out_of_range());
+    }
+    chrono::Duration::try_hours(hours).ok_or_else(out_of_range)
+}
+
+/// `[starts_at - min_rest, ends_at + min_rest)`, the span another shift must
+/// stay out of.
+fn rest_window(
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    min_rest: chrono::Duration,
+) -> AppResult<(DateTime<Utc>, DateTime<Utc>)> {
+    let out_of_range = || AppError::BadRequest("shift times are out of range".to_string());
+    Ok((
+        starts_at
+            .checked_sub_signed(min_rest)
+            .ok_or_else(out_of_range)?,
+        ends_at
+            .checked_add_signed(min_rest)
+            .ok_or_else(out_of_range)?,
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct RotaChange {
+    shift_id: Uuid,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    period: Period,
+    user_id: Uuid,
+}
+
+#[derive(Debug, Serialize)]
+struct RotaMemberLoad {
+    user_id: Uuid,
+    email: String,
+    before: rota::Load,
+    after: rota::Load,
+}
+
+#[derive(Debug, Serialize)]
+struct RotaPreview {
+    assignments: Vec<RotaChange>,
+    unfilled: Vec<rota::Unfilled>,
+    members: Vec<RotaMemberLoad>,
+}
+
+/// Proposes assignees for the unassigned shifts in a range without writing
+/// anything. Post `assignments` to `commit_rota` to apply it.
+async fn preview_rota(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<RotaPreviewRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let min_rest = check_min_rest_hours(req.min_rest_hours)?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = parse_range_bound(&req.from, "from", zone, false)?;
+    let to = parse_range_bound(&req.to, "to", zone, true)?;
+
+    let shifts = state.repo.list_shifts(schedule_id, from, to).await?;
+    let open: Vec<Shift> = shifts
+        .iter()
+        .filter(|s| s.free_places() > 0)
+        .cloned()
+        .collect();
+    let (window_start, window_end) = rest_window(
+        open.iter().map(|s| s.starts_at).min().unwrap_or(from),
+        open.iter().map(|s| s.ends_at).max().unwrap_or(to),
+        min_rest,
+    )?;
+
+    let mut users = Vec::new();
+    let mut members = Vec::new();
+    for (user, role) in state.repo.list_schedule_members(schedule_id).await? {
+        if req.roles.as_ref().is_some_and(|r| !r.contains(&role)) {
+            continue;
+        }
+        let busy = state
+            .repo
+            .list_overlapping_shifts(user.id, window_start, window_end, Uuid::nil())
+            .await?
+            .iter()
+            .map(|s| (s.starts_at, s.ends_at))
+            .collect();
+        let mut load = rota::Load::default();
+        for shift in shifts.iter().filter(|s| s.is_assigned(user.id)) {
+            load.add(shift);
+        }
+        members.push(rota::Member {
+            user_id: user.id,
+            busy,
+            load,
+        });
+        users.push((user, load));
+    }
+
+    let mut weekly: HashMap<Uuid, Vec<WeeklyUnavailability>> = HashMap::new();
+    for w in state
+        .repo
+        .list_weekly_unavailability(schedule_id, None)
+        .await?
+    {
+        weekly.entry(w.user_id).or_default().push(w);
+    }
+    let mut exceptions: HashMap<Uuid, Vec<AvailabilityException>> = HashMap::new();
+    for e in state
+        .repo
+        .list_availability_exceptions(schedule_id, None)
+        .await?
+    {
+        exceptions.entry(e.user_id).or_default().push(e);
+    }
+    let mut leave: HashMap<Uuid, Vec<LeaveRequest>> = HashMap::new();
+    for l in state
+        .repo
+        .list_leave_requests(schedule_id, None, Some(LeaveStatus::Approved))
+        .await?
+    {
+        leave.entry(l.user_id).or_default().push(l);
+    }
+    let plan = rota::propose(&open, members, min_rest, |user_id, shift| {
+        availability::leave_reason(shift, zone, leave.get(&user_id).map_or(&[], Vec::as_slice))
+            .or_else(|| {
+                availability::unavailability_reason(
+                    shift,
+                    zone,
+                    weekly.get(&user_id).map_or(&[], Vec::as_slice),
+                    exceptions.get(&user_id).map_or(&[], Vec::as_slice),
+                )
+            })
+    });
+
+    let by_id: HashMap<Uuid, &Shift> = open.iter().map(|s| (s.id, s)).collect();
+    let assignments = plan
+        .assignments
+        .iter()
+        .map(|a| {
+            let shift = by_id[&a.shift_id];
+            RotaChange {
+                shift_id: shift.id,
+                starts_at: shift.starts_at,
+                ends_at: shift.ends_at,
+                period: shift.period,
+                user_id: a.user_id,
+            }
+        })
+        .collect();
+    let members = users
+        .into_iter()
+        .zip(plan.loads)
+        .map(|((user, before), (_, after))| RotaMemberLoad {
+            user_id: user.id,
+            email: user.email,
+            before,
+            after,
+        })
+        .collect();
+    Ok(Json(RotaPreview {
+        assignments,
+        unfilled: plan.unfilled,
+        members,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitRotaRequest {
+    assignments: Vec<rota::Assignment>,
+    #[serde(default = "default_min_rest_hours")]
+    min_rest_hours: i64,
+}
+
+/// Applies a previewed rota in one transaction. Every assignment is checked
+/// again the way `preview_rota` picks them: against the member's other
+/// shifts, the rest of the batch, `min_rest_hours` and their availability.
+/// Fails with a conflict, changing nothing, if any check fails or any of the
+/// shifts filled up in the meantime.
+async fn commit_rota(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CommitRotaRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    let min_rest = check_min_rest_hours(req.min_rest_hours)?;
+
+    let mut uow = UnitOfWork::new();
+    let mut shift_ids = Vec::with_capacity(req.assignments.len());
+    let mut batch: Vec<(Uuid, Shift)> = Vec::with_capacity(req.assignments.len());
+    for a in &req.assignments {
+        let shift = state
+            .repo
+            .get_shift(a.shift_id)
+            .await?
+            .filter(|s| s.schedule_id == schedule_id)
+            .ok_or(AppError::NotFound)?;
+        if state
+            .repo
+            .get_schedule_role(schedule_id, a.user_id)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::BadRequest(
+                "user is not a member of this schedule".to_string(),
+            ));
+        }
+        ensure_no_overlaps(&state, a.user_id, &shift).await?;
+        ensure_available(&state, a.user_id, &shift).await?;
+        let (rest_from, rest_to) = rest_window(shift.starts_at, shift.ends_at, min_rest)?;
+        let mut clashes = state
+            .repo
+            .list_overlapping_shifts(a.user_id, rest_from, rest_to, shift.id)
+            .await?;
+        clashes.extend(
+            batch
+                .iter()
+                .filter(|(user_id, other)| {
+                    *user_id == a.user_id
+                        && rota::too_close(other, shift.starts_at, shift.ends_at, min_rest)
+                })
+                .map(|(_, other)| other.clone()),
+        );
+        if !clashes.is_empty() {
+            return Err(AppError::Conflict(format!(
+                "user {} would have less than {} hours rest around shift {}: {}",
+                a.user_id,
+                req.min_rest_hours,
+                shift.id,
+                describe_shifts(&clashes)
+            )));
+        }
+        uow.reassign_shift(shift.id, None, Some(a.user_id));
+        if !shift_ids.contains(&shift.id) {
+            shift_ids.push(shift.id);
+        }
+        batch.push((a.user_id, shift));
+    }
+    state.repo.commit(uow).await?;
+
+    let mut shifts = Vec::with_capacity(shift_ids.len());
+    for shift_id in shift_ids {
+        if let Some(shift) = state.repo.get_shift(shift_id).await? {
+            shifts.push(shift);
+        }
+    }
+    Ok(Json(shifts))
+}
+
+fn check_required(required: i16) -> AppResult<()> {
+    if required < 1 {
+        return Err(AppError::BadRequest(
+            "required must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+async fn list_coverage_rules(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let rules = state.repo.list_coverage_rules(schedule_id).await?;
+    Ok(Json(rules))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateCoverageRuleRequest {
+    dow: i16, // 0=Mon..6=Sun
+    period: Period,
+    #[serde(default = "default_required")]
+    required: i16,
+}
+
+fn default_required() -> i16 {
+    1
+}
+
+async fn create_coverage_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateCoverageRuleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    check_dow(req.dow)?;
+    check_required(req.required)?;
+
+    let rule = state
+        .repo
+        .create_coverage_rule(NewCoverageRule {
+            schedule_id,
+            dow: req.dow,
+            period: req.period,
+            required: req.required,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+async fn load_coverage_rule(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    rule_id: Uuid,
+) -> AppResult<CoverageRule> {
+    require_admin_or_superadmin(state, au, schedule_id).await?;
+    state
+        .repo
+        .get_coverage_rule(rule_id)
+        .await?
+        .filter(|r| r.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateCoverageRuleRequest {
+    dow: Option<i16>,
+    period: Option<Period>,
+    required: Option<i16>,
+}
+
+async fn update_coverage_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, rule_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateCoverageRuleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let rule = load_coverage_rule(&state, &au, schedule_id, rule_id).await?;
+    let dow = req.dow.unwrap_or(rule.dow);
+    let required = req.required.unwrap_or(rule.required);
+    check_dow(dow)?;
+    check_required(required)?;
+
+    let rule = state
+        .repo
+        .update_coverage_rule(
+            rule_id,
+            UpdateCoverageRule {
+                dow,
+                period: req.period.unwrap_or(rule.period),
+                required,
+            },
+        )
+        .await?;
+    Ok(Json(rule))
+}
+
+async fn delete_coverage_rule(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, rule_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    load_coverage_rule(&state, &au, schedule_id, rule_id).await?;
+
+    state.repo.delete_coverage_rule(rule_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Days `list_gaps` covers when no `to` is given.
+const GAPS_DEFAULT_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize)]
+struct GapsQuery {
+    /// Local dates in the schedule's zone, inclusive. Default: the next week
+    /// starting today.
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Days and periods where the coverage rules ask for more assigned members
+/// than the shifts have.
+async fn list_gaps(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<GapsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = q
+        .from
+        .unwrap_or_else(|| Utc::now().with_timezone(&zone).date_naive());
+    let out_of_range = || AppError::BadRequest("range is out of bounds".to_string());
+    let to = match q.to {
+        Some(to) => to,
+        None => from
+            .checked_add_days(chrono::Days::new(GAPS_DEFAULT_DAYS as u64 - 1))
+            .ok_or_else(out_of_range)?,
+    };
+    coverage::check_range(from, to)?;
+    let day_after = to.succ_opt().ok_or_else(out_of_range)?;
+
+    let rules = state.repo.list_coverage_rules(schedule_id).await?;
+    let shifts = state
+        .repo
+        .list_shifts(
+            schedule_id,
+            tz::start_of_day(zone, from)?,
+            tz::start_of_day(zone, day_after)?,
+        )
+        .await?;
+    let gaps = coverage::find_gaps(&rules, &shifts, zone, from, to)?;
+    Ok(Json(gaps))
+}
+
+/// Only the shift's assignees check in, from shortly before it starts until
+/// it ends.
+async fn check_in_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if !shift.is_assigned(au.id) {
+        return Err(AppError::Forbidden);
+    }
+    require_active_schedule(&state, shift.schedule_id).await?;
+
+    let now = Utc::now();
+    let early = attendance::EARLY_CHECK_IN_MINUTES;
+    if now < shift.starts_at - chrono::Duration::minutes(early) {
+        return Err(AppError::BadRequest(format!(
+            "check-in opens {early} minutes before the shift starts"
+        )));
+    }
+    if now >= shift.ends_at {
+        return Err(AppError::BadRequest(
+            "shift has ended; ask an admin to correct its attendance".to_string(),
+        ));
+    }
+    let entry = state.repo.check_in(shift_id, au.id, now).await?;
+    Ok(Json(entry))
+}
+
+async fn check_out_shift(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+
+    let entry = state.repo.check_out(shift_id, au.id, Utc::now()).await?;
+    Ok(Json(entry))
+}
+
+#[derive(Debug, Serialize)]
+struct ShiftAttendanceResponse {
+    #[serde(flatten)]
+    report: attendance::ShiftReport,
+    corrections: Vec<AttendanceCorrection>,
+}
+
+async fn get_shift_attendance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_member_to_read(&state, &au, shift.schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(shift.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let entries = state.repo.list_attendance_for_shifts(&[shift_id]).await?;
+    let entries: Vec<_> = entries.iter().collect();
+    Ok(Json(ShiftAttendanceResponse {
+        report: attendance::shift_report(
+            &shift,
+            &entries,
+            schedule.check_in_grace_minutes,
+            Utc::now(),
+        ),
+        corrections: state.repo.list_attendance_corrections(shift_id).await?,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CorrectAttendanceRequest {
+    checked_in_at: Option<DateTime<Utc>>,
+    checked_out_at: Option<DateTime<Utc>>,
+    reason: String,
+}
+
+/// Admins set an assignee's actual times; fields left out keep their current
+/// value. Every correction is kept with the values it replaced.
+async fn correct_attendance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, user_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<CorrectAttendanceRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::BadRequest("reason is required".to_string()));
+    }
+
+    let current = state
+        .repo
+        .list_attendance_for_shifts(&[shift_id])
+        .await?
+        .into_iter()
+        .find(|e| e.user_id == user_id);
+    if current.is_none() && !shift.is_assigned(user_id) {
+        return Err(AppError::BadRequest(
+            "user is not assigned to this shift".to_string(),
+        ));
+    }
+
+    let mut uow = UnitOfWork::new();
+    uow.correct_attendance(NewAttendanceCorrection {
+        shift_id,
+        user_id,
+        checked_in_at: req
+            .checked_in_at
+            .or(current.as_ref().and_then(|e| e.checked_in_at)),
+        checked_out_at: req
+            .checked_out_at
+            .or(current.as_ref().and_then(|e| e.checked_out_at)),
+        corrected_by: au.id,
+        reason: reason.to_string(),
+    });
+    let correction = state
+        .repo
+        .commit(uow)
+        .await?
+        .attendance_corrections
+        .pop()
+        .ok_or(AppError::Internal)?;
+    Ok(Json(correction))
+}
+
+/// Planned against actual times for shifts starting in the range, with the
+/// ones nobody checked in to marked missed.
+async fn get_attendance_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_to_read(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = parse_range_bound(&q.from, "from", zone, false)?;
+    let to = parse_range_bound(&q.to, "to", zone, true)?;
+
+    let shifts = state.repo.list_shifts(schedule_id, from, to).await?;
+    let ids: Vec<Uuid> = shifts.iter().map(|s| s.id).collect();
+    let entries = state.repo.list_attendance_for_shifts(&ids).await?;
+    Ok(Json(attendance::build(
+        from,
+        to,
+        &shifts,
+        &entries,
+        schedule.check_in_grace_minutes,
+        Utc::now(),
+    )))
+}
+
+async fn list_schedule_tasks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    let tasks = state.repo.list_schedule_tasks(schedule_id).await?;
+    Ok(Json(tasks))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateScheduleTaskRequest {
+    title: String,
+    /// HH:MM in the schedule's zone; only shifts covering it get the task.
+    due_time: Option<String>,
+    /// Only shifts in this period get the task.
+    period: Option<Period>,
+}
+
+/// Adds an item every shift created from now on copies onto its checklist.
+async fn create_schedule_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateScheduleTaskRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    checklist::check_title(&req.title)?;
+    let due_time = req
+        .due_time
+        .as_deref()
+        .map(|t| checklist::parse_time(t, "due_time"))
+        .transpose()?;
+
+    let task = state
+        .repo
+        .create_schedule_task(NewScheduleTask {
+            schedule_id,
+            title: req.title.trim().to_string(),
+            due_time,
+            period: req.period,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(task)))
+}
+
+async fn load_schedule_task(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    task_id: Uuid,
+) -> AppResult<ScheduleTask> {
+    require_admin_or_superadmin(state, au, schedule_id).await?;
+    state
+        .repo
+        .get_schedule_task(task_id)
+        .await?
+        .filter(|t| t.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateScheduleTaskRequest {
+    title: Option<String>,
+    /// An empty string clears the time.
+    due_time: Option<String>,
+    /// `null` clears the period.
+    #[serde(default, deserialize_with = "present")]
+    period: Option<Option<Period>>,
+}
+
+/// Tells a field sent as `null` (`Some(None)`) from one left out (`None`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Changes apply to shifts created afterwards; existing checklists keep the
+/// copy they were given.
+async fn update_schedule_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, task_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateScheduleTaskRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let task = load_schedule_task(&state, &au, schedule_id, task_id).await?;
+    let title = req.title.unwrap_or(task.title);
+    checklist::check_title(&title)?;
+    let due_time = match req.due_time.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(t) => Some(checklist::parse_time(t, "due_time")?),
+        None => task.due_time,
+    };
+
+    let task = state
+        .repo
+        .update_schedule_task(
+            task_id,
+            UpdateScheduleTask {
+                title: title.trim().to_string(),
+                due_time,
+                period: req.period.unwrap_or(task.period),
+            },
+        )
+        .await?;
+    Ok(Json(task))
+}
+
+async fn delete_schedule_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, task_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    load_schedule_task(&state, &au, schedule_id, task_id).await?;
+
+    state.repo.delete_schedule_task(task_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_shift_tasks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_member_to_read(&state, &au, shift.schedule_id).await?;
+    let tasks = state.repo.list_shift_tasks(shift_id).await?;
+    Ok(Json(tasks))
+}
+
+#[derive(Debug, Deserialize)]
+struct AddShiftTaskRequest {
+    title: String,
+    due_at: Option<DateTime<Utc>>,
+}
+
+/// A one-off item for this shift only, added to the end of its checklist.
+async fn add_shift_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Json(req): Json<AddShiftTaskRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+    checklist::check_title(&req.title)?;
+
+    let task = state
+        .repo
+        .add_shift_task(
+            shift_id,
+            NewShiftTask {
+                schedule_task_id: None,
+                title: req.title.trim().to_string(),
+                due_at: req.due_at,
+            },
+        )
+        .await?;
+    Ok((StatusCode::CREATED, Json(task)))
+}
+
+async fn load_shift_task(
+    state: &AppState,
+    shift_id: Uuid,
+    task_id: Uuid,
+) -> AppResult<(Shift, ShiftTask)> {
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let task = state
+        .repo
+        .get_shift_task(task_id)
+        .await?
+        .filter(|t| t.shift_id == shift_id)
+        .ok_or(AppError::NotFound)?;
+    Ok((shift, task))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateShiftTaskRequest {
+    done: bool,
+}
+
+/// The shift's assignees and schedule admins tick items off, or reopen them.
+async fn update_shift_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, task_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<UpdateShiftTaskRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let (shift, task) = load_shift_task(&state, shift_id, task_id).await?;
+    if shift.is_assigned(au.id) {
+        require_active_schedule(&state, shift.schedule_id).await?;
+    } else {
+        require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+    }
+    if req.done == task.done_at.is_some() {
+        return Ok(Json(task));
+    }
+
+    let task = state
+        .repo
+        .set_shift_task_done(task_id, req.done.then_some(au.id))
+        .await?;
+    Ok(Json(task))
+}
+
+async fn delete_shift_task(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, task_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let (shift, _) = load_shift_task(&state, shift_id, task_id).await?;
+    require_admin_or_superadmin(&state, &au, shift.schedule_id).await?;
+
+    state.repo.delete_shift_task(task_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Serialize)]
+struct IncompleteTask {
+    #[serde(flatten)]
+    task: ShiftTask,
+    shift_starts_at: DateTime<Utc>,
+    shift_ends_at: DateTime<Utc>,
+    period: Period,
+    assignee_ids: Vec<Uuid>,
+    /// Past its due time, or past the end of the shift if it has none.
+    overdue: bool,
+}
+
+/// Unfinished checklist items on shifts starting in the range.
+async fn list_incomplete_tasks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListShiftsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_to_read(&state, &au, schedule_id).await?;
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+    let from = parse_range_bound(&q.from, "from", zone, false)?;
+    let to = parse_range_bound(&q.to, "to", zone, true)?;
+
+    let tasks = state
+        .repo
+        .list_incomplete_tasks(schedule_id, from, to)
+        .await?;
+    let shifts: HashMap<Uuid, Shift> = state
+        .repo
+        .list_shifts(schedule_id, from, to)
+        .await?
+        .into_iter()
+        .map(|s| (s.id, s))
+        .collect();
+    let now = Utc::now();
+    let items: Vec<IncompleteTask> = tasks
+        .into_iter()
+        .filter_map(|task| {
+            let shift = shifts.get(&task.shift_id)?;
+            Some(IncompleteTask {
+                overdue: task.due_at.unwrap_or(shift.ends_at) <= now,
+                shift_starts_at: shift.starts_at,
+                shift_ends_at: shift.ends_at,
+                period: shift.period,
+                assignee_ids: shift.assignees.iter().map(|a| a.user_id).collect(),
+                task,
+            })
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+#[derive(Debug, Deserialize)]
+struct AddCommentRequest {
+    body: String,
+}
+
+async fn add_shift_comment(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Json(req): Json<AddCommentRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let role = require_member_or_superadmin(&state, &au, shift.schedule_id).await?;
+
+    // Only assigned user or admins can comment.
+    if !au.is_superadmin && role != ScheduleRole::Admin && !shift.is_assigned(au.id) {
+        return Err(AppError::Forbidden);
+    }
+    if req.body.trim().is_empty() {
+        return Err(AppError::BadRequest("comment body is required".to_string()));
+    }
+    let c = state
+        .repo
+        .add_shift_comment(NewShiftComment {
+            shift_id,
+            user_id: au.id,
+            body: req.body.trim().to_string(),
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(c)))
+}
+
+const DEFAULT_PAGE_LIMIT: i64 = 50;
+const MAX_PAGE_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+impl PageQuery {
+    fn resolve(&self) -> AppResult<(i64, i64)> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        let offset = self.offset.unwrap_or(0);
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(AppError::BadRequest(format!(
+                "limit must be 1..{MAX_PAGE_LIMIT}"
+            )));
+        }
+        if offset < 0 {
+            return Err(AppError::BadRequest("offset must be >= 0".to_string()));
+        }
+        Ok((limit, offset))
+    }
+}
+
+async fn list_shift_comments(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(shift_id): Path<Uuid>,
+    Query(q): Query<PageQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_member_to_read(&state, &au, shift.schedule_id).await?;
+
+    let (limit, offset) = q.resolve()?;
+    let comments = state
+        .repo
+        .list_shift_comments(shift_id, limit, offset)
+        .await?;
+    Ok(Json(comments))
+}
+
+/// Loads a comment and checks the caller is its author or a schedule admin.
+async fn require_comment_author_or_admin(
+    state: &AppState,
+    au: &AuthUser,
+    shift_id: Uuid,
+    comment_id: Uuid,
+) -> AppResult<()> {
+    let shift = state
+        .repo
+        .get_shift(shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let comment = state
+        .repo
+        .get_shift_comment(comment_id)
+        .await?
+        .filter(|c| c.shift_id == shift_id)
+        .ok_or(AppError::NotFound)?;
+    let role = require_member_or_superadmin(state, au, shift.schedule_id).await?;
+    if comment.user_id != au.id && role != ScheduleRole::Admin {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+async fn update_shift_comment(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, comment_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<AddCommentRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_comment_author_or_admin(&state, &au, shift_id, comment_id).await?;
+    if req.body.trim().is_empty() {
+        return Err(AppError::BadRequest("comment body is required".to_string()));
+    }
+    let c = state
+        .repo
+        .update_shift_comment(comment_id, req.body.trim().to_string())
+        .await?;
+    Ok(Json(c))
+}
+
+async fn delete_shift_comment(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((shift_id, comment_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_comment_author_or_admin(&state, &au, shift_id, comment_id).await?;
+    state.repo.delete_shift_comment(comment_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateTemplateRequest {
+    name: String,
+    definition: serde_json::Value,
+}
+
+async fn create_template(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateTemplateRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+    if req.name.trim().is_empty() {
+        return Err(AppError::BadRequest("name is required".to_string()));
+    }
+    TemplateDef::parse(req.definition.clone())?;
+    let t = state
+        .repo
+        .create_template(NewTemplate {
+            schedule_id,
+            name: req.name.trim().to_string(),
+            definition: req.definition,
+            created_by: au.id,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(t)))
+}
+
+async fn list_templates(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_to_read(&state, &au, schedule_id).await?;
+    Ok(Json(state.repo.list_templates(schedule_id).await?))
+}
+
+/// Header clients send so retried writes aren't applied twice.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Runs `work` at most once per `Idempotency-Key` header value. Retries with
+/// the same key and `scope` replay the stored response; without the header
+/// `work` simply runs.
+async fn idempotent<F>(
+    state: &AppState,
+    au: &AuthUser,
+    headers: &HeaderMap,
+    scope: &str,
+    work: F,
+) -> AppResult<axum::response::Response>
+where
+    F: std::future::Future<Output = AppResult<(StatusCode, serde_json::Value)>>,
+{
+    let key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|k| !k.is_empty());
+    let Some(key) = key else {
+        let (status, body) = work.await?;
+        return Ok((status, Json(body)).into_response());
+    };
+    if key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(AppError::BadRequest(format!(
+            "Idempotency-Key must be at most {MAX_IDEMPOTENCY_KEY_LEN} chars"
+        )));
+    }
+
+    match state
+        .repo
+        .begin_idempotent_request(au.id, key, scope)
+        .await?
+    {
+        IdempotentRequest::New => {}
+        IdempotentRequest::InProgress => {
+            return Err(AppError::Conflict(
+                "request with this Idempotency-Key is in progress".to_string(),
+            ))
+        }
+        IdempotentRequest::Completed { status, body } => {
+            let status = StatusCode::from_u16(status).map_err(|_| AppError::Internal)?;
+            return Ok((status, Json(body)).into_response());
+        }
+    }
+
+    match work.await {
+        Ok((status, body)) => {
+            state
+                .repo
+                .finish_idempotent_request(au.id, key, status.as_u16(), body.clone())
+                .await?;
+            Ok((status, Json(body)).into_response())
+        }
+        Err(e) => {
+            state.repo.abort_idempotent_request(au.id, key).await?;
+            Err(e)
+        }
+    }
+}
+
+/// What to do with cycle weeks the template was already applied to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ApplyMode {
+    /// Leave those weeks alone; only generate weeks not seen before.
+    #[default]
+    Skip,
+    /// Delete the template's shifts in those weeks and generate them again.
+    Replace,
+    /// Generate every week, even if that duplicates shifts.
+    Append,
+}
+
+/// Either a single `week_start`, or a `from`..`to` range (inclusive) over
+/// which the template's cycle is repeated. Dates are YYYY-MM-DD in the
+/// schedule's zone.
+#[derive(Debug, Deserialize, Serialize)]
+struct ApplyTemplateRequest {
+    week_start: Option<String>, // Monday recommended
+    from: Option<String>,
+    to: Option<String>,
+    #[serde(default)]
+    mode: ApplyMode,
+}
+
+fn parse_date(value: &str, name: &str) -> AppResult<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::BadRequest(format!("{name} must be YYYY-MM-DD")))
+}
+
+async fn apply_template(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((schedule_id, template_id)): Path<(Uuid, Uuid)>,
+    Json(req): Json<ApplyTemplateRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_admin_or_superadmin(&state, &au, schedule_id).await?;
+
+    let fingerprint = serde_json::to_string(&req).map_err(|_| AppError::Internal)?;
+    let scope = format!("apply_template:{template_id}:{}", hash_token(&fingerprint));
+    let work = async {
+        let created = apply_template_shifts(&state, &au, schedule_id, template_id, req).await?;
+        let body = serde_json::to_value(created).map_err(|_| AppError::Internal)?;
+        Ok((StatusCode::CREATED, body))
+    };
+    idempotent(&state, &au, &headers, &scope, work).await
+}
+
+async fn apply_template_shifts(
+    state: &AppState,
+    au: &AuthUser,
+    schedule_id: Uuid,
+    template_id: Uuid,
+    req: ApplyTemplateRequest,
+) -> AppResult<Vec<Shift>> {
+    let template = state
+        .repo
+        .get_template(template_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if template.schedule_id != schedule_id {
+        return Err(AppError::Forbidden);
+    }
+
+    let schedule = state
+        .repo
+        .get_schedule(schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    let zone = tz::parse_tz(&schedule.timezone)?;
+
+    let (from, to) = match (&req.week_start, &req.from, &req.to) {
+        (Some(week_start), None, None) => {
+            let week_start = parse_date(week_start, "week_start")?;
+            let week_end = week_start
+                .checked_add_days(chrono::Days::new(6))
+                .ok_or_else(|| AppError::BadRequest("week_start is out of range".to_string()))?;
+            (week_start, week_end)
+        }
+        (None, Some(from), Some(to)) => (parse_date(from, "from")?, parse_date(to, "to")?),
+        _ => {
+            return Err(AppError::BadRequest(
+                "give either week_start or from and to".to_string(),
+            ))
+        }
+    };
+
+    let def = TemplateDef::parse(template.definition)?;
+    for user_id in def.named_members() {
+        if state
+            .repo
+            .get_schedule_role(schedule_id, user_id)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::BadRequest(format!(
+                "template assignee {user_id} is not a member of this schedule"
+            )));
+        }
+    }
+
+    let anchor = default_anchor(template.created_at, zone);
+    let mut planned = def.expand(zone, anchor, from, to)?;
+    let mut weeks: Vec<NaiveDate> = planned.iter().map(|p| p.week_start).collect();
+    weeks.dedup();
+    if req.mode == ApplyMode::Skip {
+        if let (Some(first), Some(last)) = (weeks.first(), weeks.last()) {
+            let applied = state
+                .repo
+                .list_template_weeks(template_id, *first, *last)
+                .await?;
+            planned.retain(|p| !applied.contains(&p.week_start));
+        }
+    }
+
+    let mut positions: RotationState =
+        serde_json::from_value(template.rotation_state.clone()).map_err(|_| AppError::Internal)?;
+    let assignees = def.assignees(&planned, &mut positions);
+
+    let schedule_tasks = state.repo.list_schedule_tasks(schedule_id).await?;
+
+    // Old weeks, new shifts and the advanced rotation land together or not at all.
+    let mut uow = UnitOfWork::new();
+    uow.lock_template(template_id, template.rotation_state);
+    match req.mode {
+        ApplyMode::Skip => {
+            // Another application may have filled these weeks since we looked.
+            let mut unapplied: Vec<NaiveDate> = planned.iter().map(|p| p.week_start).collect();
+            unapplied.dedup();
+            uow.ensure_template_weeks_unapplied(template_id, unapplied);
+        }
+        ApplyMode::Replace => uow.delete_template_weeks(template_id, weeks.clone()),
+        ApplyMode::Append => {}
+    }
+    let replaced = |s: &Shift| {
+        req.mode == ApplyMode::Replace
+            && s.template_id == Some(template_id)
+            && s.template_week.is_some_and(|w| weeks.contains(&w))
+    };
+    let now = Utc::now();
+    let mut taken: Vec<(Uuid, Shift)> = Vec::new();
+    for (p, assignee) in planned.into_iter().zip(assignees) {
+        let draft = Shift {
+            id: Uuid::nil(),
+            schedule_id,
+            starts_at: p.starts_at,
+            ends_at: p.ends_at,
+            period: p.period,
+            capacity: 1,
+            assignees: Vec::new(),
+            template_id: Some(template_id),
+            template_week: Some(p.week_start),
+            created_by: au.id,
+            created_at: now,
+            updated_at: now,
+        };
+        // Assignees who can't work the shift leave it open, with a comment.
+        let mut clash = None;
+        if let Some(user_id) = assignee {
+            clash = template_clash(state, user_id, &draft, &replaced, &taken)
+                .await?
+                .map(|reason| format!("Unassigned: {user_id} can't take this shift: {reason}"));
+        }
+        let assigned_user_id = assignee.filter(|_| clash.is_none());
+        let tasks = checklist::instantiate(
+            &schedule_tasks,
+            &def.slots[p.slot].tasks,
+            p.starts_at,
+            p.ends_at,
+            p.period,
+            zone,
+        )?;
+        let id = uow.create_shift(NewShift {
+            schedule_id,
+            starts_at: p.starts_at,
+            ends_at: p.ends_at,
+            period: p.period,
+            capacity: 1,
+            assigned_user_id,
+            template_id: Some(template_id),
+            template_week: Some(p.week_start),
+            tasks,
+            created_by: au.id,
+        });
+        if let Some(user_id) = assigned_user_id {
+            taken.push((user_id, Shift { id, ..draft }));
+        }
+        if let Some(body) = clash {
+            uow.add_shift_comment(NewShiftComment {
+                shift_id: id,
+                user_id: au.id,
+                body,
+            });
+        }
+    }
+    uow.set_template_rotation_state(
+        template_id,
+        serde_json::to_value(&positions).map_err(|_| AppError::Internal)?,
+    );
+    let created = state.repo.commit(uow).await?.shifts;
+
+    Ok(created)
+}
+
+/// Why `user_id` can't work `shift`, which a template is about to create:
+/// it overlaps their other shifts (apart from those the application
+/// replaces) or ones it already gave them, or they're unavailable.
+async fn template_clash(
+    state: &AppState,
+    user_id: Uuid,
+    shift: &Shift,
+    replaced: impl Fn(&Shift) -> bool,
+    taken: &[(Uuid, Shift)],
+) -> AppResult<Option<String>> {
+    let mut clashes: Vec<Shift> = state
+        .repo
+        .list_overlapping_shifts(user_id, shift.starts_at, shift.ends_at, shift.id)
+        .await?
+        .into_iter()
+        .filter(|s| !replaced(s))
+        .collect();
+    clashes.extend(
+        taken
+            .iter()
+            .filter(|(u, other)| {
+                *u == user_id
+                    && rota::too_close(
+                        other,
+                        shift.starts_at,
+                        shift.ends_at,
+                        chrono::Duration::zero(),
+                    )
+            })
+            .map(|(_, other)| other.clone()),
+    );
+    if !clashes.is_empty() {
+        return Ok(Some(format!(
+            "user already assigned to overlapping shifts: {}",
+            describe_shifts(&clashes)
+        )));
+    }
+    match ensure_available(state, user_id, shift).await {
+        Ok(()) => Ok(None),
+        Err(AppError::Conflict(reason)) => Ok(Some(reason)),
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateSwapRequest {
+    shift_id: Uuid,
+    counterpart_id: Uuid,
+    /// Shift offered in return; omit to give `shift_id` away.
+    counterpart_shift_id: Option<Uuid>,
+    message: Option<String>,
+    /// Defaults to when the first of the shifts starts.
+    expires_at: Option<DateTime<Utc>>,
+}
+
+async fn create_swap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Json(req): Json<CreateSwapRequest>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    require_member_or_superadmin(&state, &au, schedule_id).await?;
+
+    let shift = state
+        .repo
+        .get_shift(req.shift_id)
+        .await?
+        .filter(|s| s.schedule_id == schedule_id)
+        .ok_or(AppError::NotFound)?;
+    if !shift.is_assigned(au.id) {
+        return Err(AppError::Forbidden);
+    }
+    if req.counterpart_id == au.id {
+        return Err(AppError::BadRequest(
+            "counterpart must be another member".to_string(),
+        ));
+    }
+    if state
+        .repo
+        .get_schedule_role(schedule_id, req.counterpart_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "counterpart is not a member of this schedule".to_string(),
+        ));
+    }
+
+    let mut first_start = shift.starts_at;
+    if let Some(counterpart_shift_id) = req.counterpart_shift_id {
+        let counterpart_shift = state
+            .repo
+            .get_shift(counterpart_shift_id)
+            .await?
+            .filter(|s| s.schedule_id == schedule_id)
+            .ok_or(AppError::NotFound)?;
+        if !counterpart_shift.is_assigned(req.counterpart_id) {
+            return Err(AppError::BadRequest(
+                "counterpart_shift_id is not assigned to the counterpart".to_string(),
+            ));
+        }
+        first_start = first_start.min(counterpart_shift.starts_at);
+    }
+
+    let now = Utc::now();
+    let expires_at = req.expires_at.unwrap_or(first_start);
+    if expires_at <= now || expires_at > first_start {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future and no later than the shifts start".to_string(),
+        ));
+    }
+
+    let swap = state
+        .repo
+        .create_swap(NewSwap {
+            schedule_id,
+            shift_id: shift.id,
+            requester_id: au.id,
+            counterpart_id: req.counterpart_id,
+            counterpart_shift_id: req.counterpart_shift_id,
+            message: req
+                .message
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty()),
+            expires_at,
+        })
+        .await?;
+    Ok((StatusCode::CREATED, Json(swap)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ListSwapsQuery {
+    status: Option<SwapStatus>,
+}
+
+/// Admins see every swap in the schedule; members only their own.
+async fn list_swaps(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(schedule_id): Path<Uuid>,
+    Query(q): Query<ListSwapsQuery>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let role = require_member_to_read(&state, &au, schedule_id).await?;
+    let involving = if au.is_superadmin || role == ScheduleRole::Admin {
+        None
+    } else {
+        Some(au.id)
+    };
+
+    state.repo.expire_swaps(Utc::now()).await?;
+    let swaps = state
+        .repo
+        .list_swaps(schedule_id, involving, q.status)
+        .await?;
+    Ok(Json(swaps))
+}
+
+/// Loads a swap the caller can see, after expiring stale requests.
+async fn load_swap(state: &AppState, au: &AuthUser, swap_id: Uuid) -> AppResult<ShiftSwap> {
+    state.repo.expire_swaps(Utc::now()).await?;
+    let swap = state
+        .repo
+        .get_swap(swap_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    require_member_or_superadmin(state, au, swap.schedule_id).await?;
+    Ok(swap)
+}
+
+fn ensure_swap_pending(swap: &ShiftSwap) -> AppResult<()> {
+    if swap.status != SwapStatus::Pending {
+        return Err(AppError::Conflict(format!(
+            "swap request is {}",
+            swap.status.as_str()
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects the swap if either side would end up double-booked or working
+/// while unavailable.
+async fn check_swap_clashes(state: &AppState, swap: &ShiftSwap) -> AppResult<()> {
+    let shift = state
+        .repo
+        .get_shift(swap.shift_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    ensure_available(state, swap.counterpart_id, &shift).await?;
+    let mut clashes: Vec<Shift> = state
+        .repo
+        .list_overlapping_shifts(
+            swap.counterpart_id,
+            shift.starts_at,
+            shift.ends_at,
+            shift.id,
+        )
+        .await?
+        .into_iter()
+        .filter(|s| Some(s.id) != swap.counterpart_shift_id)
+        .collect();
+    if let Some(counterpart_shift_id) = swap.counterpart_shift_id {
+        let counterpart_shift = state
+            .repo
+            .get_shift(counterpart_shift_id)
+            .await?
+            .ok_or(AppError::NotFound)?;
+        ensure_available(state, swap.requester_id, &counterpart_shift).await?;
+        clashes.extend(
+            state
+                .repo
+                .list_overlapping_shifts(
+                    swap.requester_id,
+                    counterpart_shift.starts_at,
+                    counterpart_shift.ends_at,
+                    counterpart_shift.id,
+                )
+                .await?
+                .into_iter()
+                .filter(|s| s.id != swap.shift_id),
+        );
+    }
+    if !clashes.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "swap would create overlapping shifts: {}",
+            describe_shifts(&clashes)
+        )));
+    }
+    Ok(())
+}
+
+/// Hands the shifts over and closes the request in one commit, so a
+/// concurrent reassignment makes the whole swap fail.
+async fn complete_swap(state: &AppState, swap: &ShiftSwap, decided_by: Uuid) -> AppResult<()> {
+    let mut uow = UnitOfWork::new();
+    uow.reassign_shift(
+        swap.shift_id,
+        Some(swap.requester_id),
+        Some(swap.counterpart_id),
+    );
+    if let Some(counterpart_shift_id) = swap.counterpart_shift_id {
+        uow.reassign_shift(
+            counterpart_shift_id,
+            Some(swap.counterpart_id),
+            Some(swap.requester_id),
+        );
+    }
+    uow.set_swap_status(swap.id, SwapStatus::Accepted, Some(decided_by));
+    state.repo.commit(uow).await?;
+    Ok(())
+}
+
+async fn reload_swap(state: &AppState, swap_id: Uuid) -> AppResult<Json<ShiftSwap>> {
+    let swap = state
+        .repo
+        .get_swap(swap_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(swap))
+}
+
+async fn accept_swap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(swap_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let swap = load_swap(&state, &au, swap_id).await?;
+    if swap.counterpart_id != au.id {
+        return Err(AppError::Forbidden);
+    }
+    ensure_swap_pending(&swap)?;
+    if swap.counterpart_accepted_at.is_some() {
+        return Err(AppError::Conflict(
+            "swap request is waiting for admin approval".to_string(),
+        ));
+    }
+    check_swap_clashes(&state, &swap).await?;
+
+    let schedule = state
+        .repo
+        .get_schedule(swap.schedule_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    if schedule.swap_requires_approval {
+        let mut uow = UnitOfWork::new();
+        uow.mark_swap_counterpart_accepted(swap.id);
+        state.repo.commit(uow).await?;
+    } else {
+        complete_swap(&state, &swap, au.id).await?;
+    }
+    reload_swap(&state, swap_id).await
+}
+
+async fn approve_swap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(swap_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let swap = load_swap(&state, &au, swap_id).await?;
+    require_admin_or_superadmin(&state, &au, swap.schedule_id).await?;
+    ensure_swap_pending(&swap)?;
+    if swap.counterpart_accepted_at.is_none() {
+        return Err(AppError::Conflict(
+            "counterpart has not accepted the swap yet".to_string(),
+        ));
+    }
+    check_swap_clashes(&state, &swap).await?;
+
+    complete_swap(&state, &swap, au.id).await?;
+    reload_swap(&state, swap_id).await
+}
+
+/// The counterpart can decline while the request is pending; admins can
+/// decline once it is waiting for their approval.
+async fn decline_swap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(swap_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let swap = load_swap(&state, &au, swap_id).await?;
+    if swap.counterpart_id != au.id {
+        require_admin_or_superadmin(&state, &au, swap.schedule_id).await?;
+        if swap.counterpart_accepted_at.is_none() {
+            return Err(AppError::Forbidden);
+        }
+    }
+    ensure_swap_pending(&swap)?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_swap_status(swap.id, SwapStatus::Declined, Some(au.id));
+    state.repo.commit(uow).await?;
+    reload_swap(&state, swap_id).await
+}
+
+async fn cancel_swap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(swap_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let au = AuthUser::from_headers(&state, &headers).await?;
+    let swap = load_swap(&state, &au, swap_id).await?;
+    if swap.requester_id != au.id {
+        return Err(AppError::Forbidden);
+    }
+    ensure_swap_pending(&swap)?;
+
+    let mut uow = UnitOfWork::new();
+    uow.set_swap_status(swap.id, SwapStatus::Cancelled, Some(au.id));
+    state.repo.commit(uow).await?;
+    reload_swap(&state, swap_id).await
+}
+
+#[cfg(test)]
+mod api_tests {
+    use super::*;
+    use crate::{mailer::LogMailer, repo::MemRepo};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    fn router() -> Router {
+        router_with_mailer().0
+    }
+
+    fn router_with_mailer() -> (Router, LogMailer) {
+        let mailer = LogMailer::default();
+        let app = build_router(AppState {
+            repo: Arc::new(MemRepo::new()),
+            jwt: JwtKeys::new("test-secret"),
+            cors_origin: None,
+            mailer: Arc::new(mailer.clone()),
+            public_url: "http://test".to_string(),
+        });
+        (app, mailer)
+    }
+
+    /// Token from the link in the latest email to `to`.
+    fn mailed_token(mailer: &LogMailer, to: &str) -> String {
+        let email = mailer
+            .sent()
+            .into_iter()
+            .rev()
+            .find(|e| e.to == to)
+            .unwrap();
+        let link = email
+            .body
+            .split_whitespace()
+            .find(|w| w.starts_with("http://test/?"))
+            .unwrap();
+        link.split_once('=').unwrap().1.to_string()
+    }
+
+    async fn send(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut req = axum::http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            req = req.header("authorization", format!("Bearer {token}"));
+        }
+        let req = match body {
+            Some(b) => req
+                .header("content-type", "application/json")
+                .body(axum::body::Body::from(b.to_string())),
+            None => req.body(axum::body::Body::empty()),
+        }
+        .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let v = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+        (status, v)
+    }
+
+    async fn register_user(app: &Router, email: &str) -> String {
+        let (status, v) = send(
+            app,
+            "POST",
+            "/api/auth/register",
+            None,
+            Some(serde_json::json!({ "email": email, "password": "password1" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        v["token"].as_str().unwrap().to_string()
+    }
+
+    async fn create_schedule_for(app: &Router, token: &str) -> String {
+        let (status, v) = send(
+            app,
+            "POST",
+            "/api/schedules",
+            Some(token),
+            Some(serde_json::json!({
+                "name": "Care",
+                "subject_type": "pet",
+                "subject_name": "Puppy"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        v["id"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn register_login_and_create_schedule() {
+        let app = router();
+
+        // Register
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri("/api/auth/register")
+                    .header("content-type", "application/json")
+                    .body(axum::body::Body::from(
+                        r#"{"email":"a@example.com","password":"password1"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let token = v.get("token").unwrap().as_str().unwrap().to_string();
+
+        // Create schedule
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri("/api/schedules")
+                    .header("content-type", "application/json")
+                    .header("authorization", format!("Bearer {token}"))
+                    .body(axum::body::Body::from(
+                        r#"{"name":"Care","subject_type":"pet","subject_name":"Puppy"}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_shift() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (status, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2025-01-06T08:00:00Z",
+                "ends_at": "2025-01-06T12:00:00Z",
+                "period": "morning"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let shift_uri = format!("/api/shifts/{}", shift["id"].as_str().unwrap());
+
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &shift_uri,
+            Some(&member),
+            Some(serde_json::json!({ "period": "afternoon" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, v) = send(
+            &app,
+            "PATCH",
+            &shift_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "ends_at": "2025-01-06T07:00:00Z" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(v["error"].as_str().unwrap().contains("ends_at"));
+
+        let (status, v) = send(
+            &app,
+            "PATCH",
+            &shift_uri,
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2025-01-06T13:00:00Z",
+                "ends_at": "2025-01-06T18:00:00Z",
+                "period": "afternoon"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["period"], "afternoon");
+        assert_eq!(v["starts_at"], "2025-01-06T13:00:00Z");
+
+        let (status, _) = send(&app, "DELETE", &shift_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "DELETE", &shift_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn list_edit_and_delete_shift_comments() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2025-01-06T08:00:00Z",
+                "ends_at": "2025-01-06T12:00:00Z",
+                "period": "morning"
+            })),
+        )
+        .await;
+        let comments_uri = format!("/api/shifts/{}/comments", shift["id"].as_str().unwrap());
+        for body in ["first", "second", "third"] {
+            let (status, _) = send(
+                &app,
+                "POST",
+                &comments_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "body": body })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let (status, page) = send(
+            &app,
+            "GET",
+            &format!("{comments_uri}?limit=2&offset=1"),
+            Some(&member),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let page = page.as_array().unwrap();
+        assert_eq!(page.len(), 2);
+        assert_eq!(page[0]["body"], "second");
+        let comment_uri = format!("{comments_uri}/{}", page[0]["id"].as_str().unwrap());
+
+        // Members can read but not edit someone else's comment.
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &comment_uri,
+            Some(&member),
+            Some(serde_json::json!({ "body": "hijack" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, v) = send(
+            &app,
+            "PATCH",
+            &comment_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "body": "second (edited)" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["body"], "second (edited)");
+        assert!(v["edited_at"].is_string());
+
+        let (status, _) = send(&app, "DELETE", &comment_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, all) = send(&app, "GET", &comments_uri, Some(&admin), None).await;
+        assert_eq!(all.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn assign_rejects_overlapping_shifts_unless_forced() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap().to_string();
+
+        // The clash is detected across schedules: the member's own, and one
+        // they were added to.
+        let mut shift_ids = Vec::new();
+        for (start, end, owner) in [
+            ("2025-01-06T08:00:00Z", "2025-01-06T12:00:00Z", &member),
+            ("2025-01-06T11:00:00Z", "2025-01-06T14:00:00Z", &admin),
+        ] {
+            let schedule_id = create_schedule_for(&app, owner).await;
+            if owner == &admin {
+                let (status, _) = send(
+                    &app,
+                    "POST",
+                    &format!("/api/schedules/{schedule_id}/members"),
+                    Some(&admin),
+                    Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+                )
+                .await;
+                assert_eq!(status, StatusCode::NO_CONTENT);
+            }
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(
+                    serde_json::json!({ "starts_at": start, "ends_at": end, "period": "morning" }),
+                ),
+            )
+            .await;
+            shift_ids.push(shift["id"].as_str().unwrap().to_string());
+        }
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/assign", shift_ids[0]),
+            Some(&member),
+            Some(serde_json::json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let assign_second = |token: String, force: bool| {
+            let app = app.clone();
+            let uri = format!("/api/shifts/{}/assign", shift_ids[1]);
+            let member_id = member_id.clone();
+            async move {
+                send(
+                    &app,
+                    "POST",
+                    &uri,
+                    Some(&token),
+                    Some(serde_json::json!({ "assigned_user_id": member_id, "force": force })),
+                )
+                .await
+            }
+        };
+
+        let (status, v) = assign_second(admin.clone(), false).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains(&shift_ids[0]));
+
+        let (status, _) = assign_second(member.clone(), true).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, _) = assign_second(admin.clone(), true).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Moving an assigned shift is checked the same way: shortening the
+        // first shift clears the clash, stretching it back needs `force`.
+        let move_first = |ends_at: &'static str, force: bool| {
+            let app = app.clone();
+            let admin = admin.clone();
+            let uri = format!("/api/shifts/{}", shift_ids[0]);
+            async move {
+                send(
+                    &app,
+                    "PATCH",
+                    &uri,
+                    Some(&admin),
+                    Some(serde_json::json!({ "ends_at": ends_at, "force": force })),
+                )
+                .await
+            }
+        };
+        let (status, _) = move_first("2025-01-06T10:00:00Z", false).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, v) = move_first("2025-01-06T12:00:00Z", false).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains(&shift_ids[1]));
+        let (status, _) = move_first("2025-01-06T12:00:00Z", true).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn apply_template_uses_schedule_time_zone() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let (status, schedule) = send(
+            &app,
+            "POST",
+            "/api/schedules",
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Care",
+                "subject_type": "person",
+                "subject_name": "Grandma",
+                "timezone": "America/New_York"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(schedule["timezone"], "America/New_York");
+        let schedule_id = schedule["id"].as_str().unwrap();
+
+        let (_, template) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Mornings",
+                "definition": { "slots": [
+                    { "dow": 0, "period": "morning", "start": "08:00", "end": "12:00" }
+                ] }
+            })),
+        )
+        .await;
+        let apply_uri = format!(
+            "/api/schedules/{schedule_id}/templates/{}/apply",
+            template["id"].as_str().unwrap()
+        );
+
+        // 08:00 EST in winter, 08:00 EDT in summer.
+        for (week_start, expected) in [
+            ("2025-01-06", "2025-01-06T13:00:00Z"),
+            ("2025-07-07", "2025-07-07T12:00:00Z"),
+        ] {
+            let (status, created) = send(
+                &app,
+                "POST",
+                &apply_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "week_start": week_start })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+            assert_eq!(created[0]["starts_at"], expected);
+        }
+
+        // Local-date ranges are resolved in the schedule's zone, `to` inclusive.
+        let (status, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2025-01-06&to=2025-01-06"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(shifts.as_array().unwrap().len(), 1);
+
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &format!("/api/schedules/{schedule_id}"),
+            Some(&admin),
+            Some(serde_json::json!({ "timezone": "Not/AZone" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn calendar_feeds_use_revocable_token() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let starts_at = Utc::now() + chrono::Duration::days(1);
+        let (_, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": starts_at,
+                "ends_at": starts_at + chrono::Duration::hours(4),
+                "period": "night"
+            })),
+        )
+        .await;
+        let shift_id = shift["id"].as_str().unwrap();
+        send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{shift_id}/assign"),
+            Some(&admin),
+            Some(serde_json::json!({})),
+        )
+        .await;
+
+        let (status, v) = send(&app, "POST", "/api/me/calendar-token", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let token = v["token"].as_str().unwrap().to_string();
+
+        let fetch = |uri: String| {
+            let app = app.clone();
+            async move {
+                let req = axum::http::Request::builder()
+                    .uri(uri)
+                    .body(axum::body::Body::empty())
+                    .unwrap();
+                let res = app.oneshot(req).await.unwrap();
+                let status = res.status();
+                let body = res.into_body().collect().await.unwrap().to_bytes();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, ics) = fetch(format!("/api/calendar/me.ics?token={token}")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ics.contains(&format!("UID:shift-{shift_id}@buddy-schedule")));
+        assert!(ics.contains("SUMMARY:Care (night)"));
+
+        let (status, ics) = fetch(format!(
+            "/api/calendar/schedules/{schedule_id}/shifts.ics?token={token}"
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(ics.contains("X-WR-CALNAME:Care"));
+
+        let (status, _) = send(&app, "DELETE", "/api/me/calendar-token", Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = fetch(format!("/api/calendar/me.ics?token={token}")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn import_ics_dry_run_reports_overlaps() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2025-01-07T09:00:00Z",
+                "ends_at": "2025-01-07T10:00:00Z",
+                "period": "morning"
+            })),
+        )
+        .await;
+
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Vet\r\n\
+                   DTSTART:20250106T083000Z\r\nDTEND:20250106T093000Z\r\n\
+                   RRULE:FREQ=DAILY;COUNT=3\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let uri = format!("/api/schedules/{schedule_id}/import/ics");
+        let body = |dry_run: bool| {
+            serde_json::json!({
+                "ics": ics,
+                "dry_run": dry_run,
+                "from": "2025-01-01",
+                "to": "2025-01-31",
+                "period_map": { "vet": "afternoon" }
+            })
+        };
+
+        let (status, v) = send(&app, "POST", &uri, Some(&admin), Some(body(true))).await;
+        assert_eq!(status, StatusCode::OK);
+        let shifts = v["shifts"].as_array().unwrap();
+        assert_eq!(shifts.len(), 3);
+        assert_eq!(shifts[0]["period"], "afternoon");
+        assert_eq!(shifts[0]["overlaps"].as_array().unwrap().len(), 0);
+        assert_eq!(shifts[1]["overlaps"].as_array().unwrap().len(), 1);
+        assert!(v["created"].as_array().unwrap().is_empty());
+
+        let (status, v) = send(&app, "POST", &uri, Some(&admin), Some(body(false))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(v["created"].as_array().unwrap().len(), 3);
+
+        // The same calendar uploaded as-is, with the options in the query.
+        let upload = axum::http::Request::builder()
+            .method("POST")
+            .uri(format!(
+                "{uri}?dry_run=true&from=2025-01-01&to=2025-01-31&period_map=%7B%22vet%22%3A%22night%22%7D"
+            ))
+            .header("authorization", format!("Bearer {admin}"))
+            .header("content-type", "text/calendar; charset=utf-8")
+            .body(axum::body::Body::from(ics))
+            .unwrap();
+        let res = app.clone().oneshot(upload).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(v["shifts"].as_array().unwrap().len(), 3);
+        assert_eq!(v["shifts"][0]["period"], "night");
+        assert_eq!(v["shifts"][0]["overlaps"].as_array().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn apply_template_over_range_repeats_cycle() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let (_, template) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Fortnightly",
+                "definition": {
+                    "cycle_weeks": 2,
+                    "anchor": "2025-01-06",
+                    "slots": [
+                        { "dow": 0, "week": 0, "period": "morning", "start": "08:00", "end": "12:00" },
+                        { "dow": 2, "week": 1, "period": "night", "start": "18:00", "end": "22:00" }
+                    ]
+                }
+            })),
+        )
+        .await;
+        let apply_uri = format!(
+            "/api/schedules/{schedule_id}/templates/{}/apply",
+            template["id"].as_str().unwrap()
+        );
+
+        let (status, created) = send(
+            &app,
+            "POST",
+            &apply_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "from": "2025-01-06", "to": "2025-02-02" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let starts: Vec<_> = created
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["starts_at"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                "2025-01-06T08:00:00Z",
+                "2025-01-15T18:00:00Z",
+                "2025-01-20T08:00:00Z",
+                "2025-01-29T18:00:00Z",
+            ]
+        );
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &apply_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "week_start": "2025-01-06", "from": "2025-01-06" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn apply_template_assigns_round_robin() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, a) = send(&app, "GET", "/api/me", Some(&admin), None).await;
+        let (_, b) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let (a, b) = (a["id"].clone(), b["id"].clone());
+
+        let (_, template) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Walks",
+                "definition": { "slots": [
+                    { "dow": 0, "period": "morning", "start": "08:00", "end": "09:00", "rotate": [a, b] }
+                ] }
+            })),
+        )
+        .await;
+        let apply_uri = format!(
+            "/api/schedules/{schedule_id}/templates/{}/apply",
+            template["id"].as_str().unwrap()
+        );
+
+        let mut assigned = Vec::new();
+        for week_start in ["2025-01-06", "2025-01-13", "2025-01-20"] {
+            let (status, created) = send(
+                &app,
+                "POST",
+                &apply_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "week_start": week_start })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+            assigned.push(created[0]["assigned_user_id"].clone());
+        }
+        assert_eq!(assigned, [a.clone(), b, a.clone()]);
+
+        // It's b's turn on the 27th, but b is away: the shift stays open.
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&member),
+            Some(serde_json::json!({ "starts_on": "2025-01-27", "ends_on": "2025-01-27" })),
+        )
+        .await;
+        let (status, created) = send(
+            &app,
+            "POST",
+            &apply_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "week_start": "2025-01-27" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(created[0]["assigned_user_id"].is_null());
+        let (_, comments) = send(
+            &app,
+            "GET",
+            &format!(
+                "/api/shifts/{}/comments",
+                created[0]["id"].as_str().unwrap()
+            ),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert!(comments[0]["body"]
+            .as_str()
+            .unwrap()
+            .contains("unavailable"));
+
+        // Replacing a week doesn't clash with the shifts it replaces.
+        let (_, created) = send(
+            &app,
+            "POST",
+            &apply_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "week_start": "2025-01-20", "mode": "replace" })),
+        )
+        .await;
+        assert_eq!(created[0]["assigned_user_id"], a);
+    }
+
+    #[tokio::test]
+    async fn reapplying_template_is_idempotent() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let (_, template) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Mornings",
+                "definition": { "slots": [
+                    { "dow": 0, "period": "morning", "start": "08:00", "end": "12:00" },
+                    { "dow": 1, "period": "morning", "start": "08:00", "end": "12:00" }
+                ] }
+            })),
+        )
+        .await;
+        let apply_uri = format!(
+            "/api/schedules/{schedule_id}/templates/{}/apply",
+            template["id"].as_str().unwrap()
+        );
+        let count_shifts = || async {
+            let (_, shifts) = send(
+                &app,
+                "GET",
+                &format!("/api/schedules/{schedule_id}/shifts?from=2025-01-06&to=2025-01-12"),
+                Some(&admin),
+                None,
+            )
+            .await;
+            shifts.as_array().unwrap().len()
+        };
+        let apply = |mode: &'static str| {
+            send(
+                &app,
+                "POST",
+                &apply_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "week_start": "2025-01-06", "mode": mode })),
+            )
+        };
+
+        let (_, created) = apply("skip").await;
+        assert_eq!(created.as_array().unwrap().len(), 2);
+        assert!(created[0]["template_week"] == "2025-01-06");
+        let (_, created) = apply("skip").await;
+        assert!(created.as_array().unwrap().is_empty());
+        assert_eq!(count_shifts().await, 2);
+
+        apply("replace").await;
+        assert_eq!(count_shifts().await, 2);
+        apply("append").await;
+        assert_eq!(count_shifts().await, 4);
+
+        // A retried request with the same key replays the first response.
+        let retry = || async {
+            let req = axum::http::Request::builder()
+                .method("POST")
+                .uri(&apply_uri)
+                .header("authorization", format!("Bearer {admin}"))
+                .header("content-type", "application/json")
+                .header("idempotency-key", "retry-1")
+                .body(axum::body::Body::from(
+                    r#"{"week_start":"2025-01-06","mode":"append"}"#,
+                ))
+                .unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            let status = res.status();
+            let body = res.into_body().collect().await.unwrap().to_bytes();
+            (
+                status,
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            )
+        };
+        let (status, first) = retry().await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, second) = retry().await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(first, second);
+        assert_eq!(count_shifts().await, 6);
+
+        // Browsers may send the key cross-origin.
+        let preflight = axum::http::Request::builder()
+            .method("OPTIONS")
+            .uri(&apply_uri)
+            .header("origin", "https://example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "idempotency-key")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(preflight).await.unwrap();
+        let allowed = res.headers()["access-control-allow-headers"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(allowed.contains("idempotency-key"), "{allowed}");
+    }
+
+    #[tokio::test]
+    async fn swap_requests_reassign_shifts_atomically() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let alice = register_user(&app, "alice@example.com").await;
+        let bob = register_user(&app, "bob@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+
+        let mut ids = HashMap::new();
+        for (name, token) in [("alice", &alice), ("bob", &bob)] {
+            send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/members"),
+                Some(&admin),
+                Some(serde_json::json!({ "email": format!("{name}@example.com"), "role": "user" })),
+            )
+            .await;
+            let (_, me) = send(&app, "GET", "/api/me", Some(token), None).await;
+            ids.insert(name, me["id"].as_str().unwrap().to_string());
+        }
+
+        // Alice holds Monday, Bob holds Tuesday.
+        let mut shift_ids = Vec::new();
+        for (day, token) in [("06", &alice), ("07", &bob)] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": format!("2099-01-{day}T08:00:00Z"),
+                    "ends_at": format!("2099-01-{day}T12:00:00Z"),
+                    "period": "morning"
+                })),
+            )
+            .await;
+            let shift_id = shift["id"].as_str().unwrap().to_string();
+            send(
+                &app,
+                "POST",
+                &format!("/api/shifts/{shift_id}/assign"),
+                Some(token),
+                Some(serde_json::json!({})),
+            )
+            .await;
+            shift_ids.push(shift_id);
+        }
+
+        send(
+            &app,
+            "PATCH",
+            &format!("/api/schedules/{schedule_id}"),
+            Some(&admin),
+            Some(serde_json::json!({ "swap_requires_approval": true })),
+        )
+        .await;
+        let (status, swap) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/swaps"),
+            Some(&alice),
+            Some(serde_json::json!({
+                "shift_id": shift_ids[0],
+                "counterpart_id": ids["bob"],
+                "counterpart_shift_id": shift_ids[1]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let swap_uri = format!("/api/swaps/{}", swap["id"].as_str().unwrap());
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/accept"),
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, swap) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/accept"),
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(swap["status"], "pending");
+        assert!(swap["counterpart_accepted_at"].is_string());
+
+        let (_, swaps) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/swaps?status=pending"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(swaps.as_array().unwrap().len(), 1);
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/approve"),
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Alice is away on Tuesday now, so the swap can't go through yet.
+        let (_, away) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&alice),
+            Some(serde_json::json!({ "starts_on": "2099-01-07", "ends_on": "2099-01-07" })),
+        )
+        .await;
+        let (status, v) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/approve"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("unavailable"), "{v}");
+        send(
+            &app,
+            "DELETE",
+            &format!(
+                "/api/schedules/{schedule_id}/availability/exceptions/{}",
+                away["id"].as_str().unwrap()
+            ),
+            Some(&alice),
+            None,
+        )
+        .await;
+
+        let (status, swap) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/approve"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(swap["status"], "accepted");
+
+        let (_, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2099-01-06&to=2099-01-07"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(shifts[0]["assigned_user_id"], ids["bob"]);
+        assert_eq!(shifts[1]["assigned_user_id"], ids["alice"]);
+
+        // Closed requests can't be acted on again.
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{swap_uri}/cancel"),
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn open_shifts_are_claimed_once() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let alice = register_user(&app, "alice@example.com").await;
+        let bob = register_user(&app, "bob@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        for email in ["alice@example.com", "bob@example.com"] {
+            send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/members"),
+                Some(&admin),
+                Some(serde_json::json!({ "email": email, "role": "user" })),
+            )
+            .await;
+        }
+        let mut shift_ids = Vec::new();
+        for day in ["06", "07"] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": format!("2099-01-{day}T08:00:00Z"),
+                    "ends_at": format!("2099-01-{day}T12:00:00Z"),
+                    "period": "morning"
+                })),
+            )
+            .await;
+            shift_ids.push(shift["id"].as_str().unwrap().to_string());
+        }
+
+        let board = "/api/open-shifts?from=2099-01-01T00:00:00Z&to=2099-02-01T00:00:00Z";
+        let (status, open) = send(&app, "GET", board, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(open.as_array().unwrap().len(), 2);
+        assert_eq!(open[0]["claim_policy"], "open");
+
+        let claim = |token: String, shift_id: String| {
+            let app = app.clone();
+            async move {
+                send(
+                    &app,
+                    "POST",
+                    &format!("/api/shifts/{shift_id}/claim"),
+                    Some(&token),
+                    None,
+                )
+                .await
+            }
+        };
+        let (status, _) = claim(alice.clone(), shift_ids[0].clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = claim(bob.clone(), shift_ids[0].clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // Under the approval policy a claim waits for an admin.
+        send(
+            &app,
+            "PATCH",
+            &format!("/api/schedules/{schedule_id}"),
+            Some(&admin),
+            Some(serde_json::json!({ "claim_policy": "approval" })),
+        )
+        .await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/assign", shift_ids[1]),
+            Some(&bob),
+            Some(serde_json::json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, bob_claim) = claim(bob.clone(), shift_ids[1].clone()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (_, alice_claim) = claim(alice.clone(), shift_ids[1].clone()).await;
+
+        // Availability recorded after the claim still counts on approval.
+        let approve_uri = format!("/api/claims/{}/approve", bob_claim["id"].as_str().unwrap());
+        let (_, away) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&bob),
+            Some(serde_json::json!({ "starts_on": "2099-01-07", "ends_on": "2099-01-07" })),
+        )
+        .await;
+        let (status, v) = send(&app, "POST", &approve_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("unavailable"), "{v}");
+        send(
+            &app,
+            "DELETE",
+            &format!(
+                "/api/schedules/{schedule_id}/availability/exceptions/{}",
+                away["id"].as_str().unwrap()
+            ),
+            Some(&bob),
+            None,
+        )
+        .await;
+
+        let (status, approved) = send(
+            &app,
+            "POST",
+            &format!("/api/claims/{}/approve", bob_claim["id"].as_str().unwrap()),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(approved["status"], "approved");
+        let (_, claims) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/claims?status=rejected"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(claims[0]["id"], alice_claim["id"]);
+
+        let (_, open) = send(&app, "GET", board, Some(&bob), None).await;
+        assert!(open.as_array().unwrap().is_empty());
+        let (status, _) = send(
+            &app,
+            "GET",
+            "/api/open-shifts?from=%2B262142-12-31T00:00:00Z",
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn assign_respects_member_availability() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap().to_string();
+
+        // 2025-03-04 is a Tuesday.
+        let (_, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2025-03-04T18:00:00Z",
+                "ends_at": "2025-03-04T22:00:00Z",
+                "period": "night"
+            })),
+        )
+        .await;
+        let assign_uri = format!("/api/shifts/{}/assign", shift["id"].as_str().unwrap());
+        let assign = |force: bool| {
+            send(
+                &app,
+                "POST",
+                &assign_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "assigned_user_id": member_id, "force": force })),
+            )
+        };
+
+        let (status, rule) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/weekly"),
+            Some(&member),
+            Some(serde_json::json!({ "dow": 1, "period": "night", "note": "choir" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, v) = assign(false).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("choir"));
+
+        // The weekly rule moves to Wednesday, but the member is away that week.
+        let rule_uri = format!(
+            "/api/schedules/{schedule_id}/availability/weekly/{}",
+            rule["id"].as_str().unwrap()
+        );
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &rule_uri,
+            Some(&member),
+            Some(serde_json::json!({ "dow": 2 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&member),
+            Some(serde_json::json!({ "starts_on": "2025-03-03", "ends_on": "2025-03-10" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, v) = assign(false).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("away"));
+
+        let (_, availability) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/availability?user_id={member_id}"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(availability["weekly"].as_array().unwrap().len(), 1);
+        assert_eq!(availability["exceptions"].as_array().unwrap().len(), 1);
+
+        let (status, _) = assign(true).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "DELETE", &rule_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Even a forced admin assignment needs a member of the schedule.
+        let outsider = register_user(&app, "outsider@example.com").await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&outsider), None).await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            &assign_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "assigned_user_id": me["id"], "force": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn approved_leave_unassigns_overlapping_shifts() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap().to_string();
+
+        let mut shift_ids = Vec::new();
+        for starts_at in ["2099-03-04T08:00:00Z", "2099-03-12T08:00:00Z"] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": starts_at,
+                    "ends_at": starts_at.replace("08:00", "12:00"),
+                    "period": "morning"
+                })),
+            )
+            .await;
+            let shift_id = shift["id"].as_str().unwrap().to_string();
+            send(
+                &app,
+                "POST",
+                &format!("/api/shifts/{shift_id}/assign"),
+                Some(&admin),
+                Some(serde_json::json!({ "assigned_user_id": member_id })),
+            )
+            .await;
+            shift_ids.push(shift_id);
+        }
+
+        let (status, leave) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/leave"),
+            Some(&member),
+            Some(serde_json::json!({
+                "starts_on": "2099-03-03",
+                "ends_on": "2099-03-10",
+                "reason": "holiday"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(leave["status"], "pending");
+        let approve_uri = format!("/api/leave/{}/approve", leave["id"].as_str().unwrap());
+
+        let (status, _) = send(&app, "POST", &approve_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, v) = send(&app, "POST", &approve_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["leave"]["status"], "approved");
+        assert_eq!(v["unassigned_shift_ids"], serde_json::json!([shift_ids[0]]));
+
+        let (_, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2099-03-01&to=2099-03-31"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        let assigned: Vec<_> = shifts
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["assigned_user_id"].clone())
+            .collect();
+        assert_eq!(
+            assigned,
+            [serde_json::Value::Null, member_id.clone().into()]
+        );
+
+        let (_, comments) = send(
+            &app,
+            "GET",
+            &format!("/api/shifts/{}/comments", shift_ids[0]),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert!(comments[0]["body"].as_str().unwrap().contains("holiday"));
+
+        let (status, _) = send(&app, "POST", &approve_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, endless) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/leave"),
+            Some(&member),
+            Some(serde_json::json!({
+                "starts_on": "2099-04-01",
+                "ends_on": NaiveDate::MAX,
+                "reason": "sabbatical"
+            })),
+        )
+        .await;
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/leave/{}/approve", endless["id"].as_str().unwrap()),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        // Non-admins are refused before learning the request's status.
+        let (status, _) = send(&app, "POST", &approve_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // The freed shift can't be claimed or assigned back during the leave.
+        let (status, v) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/claim", shift_ids[0]),
+            Some(&member),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(
+            v["error"].as_str().unwrap_or_default().contains("leave"),
+            "{v}"
+        );
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/assign", shift_ids[0]),
+            Some(&admin),
+            Some(serde_json::json!({ "assigned_user_id": member_id })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn rota_preview_commits_in_one_transaction() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+
+        for day in ["2099-03-02", "2099-03-03", "2099-03-04", "2099-03-05"] {
+            send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": format!("{day}T22:00:00Z"),
+                    "ends_at": format!("{day}T23:59:00Z"),
+                    "period": "night"
+                })),
+            )
+            .await;
+        }
+
+        let preview_uri = format!("/api/schedules/{schedule_id}/rota/preview");
+        let range = serde_json::json!({ "from": "2099-03-02", "to": "2099-03-08" });
+        let (status, _) = send(
+            &app,
+            "POST",
+            &preview_uri,
+            Some(&member),
+            Some(range.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, preview) = send(&app, "POST", &preview_uri, Some(&admin), Some(range)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(preview["assignments"].as_array().unwrap().len(), 4);
+        for load in preview["members"].as_array().unwrap() {
+            assert_eq!(load["before"]["shifts"], 0);
+            assert_eq!(load["after"]["unpleasant"], 2);
+        }
+
+        // Someone takes a shift between preview and commit.
+        let first = preview["assignments"][0]["shift_id"].as_str().unwrap();
+        send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{first}/claim"),
+            Some(&member),
+            None,
+        )
+        .await;
+        let commit_uri = format!("/api/schedules/{schedule_id}/rota/commit");
+        let (status, _) = send(
+            &app,
+            "POST",
+            &commit_uri,
+            Some(&admin),
+            Some(preview.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2099-03-02&to=2099-03-08"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        let assigned = shifts
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|s| !s["assigned_user_id"].is_null())
+            .count();
+        assert_eq!(assigned, 1);
+
+        let rest: Vec<_> = preview["assignments"].as_array().unwrap()[1..].to_vec();
+        // The batch is checked against itself: two nights 22 hours apart
+        // break a 24 hour rest, and nothing is written.
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap();
+        let (status, v) = send(
+            &app,
+            "POST",
+            &commit_uri,
+            Some(&admin),
+            Some(serde_json::json!({
+                "assignments": [
+                    { "shift_id": rest[0]["shift_id"], "user_id": member_id },
+                    { "shift_id": rest[1]["shift_id"], "user_id": member_id }
+                ],
+                "min_rest_hours": 24
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("rest"), "{v}");
+        let (status, _) = send(
+            &app,
+            "POST",
+            &commit_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "assignments": [], "min_rest_hours": 1_000_000_000 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Availability is checked again at commit time.
+        let away_on = rest[0]["starts_at"].as_str().unwrap()[..10].to_string();
+        let (status, away) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&member),
+            Some(serde_json::json!({ "starts_on": away_on, "ends_on": away_on })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, v) = send(
+            &app,
+            "POST",
+            &commit_uri,
+            Some(&admin),
+            Some(serde_json::json!({
+                "assignments": [{ "shift_id": rest[0]["shift_id"], "user_id": member_id }]
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("unavailable"), "{v}");
+        send(
+            &app,
+            "DELETE",
+            &format!(
+                "/api/schedules/{schedule_id}/availability/exceptions/{}",
+                away["id"].as_str().unwrap()
+            ),
+            Some(&member),
+            None,
+        )
+        .await;
+
+        let (status, committed) = send(
+            &app,
+            "POST",
+            &commit_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "assignments": rest })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(committed.as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn stats_report_workload_per_member() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&admin), None).await;
+        let admin_id = me["id"].as_str().unwrap().to_string();
+
+        // 2025-03-08 is a Saturday.
+        for (starts_at, ends_at, period, assign) in [
+            (
+                "2025-03-07T22:00:00Z",
+                "2025-03-08T06:00:00Z",
+                "sleep",
+                true,
+            ),
+            (
+                "2025-03-08T08:00:00Z",
+                "2025-03-08T12:00:00Z",
+                "morning",
+                true,
+            ),
+            (
+                "2025-03-09T08:00:00Z",
+                "2025-03-09T10:00:00Z",
+                "morning",
+                false,
+            ),
+        ] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": starts_at,
+                    "ends_at": ends_at,
+                    "period": period
+                })),
+            )
+            .await;
+            if assign {
+                send(
+                    &app,
+                    "POST",
+                    &format!("/api/shifts/{}/assign", shift["id"].as_str().unwrap()),
+                    Some(&admin),
+                    Some(serde_json::json!({ "assigned_user_id": admin_id, "force": true })),
+                )
+                .await;
+            }
+        }
+
+        let range = "from=2025-03-03&to=2025-03-09";
+        let (status, stats) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/stats?{range}"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["unassigned"]["hours"], 2.0);
+        assert_eq!(stats["share_hours"], 6.0);
+        let row = stats["members"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|m| m["user_id"] == admin_id.as_str())
+            .unwrap();
+        assert_eq!(row["hours"], 12.0);
+        assert_eq!(row["weekend"]["shifts"], 1);
+        assert_eq!(row["by_period"]["sleep"]["hours"], 8.0);
+        assert_eq!(row["deviation_hours"], 6.0);
 
-        // Register
         let res = app
             .clone()
             .oneshot(
                 axum::http::Request::builder()
-                    .method("POST")
-                    .uri("/api/auth/register")
-                    .header("content-type", "application/json")
-                    .body(axum::body::Body::from(
-                        r#"{"email":"a@example.com","password":"password1"}"#,
-                    ))
+                    .uri(format!("/api/schedules/{schedule_id}/stats.csv?{range}"))
+                    .header("authorization", format!("Bearer {admin}"))
+                    .body(axum::body::Body::empty())
                     .unwrap(),
             )
             .await
             .unwrap();
         assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], "text/csv; charset=utf-8");
         let body = res.into_body().collect().await.unwrap().to_bytes();
-        let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
-        let token = v.get("token").unwrap().as_str().unwrap().to_string();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.contains(",unassigned,1,2,"));
+    }
 
-        // Create schedule
-        let res = app
-            .clone()
-            .oneshot(
-                axum::http::Request::builder()
-                    .method("POST")
-                    .uri("/api/schedules")
-                    .header("content-type", "application/json")
-                    .header("authorization", format!("Bearer {token}"))
-                    .body(axum::body::Body::from(
-                        r#"{"name":"Care","subject_type":"pet","subject_name":"Puppy"}"#,
-                    ))
-                    .unwrap(),
+    #[tokio::test]
+    async fn gaps_compare_coverage_rules_with_shifts() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let coverage_uri = format!("/api/schedules/{schedule_id}/coverage");
+
+        // Someone every Monday and Tuesday sleep (2099-03-02 is a Monday).
+        for dow in [0, 1] {
+            let (status, _) = send(
+                &app,
+                "POST",
+                &coverage_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "dow": dow, "period": "sleep" })),
             )
-            .await
-            .unwrap();
-        assert_eq!(res.status(), StatusCode::CREATED);
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        let (status, _) = send(
+            &app,
+            "POST",
+            &coverage_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "dow": 0, "period": "sleep", "required": 2 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (_, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2099-03-02T22:00:00Z",
+                "ends_at": "2099-03-03T06:00:00Z",
+                "period": "sleep"
+            })),
+        )
+        .await;
+        let shift_id = shift["id"].as_str().unwrap().to_string();
+        let gaps_uri = format!("/api/schedules/{schedule_id}/gaps?from=2099-03-02&to=2099-03-08");
+        let (status, gaps) = send(&app, "GET", &gaps_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(gaps.as_array().unwrap().len(), 2);
+        assert_eq!(gaps[0]["date"], "2099-03-02");
+        assert_eq!(
+            gaps[0]["unassigned_shift_ids"],
+            serde_json::json!([shift_id])
+        );
+
+        let (_, me) = send(&app, "GET", "/api/me", Some(&admin), None).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{shift_id}/assign"),
+            Some(&admin),
+            Some(serde_json::json!({ "assigned_user_id": me["id"] })),
+        )
+        .await;
+        let (_, gaps) = send(&app, "GET", &gaps_uri, Some(&admin), None).await;
+        assert_eq!(gaps.as_array().unwrap().len(), 1);
+        assert_eq!(gaps[0]["date"], "2099-03-03");
+        assert_eq!(gaps[0]["missing"], 1);
+
+        for range in [
+            "from=2099-03-08&to=2099-03-02",
+            "from=2099-03-02&to=2199-03-02",
+            "from=%2B262142-12-31",
+            "from=%2B262142-12-31&to=%2B262142-12-31",
+        ] {
+            let (status, _) = send(
+                &app,
+                "GET",
+                &format!("/api/schedules/{schedule_id}/gaps?{range}"),
+                Some(&admin),
+                None,
+            )
+            .await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{range}");
+        }
+    }
+
+    #[tokio::test]
+    async fn shifts_take_several_assignees_up_to_capacity() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let alice = register_user(&app, "alice@example.com").await;
+        let bob = register_user(&app, "bob@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let mut ids = Vec::new();
+        for (email, token) in [("alice@example.com", &alice), ("bob@example.com", &bob)] {
+            send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/members"),
+                Some(&admin),
+                Some(serde_json::json!({ "email": email, "role": "user" })),
+            )
+            .await;
+            let (_, me) = send(&app, "GET", "/api/me", Some(token), None).await;
+            ids.push(me["id"].as_str().unwrap().to_string());
+        }
+
+        let (status, shift) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/shifts"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "starts_at": "2099-03-04T08:00:00Z",
+                "ends_at": "2099-03-04T12:00:00Z",
+                "period": "morning",
+                "capacity": 2
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(shift["capacity"], 2);
+        assert_eq!(shift["assignees"], serde_json::json!([]));
+        assert!(shift["assigned_user_id"].is_null());
+        let uri = format!("/api/shifts/{}/assignees", shift["id"].as_str().unwrap());
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &uri,
+            Some(&alice),
+            Some(serde_json::json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &uri,
+            Some(&bob),
+            Some(serde_json::json!({ "lead": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, shift) = send(
+            &app,
+            "POST",
+            &uri,
+            Some(&admin),
+            Some(serde_json::json!({ "user_id": ids[1], "lead": true })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let assignees: Vec<_> = shift["assignees"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| (a["user_id"].clone(), a["lead"].clone()))
+            .collect();
+        assert_eq!(
+            assignees,
+            [
+                (ids[1].clone().into(), true.into()),
+                (ids[0].clone().into(), false.into())
+            ]
+        );
+        // Older clients see the lead as the single assignee.
+        assert_eq!(shift["assigned_user_id"], ids[1]);
+
+        let (_, admin_me) = send(&app, "GET", "/api/me", Some(&admin), None).await;
+        let (status, v) = send(
+            &app,
+            "POST",
+            &uri,
+            Some(&admin),
+            Some(serde_json::json!({ "user_id": admin_me["id"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(v["error"].as_str().unwrap().contains("shift is full"));
+
+        let (_, open) = send(
+            &app,
+            "GET",
+            "/api/open-shifts?from=2099-03-01T00:00:00Z&to=2099-03-31T00:00:00Z",
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(open, serde_json::json!([]));
+
+        let (status, _) = send(
+            &app,
+            "DELETE",
+            &format!("{uri}/{}", ids[0]),
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(
+            &app,
+            "DELETE",
+            &format!("{uri}/{}", ids[0]),
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let (_, open) = send(
+            &app,
+            "GET",
+            "/api/open-shifts?from=2099-03-01T00:00:00Z&to=2099-03-31T00:00:00Z",
+            Some(&alice),
+            None,
+        )
+        .await;
+        assert_eq!(open.as_array().unwrap().len(), 1);
+        assert_eq!(open[0]["assignees"].as_array().unwrap().len(), 1);
+
+        // The legacy endpoint only takes the free place on a half-filled
+        // shift, leaving the co-assignee where they are.
+        let assign_uri = format!("/api/shifts/{}/assign", shift["id"].as_str().unwrap());
+        let (status, _) = send(
+            &app,
+            "POST",
+            &assign_uri,
+            Some(&alice),
+            Some(serde_json::json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2099-03-04&to=2099-03-04"),
+            Some(&alice),
+            None,
+        )
+        .await;
+        let mut on_shift: Vec<_> = shifts[0]["assignees"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["user_id"].as_str().unwrap().to_string())
+            .collect();
+        on_shift.sort();
+        let mut both = ids.clone();
+        both.sort();
+        assert_eq!(on_shift, both);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &assign_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "assigned_user_id": admin_me["id"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, v) = send(
+            &app,
+            "PATCH",
+            &format!("/api/shifts/{}", shift["id"].as_str().unwrap()),
+            Some(&admin),
+            Some(serde_json::json!({ "capacity": 0 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{v}");
+    }
+
+    #[tokio::test]
+    async fn check_in_and_out_with_audited_corrections() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap().to_string();
+
+        // One shift under way, one whose grace period ran out unattended.
+        let now = Utc::now();
+        let mut shift_ids = Vec::new();
+        for started_mins_ago in [5, 60] {
+            let starts_at = now - chrono::Duration::minutes(started_mins_ago);
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": starts_at,
+                    "ends_at": starts_at + chrono::Duration::hours(4),
+                    "period": "morning"
+                })),
+            )
+            .await;
+            let shift_id = shift["id"].as_str().unwrap().to_string();
+            send(
+                &app,
+                "POST",
+                &format!("/api/shifts/{shift_id}/assign"),
+                Some(&admin),
+                Some(serde_json::json!({ "assigned_user_id": member_id, "force": true })),
+            )
+            .await;
+            shift_ids.push(shift_id);
+        }
+
+        let check_in = format!("/api/shifts/{}/check-in", shift_ids[0]);
+        let (status, _) = send(&app, "POST", &check_in, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, v) = send(&app, "POST", &check_in, Some(&member), None).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert!(v["checked_in_at"].is_string());
+        let (status, _) = send(&app, "POST", &check_in, Some(&member), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let attendance_uri = format!("/api/shifts/{}/attendance", shift_ids[0]);
+        let (_, v) = send(&app, "GET", &attendance_uri, Some(&member), None).await;
+        assert_eq!(v["status"], "in_progress");
+        let (status, v) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/check-out", shift_ids[0]),
+            Some(&member),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        let (_, v) = send(&app, "GET", &attendance_uri, Some(&member), None).await;
+        assert_eq!(v["status"], "completed");
+
+        let range = "from=2000-01-01T00:00:00Z&to=2999-01-01T00:00:00Z";
+        let report_uri = format!("/api/schedules/{schedule_id}/attendance?{range}");
+        let (status, _) = send(&app, "GET", &report_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, report) = send(&app, "GET", &report_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["missed"], 1);
+        assert_eq!(report["shifts"][0]["shift_id"], shift_ids[1].as_str());
+        assert_eq!(report["shifts"][0]["status"], "missed");
+        assert_eq!(report["shifts"][0]["planned_minutes"], 240);
+
+        // The member forgot to check in; an admin fills in the times.
+        let correct_uri = format!("/api/shifts/{}/attendance/{member_id}", shift_ids[1]);
+        let times = serde_json::json!({
+            "checked_in_at": now - chrono::Duration::minutes(55),
+            "checked_out_at": now - chrono::Duration::minutes(10),
+            "reason": "phone was flat"
+        });
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &correct_uri,
+            Some(&member),
+            Some(times.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, v) = send(
+            &app,
+            "PATCH",
+            &correct_uri,
+            Some(&admin),
+            Some(serde_json::json!({
+                "checked_in_at": now - chrono::Duration::minutes(5),
+                "checked_out_at": now - chrono::Duration::minutes(10),
+                "reason": "typo"
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(v["error"].as_str().unwrap().contains("checked_out_at"));
+        let (status, v) = send(&app, "PATCH", &correct_uri, Some(&admin), Some(times)).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert!(v["previous_checked_in_at"].is_null());
+
+        let (_, v) = send(
+            &app,
+            "GET",
+            &format!("/api/shifts/{}/attendance", shift_ids[1]),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(v["status"], "completed");
+        assert_eq!(v["worked_minutes"], 45);
+        assert_eq!(v["corrections"].as_array().unwrap().len(), 1);
+        assert_eq!(v["corrections"][0]["reason"], "phone was flat");
+    }
+
+    #[tokio::test]
+    async fn shifts_copy_the_schedule_checklist() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let member = register_user(&app, "member@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "member@example.com", "role": "user" })),
+        )
+        .await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&member), None).await;
+        let member_id = me["id"].as_str().unwrap().to_string();
+
+        let tasks_uri = format!("/api/schedules/{schedule_id}/tasks");
+        let (status, _) = send(
+            &app,
+            "POST",
+            &tasks_uri,
+            Some(&member),
+            Some(serde_json::json!({ "title": "Feed" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &tasks_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "title": "Meds", "due_time": "9am" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        for body in [
+            serde_json::json!({ "title": "Feed" }),
+            serde_json::json!({ "title": "Meds", "due_time": "09:00" }),
+        ] {
+            let (status, v) = send(&app, "POST", &tasks_uri, Some(&admin), Some(body)).await;
+            assert_eq!(status, StatusCode::CREATED, "{v}");
+        }
+
+        // Only the morning shift covers 09:00.
+        let mut shift_ids = Vec::new();
+        for (start, end, period) in [
+            ("2030-01-07T08:00:00Z", "2030-01-07T12:00:00Z", "morning"),
+            ("2030-01-07T13:00:00Z", "2030-01-07T17:00:00Z", "afternoon"),
+        ] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({ "starts_at": start, "ends_at": end, "period": period })),
+            )
+            .await;
+            shift_ids.push(shift["id"].as_str().unwrap().to_string());
+        }
+        let morning_uri = format!("/api/shifts/{}/tasks", shift_ids[0]);
+        let (status, tasks) = send(&app, "GET", &morning_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let titles: Vec<_> = tasks
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, ["Meds", "Feed"]);
+        assert_eq!(tasks[0]["due_at"], "2030-01-07T09:00:00Z");
+        let meds_uri = format!("{morning_uri}/{}", tasks[0]["id"].as_str().unwrap());
+        let (_, tasks) = send(
+            &app,
+            "GET",
+            &format!("/api/shifts/{}/tasks", shift_ids[1]),
+            Some(&member),
+            None,
+        )
+        .await;
+        assert_eq!(tasks.as_array().unwrap().len(), 1);
+
+        let (status, extra) = send(
+            &app,
+            "POST",
+            &morning_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "title": "Buy food" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(extra["position"], 2);
+
+        // Only the shift's assignees (or an admin) tick items off.
+        let done = Some(serde_json::json!({ "done": true }));
+        let (status, _) = send(&app, "PATCH", &meds_uri, Some(&member), done.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/assign", shift_ids[0]),
+            Some(&admin),
+            Some(serde_json::json!({ "assigned_user_id": member_id })),
+        )
+        .await;
+        let (status, v) = send(&app, "PATCH", &meds_uri, Some(&member), done).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["done_by"], member_id.as_str());
+        assert!(v["done_at"].is_string());
+
+        let incomplete_uri =
+            format!("/api/schedules/{schedule_id}/tasks/incomplete?from=2030-01-07&to=2030-01-07");
+        let (status, _) = send(&app, "GET", &incomplete_uri, Some(&member), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, v) = send(&app, "GET", &incomplete_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let items: Vec<_> = v
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| (t["title"].as_str().unwrap(), t["period"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                ("Feed", "morning"),
+                ("Buy food", "morning"),
+                ("Feed", "afternoon")
+            ]
+        );
+        assert_eq!(v[0]["assignee_ids"][0], member_id.as_str());
+        assert_eq!(v[0]["overdue"], false);
+
+        // Leaving `period` out keeps it; `null` clears it.
+        let (_, walk) = send(
+            &app,
+            "POST",
+            &tasks_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "title": "Walk", "period": "night" })),
+        )
+        .await;
+        let walk_uri = format!("{tasks_uri}/{}", walk["id"].as_str().unwrap());
+        let (status, walk) = send(
+            &app,
+            "PATCH",
+            &walk_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "title": "Long walk" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(walk["period"], "night");
+        let (status, walk) = send(
+            &app,
+            "PATCH",
+            &walk_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "period": null })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(walk["period"].is_null());
+        assert_eq!(walk["title"], "Long walk");
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_rotate_and_sessions_revoke() {
+        let app = router();
+        register_user(&app, "phone@example.com").await;
+        let login = serde_json::json!({ "email": "phone@example.com", "password": "password1" });
+        let (status, phone) =
+            send(&app, "POST", "/api/auth/login", None, Some(login.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(phone["expires_in"], ACCESS_TOKEN_MINUTES * 60);
+
+        let refresh = |token: &serde_json::Value| {
+            Some(serde_json::json!({ "refresh_token": token["refresh_token"] }))
+        };
+        let (status, renewed) =
+            send(&app, "POST", "/api/auth/refresh", None, refresh(&phone)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(renewed["refresh_token"], phone["refresh_token"]);
+        let renewed_token = renewed["token"].as_str().unwrap();
+        let (status, _) = send(&app, "GET", "/api/me", Some(renewed_token), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Replaying a spent refresh token kills the whole chain.
+        let (status, _) = send(&app, "POST", "/api/auth/refresh", None, refresh(&phone)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, "GET", "/api/me", Some(renewed_token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, "POST", "/api/auth/refresh", None, refresh(&renewed)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (_, laptop) = send(&app, "POST", "/api/auth/login", None, Some(login.clone())).await;
+        let (_, tablet) = send(&app, "POST", "/api/auth/login", None, Some(login)).await;
+        let laptop_token = laptop["token"].as_str().unwrap();
+        let tablet_token = tablet["token"].as_str().unwrap();
+        let (status, _) = send(&app, "POST", "/api/auth/logout", Some(laptop_token), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "GET", "/api/me", Some(laptop_token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, "POST", "/api/auth/refresh", None, refresh(&laptop)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        // The registration session and the tablet are still signed in.
+        let (status, v) = send(
+            &app,
+            "POST",
+            "/api/auth/logout-all",
+            Some(tablet_token),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["revoked"], 2);
+        let (status, _) = send(&app, "GET", "/api/me", Some(tablet_token), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn email_verification_and_password_reset() {
+        let (app, mailer) = router_with_mailer();
+        let admin = register_user(&app, "admin@example.com").await;
+        let alice = register_user(&app, "alice@example.com").await;
+        let (_, me) = send(&app, "GET", "/api/me", Some(&alice), None).await;
+        assert!(me["email_verified_at"].is_null());
+
+        // Unverified, alice can join one schedule she didn't create.
+        let member = Some(serde_json::json!({ "email": "alice@example.com", "role": "user" }));
+        let first = create_schedule_for(&app, &admin).await;
+        let second = create_schedule_for(&app, &admin).await;
+        let members_uri = |id: &str| format!("/api/schedules/{id}/members");
+        let (status, _) = send(
+            &app,
+            "POST",
+            &members_uri(&first),
+            Some(&admin),
+            member.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, v) = send(
+            &app,
+            "POST",
+            &members_uri(&second),
+            Some(&admin),
+            member.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(v["error"].as_str().unwrap().contains("verify"));
+
+        let verify =
+            Some(serde_json::json!({ "token": mailed_token(&mailer, "alice@example.com") }));
+        let (status, _) = send(&app, "POST", "/api/auth/verify-email", None, verify.clone()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "POST", "/api/auth/verify-email", None, verify).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, "POST", &members_uri(&second), Some(&admin), member).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        // Unknown addresses get the same answer and no email.
+        let sent = mailer.sent().len();
+        let (status, _) = send(
+            &app,
+            "POST",
+            "/api/auth/password-reset",
+            None,
+            Some(serde_json::json!({ "email": "nobody@example.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(mailer.sent().len(), sent);
+        send(
+            &app,
+            "POST",
+            "/api/auth/password-reset",
+            None,
+            Some(serde_json::json!({ "email": "Alice@example.com" })),
+        )
+        .await;
+        let token = mailed_token(&mailer, "alice@example.com");
+        let confirm =
+            |password: &str| Some(serde_json::json!({ "token": token, "password": password }));
+        let confirm_uri = "/api/auth/password-reset/confirm";
+        let (status, _) = send(&app, "POST", confirm_uri, None, confirm("short")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&app, "POST", confirm_uri, None, confirm("new-password")).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "POST", confirm_uri, None, confirm("other-password")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Resetting signs out every session; the new password works.
+        let (status, _) = send(&app, "GET", "/api/me", Some(&alice), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let login = |password: &str| {
+            Some(serde_json::json!({ "email": "alice@example.com", "password": password }))
+        };
+        let (status, _) = send(&app, "POST", "/api/auth/login", None, login("password1")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = send(&app, "POST", "/api/auth/login", None, login("new-password")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn invites_by_email_and_link() {
+        let (app, mailer) = router_with_mailer();
+        let admin = register_user(&app, "admin@example.com").await;
+        let carol = register_user(&app, "carol@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let invites_uri = format!("/api/schedules/{schedule_id}/invites");
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &invites_uri,
+            Some(&carol),
+            Some(serde_json::json!({ "role": "user" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        for bad in [
+            serde_json::json!({ "role": "user", "max_uses": 0 }),
+            serde_json::json!({ "role": "user", "email": "bob@example.com", "max_uses": 2 }),
+        ] {
+            let (status, _) = send(&app, "POST", &invites_uri, Some(&admin), Some(bad)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+
+        // Bob hasn't registered yet; the invite waits for him.
+        let (status, invite) = send(
+            &app,
+            "POST",
+            &invites_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "role": "user", "email": "Bob@example.com" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED, "{invite}");
+        assert_eq!(invite["max_uses"], 1);
+        let token = mailed_token(&mailer, "bob@example.com");
+        assert!(invite["url"].as_str().unwrap().ends_with(&token));
+        let (status, preview) =
+            send(&app, "GET", &format!("/api/invites/{token}"), None, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(preview["subject_name"], "Puppy");
+
+        let accept_uri = format!("/api/invites/{token}/accept");
+        let (status, _) = send(&app, "POST", &accept_uri, Some(&carol), None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let bob = register_user(&app, "bob@example.com").await;
+        let (status, v) = send(&app, "POST", &accept_uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["role"], "user");
+        let (_, me) = send(&app, "GET", "/api/me", Some(&bob), None).await;
+        assert!(me["email_verified_at"].is_string());
+        let (status, _) = send(&app, "POST", &accept_uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // A single-use admin link, and one revoked before anyone used it.
+        let mut links = Vec::new();
+        for _ in 0..2 {
+            let (_, link) = send(
+                &app,
+                "POST",
+                &invites_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "role": "admin", "max_uses": 1 })),
+            )
+            .await;
+            links.push(link);
+        }
+        let (_, pending) = send(&app, "GET", &invites_uri, Some(&admin), None).await;
+        assert_eq!(pending.as_array().unwrap().len(), 2);
+        let link_token = |link: &serde_json::Value| {
+            link["url"]
+                .as_str()
+                .unwrap()
+                .rsplit('=')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        let (status, v) = send(
+            &app,
+            "POST",
+            &format!("/api/invites/{}/accept", link_token(&links[0])),
+            Some(&carol),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["role"], "admin");
+        let revoke_uri = format!("{invites_uri}/{}", links[1]["id"].as_str().unwrap());
+        let (status, _) = send(&app, "DELETE", &revoke_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (_, pending) = send(&app, "GET", &invites_uri, Some(&admin), None).await;
+        assert!(pending.as_array().unwrap().is_empty());
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/invites/{}/accept", link_token(&links[1])),
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (_, members) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(members.as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn removing_members_keeps_an_admin_and_hands_off_shifts() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let mut ids = HashMap::new();
+        let mut tokens = HashMap::new();
+        for name in ["admin", "bob", "carol", "dave"] {
+            let email = format!("{name}@example.com");
+            let token = if name == "admin" {
+                admin.clone()
+            } else {
+                let token = register_user(&app, &email).await;
+                send(
+                    &app,
+                    "POST",
+                    &format!("/api/schedules/{schedule_id}/members"),
+                    Some(&admin),
+                    Some(serde_json::json!({ "email": email, "role": "user" })),
+                )
+                .await;
+                token
+            };
+            let (_, me) = send(&app, "GET", "/api/me", Some(&token), None).await;
+            ids.insert(name, me["id"].as_str().unwrap().to_string());
+            tokens.insert(name, token);
+        }
+        let member_uri = |name: &str| format!("/api/schedules/{schedule_id}/members/{}", ids[name]);
+        let leave_uri = format!("/api/schedules/{schedule_id}/membership");
+
+        // The only admin can neither step down nor leave.
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{}/role", member_uri("admin")),
+            Some(&admin),
+            Some(serde_json::json!({ "role": "user" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(&app, "DELETE", &leave_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let mut shift_ids = Vec::new();
+        for (starts_at, name) in [
+            ("2000-01-03T08:00:00Z", "bob"),
+            ("2099-01-05T08:00:00Z", "bob"),
+            ("2099-01-06T08:00:00Z", "bob"),
+            ("2099-01-08T08:00:00Z", "dave"),
+            ("2099-01-09T08:00:00Z", ""),
+        ] {
+            let (_, shift) = send(
+                &app,
+                "POST",
+                &format!("/api/schedules/{schedule_id}/shifts"),
+                Some(&admin),
+                Some(serde_json::json!({
+                    "starts_at": starts_at,
+                    "ends_at": starts_at.replace("08:00", "12:00"),
+                    "period": "morning"
+                })),
+            )
+            .await;
+            let shift_id = shift["id"].as_str().unwrap().to_string();
+            if !name.is_empty() {
+                send(
+                    &app,
+                    "POST",
+                    &format!("/api/shifts/{shift_id}/assign"),
+                    Some(&admin),
+                    Some(serde_json::json!({ "assigned_user_id": ids[name], "force": true })),
+                )
+                .await;
+            }
+            shift_ids.push(shift_id);
+        }
+
+        // Members may hand their own shifts on when they leave.
+        let transfer =
+            serde_json::json!({ "future_shifts": "transfer", "transfer_to": ids["carol"] });
+        let (status, v) = send(
+            &app,
+            "DELETE",
+            &leave_uri,
+            Some(&tokens["dave"]),
+            Some(transfer.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(
+            v["transferred_shift_ids"],
+            serde_json::json!([shift_ids[3]])
+        );
+
+        // Bob's pending claim and swap don't outlive his membership.
+        send(
+            &app,
+            "PATCH",
+            &format!("/api/schedules/{schedule_id}"),
+            Some(&admin),
+            Some(serde_json::json!({ "claim_policy": "approval" })),
+        )
+        .await;
+        let (status, claim) = send(
+            &app,
+            "POST",
+            &format!("/api/shifts/{}/claim", shift_ids[4]),
+            Some(&tokens["bob"]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, swap) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/swaps"),
+            Some(&tokens["bob"]),
+            Some(serde_json::json!({ "shift_id": shift_ids[2], "counterpart_id": ids["carol"] })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = send(
+            &app,
+            "DELETE",
+            &member_uri("carol"),
+            Some(&tokens["bob"]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(
+            &app,
+            "DELETE",
+            &member_uri("bob"),
+            Some(&admin),
+            Some(serde_json::json!({ "future_shifts": "transfer" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Carol is away for the last shift, so it goes back to the pool.
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/availability/exceptions"),
+            Some(&tokens["carol"]),
+            Some(serde_json::json!({ "starts_on": "2099-01-06", "ends_on": "2099-01-06" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, v) = send(
+            &app,
+            "DELETE",
+            &member_uri("bob"),
+            Some(&admin),
+            Some(transfer),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(
+            v["transferred_shift_ids"],
+            serde_json::json!([shift_ids[1]])
+        );
+        assert_eq!(v["unassigned_shift_ids"], serde_json::json!([shift_ids[2]]));
+        assert_eq!(v["skipped_transfers"][0]["shift_id"], shift_ids[2]);
+        assert!(v["skipped_transfers"][0]["reason"]
+            .as_str()
+            .unwrap()
+            .contains("unavailable"));
+        let (_, shifts) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/shifts?from=2000-01-01&to=2099-12-31"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        let assigned: Vec<_> = shifts
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["assigned_user_id"].clone())
+            .collect();
+        assert_eq!(
+            assigned,
+            [
+                serde_json::json!(ids["bob"]),
+                serde_json::json!(ids["carol"]),
+                serde_json::Value::Null,
+                serde_json::json!(ids["carol"]),
+                serde_json::Value::Null
+            ]
+        );
+        let (status, _) = send(&app, "DELETE", &member_uri("bob"), Some(&admin), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/claims/{}/approve", claim["id"].as_str().unwrap()),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, claims) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/claims?status=rejected"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(claims[0]["id"], claim["id"]);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/swaps/{}/accept", swap["id"].as_str().unwrap()),
+            Some(&tokens["carol"]),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (_, swaps) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/swaps?status=cancelled"),
+            Some(&admin),
+            None,
+        )
+        .await;
+        assert_eq!(swaps[0]["id"], swap["id"]);
+
+        // Once carol is an admin too, the original admin may leave.
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{}/role", member_uri("carol")),
+            Some(&admin),
+            Some(serde_json::json!({ "role": "admin" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, v) = send(&app, "DELETE", &leave_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["unassigned_shift_ids"], serde_json::json!([]));
+        let (_, members) = send(
+            &app,
+            "GET",
+            &format!("/api/schedules/{schedule_id}/members"),
+            Some(&tokens["carol"]),
+            None,
+        )
+        .await;
+        assert_eq!(members.as_array().unwrap().len(), 1);
+        let (status, _) = send(&app, "DELETE", &leave_uri, Some(&tokens["carol"]), None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn schedules_update_archive_and_delete() {
+        let app = router();
+        let root = register_user(&app, "root@example.com").await;
+        let admin = register_user(&app, "admin@example.com").await;
+        let bob = register_user(&app, "bob@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let schedule_uri = format!("/api/schedules/{schedule_id}");
+        send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/members"),
+            Some(&admin),
+            Some(serde_json::json!({ "email": "bob@example.com", "role": "user" })),
+        )
+        .await;
+
+        let rename = serde_json::json!({ "name": "Walks", "subject_name": "Rex" });
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &schedule_uri,
+            Some(&bob),
+            Some(rename.clone()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &schedule_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "name": " " })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, v) = send(&app, "PATCH", &schedule_uri, Some(&admin), Some(rename)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(v["name"], "Walks");
+        assert_eq!(v["subject_type"], "pet");
+        assert_eq!(v["subject_name"], "Rex");
+
+        send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/tasks"),
+            Some(&admin),
+            Some(serde_json::json!({ "title": "Feed" })),
+        )
+        .await;
+        let new_shift = serde_json::json!({
+            "starts_at": "2099-01-05T08:00:00Z",
+            "ends_at": "2099-01-05T12:00:00Z",
+            "period": "morning"
+        });
+        let (_, shift) = send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/shifts"),
+            Some(&admin),
+            Some(new_shift.clone()),
+        )
+        .await;
+        let comments_uri = format!("/api/shifts/{}/comments", shift["id"].as_str().unwrap());
+        let comment = serde_json::json!({ "body": "Lead is in the hall" });
+        send(
+            &app,
+            "POST",
+            &comments_uri,
+            Some(&admin),
+            Some(comment.clone()),
+        )
+        .await;
+        send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/invites"),
+            Some(&admin),
+            Some(serde_json::json!({ "role": "user" })),
+        )
+        .await;
+
+        // Archived: hidden from lists by default, readable, but frozen.
+        let archive_uri = format!("{schedule_uri}/archive");
+        let (status, _) = send(&app, "POST", &archive_uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, v) = send(&app, "POST", &archive_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(v["archived_at"].is_string());
+        let (_, list) = send(&app, "GET", "/api/schedules", Some(&bob), None).await;
+        assert_eq!(list, serde_json::json!([]));
+        let (_, list) = send(
+            &app,
+            "GET",
+            "/api/schedules?include_archived=true",
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(list[0]["schedule"]["id"], schedule_id.as_str());
+        let (status, comments) = send(&app, "GET", &comments_uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(comments.as_array().unwrap().len(), 1);
+        let (status, _) = send(&app, "POST", &comments_uri, Some(&admin), Some(comment)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = send(
+            &app,
+            "PATCH",
+            &schedule_uri,
+            Some(&root),
+            Some(serde_json::json!({ "name": "Root" })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, v) = send(&app, "DELETE", &archive_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(v["archived_at"].is_null());
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/shifts"),
+            Some(&admin),
+            Some(new_shift),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        // Only the creator or a superadmin may delete, even among admins.
+        send(
+            &app,
+            "POST",
+            &format!("{schedule_uri}/members/{}/role", {
+                let (_, me) = send(&app, "GET", "/api/me", Some(&bob), None).await;
+                me["id"].as_str().unwrap().to_string()
+            }),
+            Some(&admin),
+            Some(serde_json::json!({ "role": "admin" })),
+        )
+        .await;
+        let (status, _) = send(&app, "DELETE", &schedule_uri, Some(&bob), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, v) = send(&app, "DELETE", &schedule_uri, Some(&admin), None).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["members"], 2);
+        assert_eq!(v["shifts"], 2);
+        assert_eq!(v["comments"], 1);
+        assert_eq!(v["schedule_tasks"], 1);
+        assert_eq!(v["shift_tasks"], 2);
+        assert_eq!(v["invites"], 1);
+        let (_, list) = send(
+            &app,
+            "GET",
+            "/api/schedules?include_archived=true",
+            Some(&bob),
+            None,
+        )
+        .await;
+        assert_eq!(list, serde_json::json!([]));
+        let (status, _) = send(&app, "DELETE", &schedule_uri, Some(&root), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn multi_week_template_advances_week_by_week() {
+        let app = router();
+        let admin = register_user(&app, "admin@example.com").await;
+        let schedule_id = create_schedule_for(&app, &admin).await;
+        let (_, template) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Fortnightly",
+                "definition": {
+                    "cycle_weeks": 2,
+                    "slots": [
+                        { "dow": 0, "week": 0, "period": "morning", "start": "08:00", "end": "12:00" },
+                        { "dow": 2, "week": 1, "period": "night", "start": "18:00", "end": "22:00" }
+                    ]
+                }
+            })),
+        )
+        .await;
+        let apply_uri = format!(
+            "/api/schedules/{schedule_id}/templates/{}/apply",
+            template["id"].as_str().unwrap()
+        );
+
+        // Without an anchor, cycle week 0 is the week the template was made.
+        let this_monday = default_anchor(Utc::now(), chrono_tz::Tz::UTC);
+        let mut periods = Vec::new();
+        for weeks_ahead in [1, 2] {
+            let week_start = this_monday + chrono::Duration::weeks(weeks_ahead);
+            let (status, created) = send(
+                &app,
+                "POST",
+                &apply_uri,
+                Some(&admin),
+                Some(serde_json::json!({ "week_start": week_start.to_string() })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED, "{created}");
+            let created = created.as_array().unwrap();
+            assert_eq!(created.len(), 1);
+            periods.push(created[0]["period"].clone());
+        }
+        assert_eq!(periods, ["night", "morning"]);
+        let (status, _) = send(
+            &app,
+            "POST",
+            &apply_uri,
+            Some(&admin),
+            Some(serde_json::json!({ "week_start": NaiveDate::MAX.to_string() })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send(
+            &app,
+            "POST",
+            &format!("/api/schedules/{schedule_id}/templates"),
+            Some(&admin),
+            Some(serde_json::json!({
+                "name": "Off by two",
+                "definition": { "cycle_weeks": 2, "anchor": "2025-01-08", "slots": [] }
+            })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 }
@@ -1,4 +1,9 @@
-use buddy_schedule_api::{config::Config, repo::PgRepo, AppState};
+use buddy_schedule_api::{
+    config::Config,
+    mailer::{LogMailer, Mailer, SmtpMailer},
+    repo::PgRepo,
+    AppState,
+};
 use sqlx::postgres::PgPoolOptions;
 use std::sync::Arc;
 use tracing_subscriber::EnvFilter;
@@ -24,10 +29,20 @@ async fn main() -> Result<(), String> {
         .await
         .map_err(|e| format!("Failed to run migrations: {e}"))?;
 
+    let mailer: Arc<dyn Mailer> = match &cfg.smtp_url {
+        Some(url) => Arc::new(SmtpMailer::new(url, &cfg.mail_from)?),
+        None => {
+            tracing::warn!("SMTP_URL not set; emails are only logged");
+            Arc::new(LogMailer::new(cfg.mail_log_file.clone().map(Into::into)))
+        }
+    };
+
     let state = AppState {
         repo: Arc::new(PgRepo::new(pool)),
         jwt: buddy_schedule_api::auth::JwtKeys::new(&cfg.jwt_secret),
         cors_origin: cfg.cors_origin.clone(),
+        mailer,
+        public_url: cfg.public_url.clone(),
     };
 
     let app = buddy_schedule_api::build_router(state);
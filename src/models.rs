@@ -1,5 +1,5 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use uuid::Uuid;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -30,7 +30,7 @@ impl TryFrom<&str> for ScheduleRole {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum Period {
     Morning,
@@ -48,6 +48,28 @@ impl Period {
             Period::Sleep => "sleep",
         }
     }
+
+    /// Local start and end hour of the period under the web UI defaults;
+    /// sleep runs past midnight.
+    pub fn default_hours(self) -> (u32, u32) {
+        match self {
+            Period::Morning => (8, 12),
+            Period::Afternoon => (12, 18),
+            Period::Night => (18, 22),
+            Period::Sleep => (22, 8),
+        }
+    }
+
+    /// Period a shift starting at local `hour` belongs to, using the same
+    /// boundaries as the web UI defaults (08/12/18/22).
+    pub fn for_start_hour(hour: u32) -> Self {
+        match hour {
+            8..=11 => Period::Morning,
+            12..=17 => Period::Afternoon,
+            18..=21 => Period::Night,
+            _ => Period::Sleep,
+        }
+    }
 }
 
 impl TryFrom<&str> for Period {
@@ -64,40 +86,184 @@ impl TryFrom<&str> for Period {
     }
 }
 
+/// How members pick up unassigned shifts.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimPolicy {
+    /// Members claim open shifts directly and can self-assign any free place.
+    Open,
+    /// Members can only take shifts nobody holds; the first claim wins.
+    FirstCome,
+    /// Claims wait for an admin to approve one of them.
+    Approval,
+}
+
+impl ClaimPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClaimPolicy::Open => "open",
+            ClaimPolicy::FirstCome => "first_come",
+            ClaimPolicy::Approval => "approval",
+        }
+    }
+}
+
+impl TryFrom<&str> for ClaimPolicy {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "open" => Ok(ClaimPolicy::Open),
+            "first_come" => Ok(ClaimPolicy::FirstCome),
+            "approval" => Ok(ClaimPolicy::Approval),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub is_superadmin: bool,
+    /// When the user followed their verification link; `None` until then.
+    pub email_verified_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
 }
 
+/// What a mailed account token lets its holder do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+impl TokenPurpose {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailVerification => "email_verification",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuthSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_refreshed_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct Schedule {
     pub id: Uuid,
     pub name: String,
     pub subject_type: String,
     pub subject_name: String,
+    pub timezone: String,
+    /// Whether swaps agreed between members also need an admin's approval.
+    pub swap_requires_approval: bool,
+    pub claim_policy: ClaimPolicy,
+    /// Minutes after a shift starts before it counts as missed.
+    pub check_in_grace_minutes: i32,
+    /// Set while the schedule is archived, when it can't be changed.
+    pub archived_at: Option<DateTime<Utc>>,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
 }
 
+/// Rows removed along with a deleted schedule, by what they were.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScheduleDeletion {
+    pub members: i64,
+    pub shifts: i64,
+    pub comments: i64,
+    pub templates: i64,
+    pub swaps: i64,
+    pub claims: i64,
+    /// Weekly unavailability and away dates.
+    pub availability: i64,
+    pub leave_requests: i64,
+    pub coverage_rules: i64,
+    pub attendance: i64,
+    pub attendance_corrections: i64,
+    pub schedule_tasks: i64,
+    pub shift_tasks: i64,
+    pub invites: i64,
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct ScheduleWithRole {
     pub schedule: Schedule,
     pub role: ScheduleRole,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug)]
 pub struct Shift {
     pub id: Uuid,
     pub schedule_id: Uuid,
     pub starts_at: DateTime<Utc>,
     pub ends_at: DateTime<Utc>,
     pub period: Period,
-    pub assigned_user_id: Option<Uuid>,
+    /// How many members the shift needs.
+    pub capacity: i16,
+    /// Lead first, then in the order they were assigned.
+    pub assignees: Vec<ShiftAssignee>,
+    /// Template and cycle week start this shift was generated from, if any.
+    pub template_id: Option<Uuid>,
+    pub template_week: Option<NaiveDate>,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
+    /// Last change to the time, period or capacity.
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftAssignee {
+    pub user_id: Uuid,
+    pub lead: bool,
+    pub assigned_at: DateTime<Utc>,
+}
+
+impl Shift {
+    /// The lead, or else the first assignee: what clients that predate
+    /// multiple assignees see as `assigned_user_id`.
+    pub fn assigned_user_id(&self) -> Option<Uuid> {
+        self.assignees.first().map(|a| a.user_id)
+    }
+
+    pub fn is_assigned(&self, user_id: Uuid) -> bool {
+        self.assignees.iter().any(|a| a.user_id == user_id)
+    }
+
+    /// Places still to fill.
+    pub fn free_places(&self) -> usize {
+        usize::try_from(self.capacity)
+            .unwrap_or(0)
+            .saturating_sub(self.assignees.len())
+    }
+}
+
+impl Serialize for Shift {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Shift", 13)?;
+        s.serialize_field("id", &self.id)?;
+        s.serialize_field("schedule_id", &self.schedule_id)?;
+        s.serialize_field("starts_at", &self.starts_at)?;
+        s.serialize_field("ends_at", &self.ends_at)?;
+        s.serialize_field("period", &self.period)?;
+        s.serialize_field("capacity", &self.capacity)?;
+        s.serialize_field("assignees", &self.assignees)?;
+        s.serialize_field("assigned_user_id", &self.assigned_user_id())?;
+        s.serialize_field("template_id", &self.template_id)?;
+        s.serialize_field("template_week", &self.template_week)?;
+        s.serialize_field("created_by", &self.created_by)?;
+        s.serialize_field("created_at", &self.created_at)?;
+        s.serialize_field("updated_at", &self.updated_at)?;
+        s.end()
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -107,6 +273,7 @@ pub struct ShiftComment {
     pub user_id: Uuid,
     pub body: String,
     pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -115,6 +282,284 @@ pub struct RotationTemplate {
     pub schedule_id: Uuid,
     pub name: String,
     pub definition: serde_json::Value,
+    /// Next round-robin position per slot index, e.g. `{"0": 3}`.
+    pub rotation_state: serde_json::Value,
     pub created_by: Uuid,
     pub created_at: DateTime<Utc>,
 }
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Cancelled,
+    Expired,
+}
+
+impl SwapStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SwapStatus::Pending => "pending",
+            SwapStatus::Accepted => "accepted",
+            SwapStatus::Declined => "declined",
+            SwapStatus::Cancelled => "cancelled",
+            SwapStatus::Expired => "expired",
+        }
+    }
+}
+
+impl TryFrom<&str> for SwapStatus {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "pending" => Ok(SwapStatus::Pending),
+            "accepted" => Ok(SwapStatus::Accepted),
+            "declined" => Ok(SwapStatus::Declined),
+            "cancelled" => Ok(SwapStatus::Cancelled),
+            "expired" => Ok(SwapStatus::Expired),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftSwap {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub shift_id: Uuid,
+    pub requester_id: Uuid,
+    pub counterpart_id: Uuid,
+    /// Shift offered in return; `None` gives `shift_id` away.
+    pub counterpart_shift_id: Option<Uuid>,
+    pub message: Option<String>,
+    pub status: SwapStatus,
+    /// Set when the counterpart agreed but an admin still has to approve.
+    pub counterpart_accepted_at: Option<DateTime<Utc>>,
+    pub decided_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaimStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl ClaimStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ClaimStatus::Pending => "pending",
+            ClaimStatus::Approved => "approved",
+            ClaimStatus::Rejected => "rejected",
+        }
+    }
+}
+
+impl TryFrom<&str> for ClaimStatus {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "pending" => Ok(ClaimStatus::Pending),
+            "approved" => Ok(ClaimStatus::Approved),
+            "rejected" => Ok(ClaimStatus::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A member's request to take an open shift under the `approval` policy.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftClaim {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+    pub status: ClaimStatus,
+    pub decided_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+}
+
+/// A recurring "can't do this period on this day" entry for a schedule member.
+#[derive(Clone, Debug, Serialize)]
+pub struct WeeklyUnavailability {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    pub dow: i16, // 0=Mon..6=Sun, in the schedule's time zone
+    pub period: Period,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A one-off date range (inclusive) overriding the weekly pattern: away when
+/// `available` is false, available anyway when true.
+#[derive(Clone, Debug, Serialize)]
+pub struct AvailabilityException {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub available: bool,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaveStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Cancelled,
+}
+
+impl LeaveStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LeaveStatus::Pending => "pending",
+            LeaveStatus::Approved => "approved",
+            LeaveStatus::Rejected => "rejected",
+            LeaveStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<&str> for LeaveStatus {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "pending" => Ok(LeaveStatus::Pending),
+            "approved" => Ok(LeaveStatus::Approved),
+            "rejected" => Ok(LeaveStatus::Rejected),
+            "cancelled" => Ok(LeaveStatus::Cancelled),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LeaveRequest {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    /// Inclusive local dates in the schedule's time zone.
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub reason: String,
+    pub status: LeaveStatus,
+    pub decided_by: Option<Uuid>,
+    pub decision_note: Option<String>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Shifts sharing an assignee, period and weekday/weekend start, summed.
+#[derive(Clone, Debug)]
+pub struct ShiftTally {
+    pub user_id: Option<Uuid>,
+    pub period: Period,
+    pub weekend: bool,
+    pub shifts: i64,
+    pub minutes: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CoverageRule {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub dow: i16, // 0=Mon..6=Sun, in the schedule's time zone
+    pub period: Period,
+    /// Members that must be assigned to shifts of this period on that day.
+    pub required: i16,
+    pub created_at: DateTime<Utc>,
+}
+
+/// When an assignee actually arrived at and left a shift.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftAttendance {
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// An admin's change to a shift's attendance, with the values it replaced.
+#[derive(Clone, Debug, Serialize)]
+pub struct AttendanceCorrection {
+    pub id: Uuid,
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+    pub corrected_by: Option<Uuid>,
+    pub previous_checked_in_at: Option<DateTime<Utc>>,
+    pub previous_checked_out_at: Option<DateTime<Utc>>,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A duty copied onto each matching shift of a schedule when it is created.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScheduleTask {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub title: String,
+    /// Local time in the schedule's zone; the task goes on every shift that
+    /// covers it. Without one, on every shift.
+    pub due_time: Option<NaiveTime>,
+    /// Only shifts of this period get the task.
+    pub period: Option<Period>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A checklist item on one shift.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShiftTask {
+    pub id: Uuid,
+    pub shift_id: Uuid,
+    /// The schedule task this was copied from, if any.
+    pub schedule_task_id: Option<Uuid>,
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+    pub position: i32,
+    pub done_at: Option<DateTime<Utc>>,
+    pub done_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScheduleInvite {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    /// Only this address may accept; `None` for a shareable link.
+    pub email: Option<String>,
+    pub role: ScheduleRole,
+    /// `None` for unlimited uses until it expires.
+    pub max_uses: Option<i32>,
+    pub uses: i32,
+    pub expires_at: DateTime<Utc>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ScheduleInvite {
+    /// Whether the invite can still be accepted at `now`.
+    pub fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at > now
+            && self.max_uses.is_none_or(|max| self.uses < max)
+    }
+}
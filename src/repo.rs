@@ -1,13 +1,18 @@
 use crate::{
+    attendance::DEFAULT_CHECK_IN_GRACE_MINUTES,
     error::{AppError, AppResult},
     models::{
-        Period, RotationTemplate, Schedule, ScheduleRole, ScheduleWithRole, Shift, ShiftComment,
-        User,
+        AttendanceCorrection, AuthSession, AvailabilityException, ClaimPolicy, ClaimStatus,
+        CoverageRule, LeaveRequest, LeaveStatus, Period, RotationTemplate, Schedule,
+        ScheduleDeletion, ScheduleInvite, ScheduleRole, ScheduleTask, ScheduleWithRole, Shift,
+        ShiftAssignee, ShiftAttendance, ShiftClaim, ShiftComment, ShiftSwap, ShiftTally, ShiftTask,
+        SwapStatus, TokenPurpose, User, WeeklyUnavailability,
     },
 };
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
-use sqlx::{PgPool, Row};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use sqlx::{postgres::PgRow, PgPool, Row};
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
@@ -26,18 +31,67 @@ pub struct NewSchedule {
     pub name: String,
     pub subject_type: String,
     pub subject_name: String,
+    pub timezone: String,
     pub created_by: Uuid,
 }
 
+#[derive(Clone, Debug)]
+pub struct UpdateSchedule {
+    pub name: String,
+    pub subject_type: String,
+    pub subject_name: String,
+    pub timezone: String,
+    pub swap_requires_approval: bool,
+    pub claim_policy: ClaimPolicy,
+    pub check_in_grace_minutes: i32,
+}
+
 #[derive(Clone, Debug)]
 pub struct NewShift {
     pub schedule_id: Uuid,
     pub starts_at: DateTime<Utc>,
     pub ends_at: DateTime<Utc>,
     pub period: Period,
+    pub capacity: i16,
+    /// First assignee, if any.
+    pub assigned_user_id: Option<Uuid>,
+    pub template_id: Option<Uuid>,
+    pub template_week: Option<NaiveDate>,
+    /// Checklist, in order.
+    pub tasks: Vec<NewShiftTask>,
     pub created_by: Uuid,
 }
 
+#[derive(Clone, Debug)]
+pub struct NewShiftTask {
+    pub schedule_task_id: Option<Uuid>,
+    pub title: String,
+    pub due_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewScheduleTask {
+    pub schedule_id: Uuid,
+    pub title: String,
+    pub due_time: Option<NaiveTime>,
+    pub period: Option<Period>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdateScheduleTask {
+    pub title: String,
+    pub due_time: Option<NaiveTime>,
+    pub period: Option<Period>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdateShift {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub period: Period,
+    pub capacity: i16,
+}
+
 #[derive(Clone, Debug)]
 pub struct NewShiftComment {
     pub shift_id: Uuid,
@@ -53,16 +107,469 @@ pub struct NewTemplate {
     pub created_by: Uuid,
 }
 
+#[derive(Clone, Debug)]
+pub struct NewWeeklyUnavailability {
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    pub dow: i16,
+    pub period: Period,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdateWeeklyUnavailability {
+    pub dow: i16,
+    pub period: Period,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewAvailabilityException {
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub available: bool,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdateAvailabilityException {
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub available: bool,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewClaim {
+    pub schedule_id: Uuid,
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewSwap {
+    pub schedule_id: Uuid,
+    pub shift_id: Uuid,
+    pub requester_id: Uuid,
+    pub counterpart_id: Uuid,
+    pub counterpart_shift_id: Option<Uuid>,
+    pub message: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewLeaveRequest {
+    pub schedule_id: Uuid,
+    pub user_id: Uuid,
+    pub starts_on: NaiveDate,
+    pub ends_on: NaiveDate,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewInvite {
+    pub schedule_id: Uuid,
+    pub email: Option<String>,
+    pub token_hash: String,
+    pub role: ScheduleRole,
+    pub max_uses: Option<i32>,
+    pub expires_at: DateTime<Utc>,
+    pub created_by: Uuid,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewCoverageRule {
+    pub schedule_id: Uuid,
+    pub dow: i16,
+    pub period: Period,
+    pub required: i16,
+}
+
+#[derive(Clone, Debug)]
+pub struct UpdateCoverageRule {
+    pub dow: i16,
+    pub period: Period,
+    pub required: i16,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewAttendanceCorrection {
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+    pub checked_in_at: Option<DateTime<Utc>>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+    pub corrected_by: Uuid,
+    pub reason: String,
+}
+
+/// Outcome of reserving an `Idempotency-Key`.
+#[derive(Clone, Debug)]
+pub enum IdempotentRequest {
+    /// First use of the key; the caller must finish or abort it.
+    New,
+    /// Another request with this key hasn't finished yet.
+    InProgress,
+    /// Replay of a completed request.
+    Completed {
+        status: u16,
+        body: serde_json::Value,
+    },
+}
+
+/// How long a stored idempotent response is replayed.
+const IDEMPOTENCY_TTL_HOURS: i64 = 24;
+
+/// One write queued in a [`UnitOfWork`].
+#[derive(Clone, Debug)]
+enum Write {
+    CreateSchedule {
+        id: Uuid,
+        ns: NewSchedule,
+    },
+    AddMember {
+        schedule_id: Uuid,
+        user_id: Uuid,
+        role: ScheduleRole,
+    },
+    CreateShift {
+        id: Uuid,
+        ns: NewShift,
+    },
+    LockTemplate {
+        template_id: Uuid,
+        rotation_state: serde_json::Value,
+    },
+    EnsureTemplateWeeksUnapplied {
+        template_id: Uuid,
+        weeks: Vec<NaiveDate>,
+    },
+    DeleteTemplateWeeks {
+        template_id: Uuid,
+        weeks: Vec<NaiveDate>,
+    },
+    SetTemplateRotationState {
+        template_id: Uuid,
+        rotation_state: serde_json::Value,
+    },
+    ReassignShift {
+        shift_id: Uuid,
+        from: Option<Uuid>,
+        to: Option<Uuid>,
+    },
+    ReplaceShiftAssignees {
+        shift_id: Uuid,
+        user_id: Option<Uuid>,
+    },
+    SetShiftLead {
+        shift_id: Uuid,
+        user_id: Uuid,
+    },
+    SetSwapStatus {
+        swap_id: Uuid,
+        status: SwapStatus,
+        decided_by: Option<Uuid>,
+    },
+    MarkSwapCounterpartAccepted {
+        swap_id: Uuid,
+    },
+    SetClaimStatus {
+        claim_id: Uuid,
+        status: ClaimStatus,
+        decided_by: Uuid,
+    },
+    RejectPendingClaims {
+        shift_id: Uuid,
+        decided_by: Uuid,
+    },
+    CloseMemberRequests {
+        schedule_id: Uuid,
+        user_id: Uuid,
+        decided_by: Uuid,
+    },
+    AddShiftComment {
+        id: Uuid,
+        nc: NewShiftComment,
+    },
+    SetLeaveStatus {
+        leave_id: Uuid,
+        status: LeaveStatus,
+        decided_by: Uuid,
+        note: Option<String>,
+    },
+    CorrectAttendance {
+        id: Uuid,
+        nc: NewAttendanceCorrection,
+    },
+    RemoveMember {
+        schedule_id: Uuid,
+        user_id: Uuid,
+    },
+}
+
+/// Writes that `Repo::commit` applies all together or not at all.
+///
+/// Ids are assigned when a row is queued, so later writes in the same unit
+/// can refer to it.
+#[derive(Clone, Debug, Default)]
+pub struct UnitOfWork {
+    writes: Vec<Write>,
+}
+
+impl UnitOfWork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn create_schedule(&mut self, ns: NewSchedule) -> Uuid {
+        let id = Uuid::new_v4();
+        self.writes.push(Write::CreateSchedule { id, ns });
+        id
+    }
+
+    pub fn add_member(&mut self, schedule_id: Uuid, user_id: Uuid, role: ScheduleRole) {
+        self.writes.push(Write::AddMember {
+            schedule_id,
+            user_id,
+            role,
+        });
+    }
+
+    pub fn create_shift(&mut self, ns: NewShift) -> Uuid {
+        let id = Uuid::new_v4();
+        self.writes.push(Write::CreateShift { id, ns });
+        id
+    }
+
+    /// Locks the template for the rest of the commit, failing with a
+    /// conflict if its rotation state is no longer `rotation_state`.
+    pub fn lock_template(&mut self, template_id: Uuid, rotation_state: serde_json::Value) {
+        self.writes.push(Write::LockTemplate {
+            template_id,
+            rotation_state,
+        });
+    }
+
+    /// Fails with a conflict if the template has shifts for any of `weeks`.
+    /// Queue it after `lock_template` so concurrent applications serialize.
+    pub fn ensure_template_weeks_unapplied(&mut self, template_id: Uuid, weeks: Vec<NaiveDate>) {
+        self.writes
+            .push(Write::EnsureTemplateWeeksUnapplied { template_id, weeks });
+    }
+
+    /// Deletes the template's shifts generated for any of `weeks`.
+    pub fn delete_template_weeks(&mut self, template_id: Uuid, weeks: Vec<NaiveDate>) {
+        self.writes
+            .push(Write::DeleteTemplateWeeks { template_id, weeks });
+    }
+
+    pub fn set_template_rotation_state(
+        &mut self,
+        template_id: Uuid,
+        rotation_state: serde_json::Value,
+    ) {
+        self.writes.push(Write::SetTemplateRotationState {
+            template_id,
+            rotation_state,
+        });
+    }
+
+    /// Hands `from`'s place on the shift to `to`. With no `from` it fills a
+    /// free place, and with no `to` it gives the place up. Fails with a
+    /// conflict if `from` is no longer on the shift, the shift is full, or
+    /// `to` is already on it by the time the unit commits.
+    pub fn reassign_shift(&mut self, shift_id: Uuid, from: Option<Uuid>, to: Option<Uuid>) {
+        self.writes
+            .push(Write::ReassignShift { shift_id, from, to });
+    }
+
+    /// Makes `user_id` the shift's only assignee, or clears the shift.
+    pub fn replace_shift_assignees(&mut self, shift_id: Uuid, user_id: Option<Uuid>) {
+        self.writes
+            .push(Write::ReplaceShiftAssignees { shift_id, user_id });
+    }
+
+    /// Makes an assignee the shift's lead, replacing any previous lead.
+    pub fn set_shift_lead(&mut self, shift_id: Uuid, user_id: Uuid) {
+        self.writes.push(Write::SetShiftLead { shift_id, user_id });
+    }
+
+    /// Closes a pending swap request. Fails with a conflict if it was
+    /// already closed.
+    pub fn set_swap_status(&mut self, swap_id: Uuid, status: SwapStatus, decided_by: Option<Uuid>) {
+        self.writes.push(Write::SetSwapStatus {
+            swap_id,
+            status,
+            decided_by,
+        });
+    }
+
+    /// Records that the counterpart agreed to a pending swap that still
+    /// needs an admin's approval.
+    pub fn mark_swap_counterpart_accepted(&mut self, swap_id: Uuid) {
+        self.writes
+            .push(Write::MarkSwapCounterpartAccepted { swap_id });
+    }
+
+    /// Decides a pending claim. Fails with a conflict if it was already decided.
+    pub fn set_claim_status(&mut self, claim_id: Uuid, status: ClaimStatus, decided_by: Uuid) {
+        self.writes.push(Write::SetClaimStatus {
+            claim_id,
+            status,
+            decided_by,
+        });
+    }
+
+    /// Rejects every claim on the shift that is still pending.
+    pub fn reject_pending_claims(&mut self, shift_id: Uuid, decided_by: Uuid) {
+        self.writes.push(Write::RejectPendingClaims {
+            shift_id,
+            decided_by,
+        });
+    }
+
+    /// Rejects the user's pending claims in the schedule and cancels the
+    /// pending swaps they requested or were asked to take part in.
+    pub fn close_member_requests(&mut self, schedule_id: Uuid, user_id: Uuid, decided_by: Uuid) {
+        self.writes.push(Write::CloseMemberRequests {
+            schedule_id,
+            user_id,
+            decided_by,
+        });
+    }
+
+    pub fn add_shift_comment(&mut self, nc: NewShiftComment) -> Uuid {
+        let id = Uuid::new_v4();
+        self.writes.push(Write::AddShiftComment { id, nc });
+        id
+    }
+
+    /// Decides a pending leave request. Fails with a conflict if it was
+    /// already decided or cancelled.
+    pub fn set_leave_status(
+        &mut self,
+        leave_id: Uuid,
+        status: LeaveStatus,
+        decided_by: Uuid,
+        note: Option<String>,
+    ) {
+        self.writes.push(Write::SetLeaveStatus {
+            leave_id,
+            status,
+            decided_by,
+            note,
+        });
+    }
+
+    /// Overwrites an assignee's check-in and check-out times, recording the
+    /// values replaced.
+    pub fn correct_attendance(&mut self, nc: NewAttendanceCorrection) -> Uuid {
+        let id = Uuid::new_v4();
+        self.writes.push(Write::CorrectAttendance { id, nc });
+        id
+    }
+
+    /// Takes the user out of the schedule. Fails with a conflict if they are
+    /// its last admin.
+    pub fn remove_member(&mut self, schedule_id: Uuid, user_id: Uuid) {
+        self.writes.push(Write::RemoveMember {
+            schedule_id,
+            user_id,
+        });
+    }
+}
+
+/// Rows created by a committed [`UnitOfWork`], in the order they were queued.
+#[derive(Debug, Default)]
+pub struct Committed {
+    pub schedules: Vec<Schedule>,
+    pub shifts: Vec<Shift>,
+    pub comments: Vec<ShiftComment>,
+    pub attendance_corrections: Vec<AttendanceCorrection>,
+}
+
 #[async_trait]
 pub trait Repo: Send + Sync {
     async fn count_users(&self) -> AppResult<i64>;
     async fn create_user(&self, nu: NewUser) -> AppResult<User>;
     async fn find_user_by_email(&self, email: &str) -> AppResult<Option<(User, String)>>;
     async fn get_user(&self, user_id: Uuid) -> AppResult<Option<User>>;
+    /// Replaces (or with `None`, revokes) the user's calendar feed token.
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: Uuid,
+        token_hash: Option<String>,
+    ) -> AppResult<()>;
+    async fn find_user_by_feed_token(&self, token_hash: &str) -> AppResult<Option<User>>;
+    async fn mark_email_verified(&self, user_id: Uuid) -> AppResult<()>;
+    async fn set_password(&self, user_id: Uuid, password_hash: String) -> AppResult<()>;
+
+    /// Stores a mailed token, voiding any unused one the user has for the
+    /// same purpose.
+    async fn create_account_token(
+        &self,
+        user_id: Uuid,
+        purpose: TokenPurpose,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<()>;
+    /// Spends a token if it is unused and unexpired, returning its user.
+    async fn consume_account_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> AppResult<Option<Uuid>>;
+
+    /// Starts a session whose first refresh token hashes to `refresh_hash`.
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        refresh_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession>;
+    async fn get_session(&self, session_id: Uuid) -> AppResult<Option<AuthSession>>;
+    /// Spends a refresh token and stores the next one in its session's chain.
+    /// A token that was already spent revokes the whole session: either the
+    /// client or whoever copied the token is replaying it.
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        next_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession>;
+    async fn revoke_session(&self, session_id: Uuid) -> AppResult<()>;
+    /// Revokes every live session of the user, returning how many there were.
+    async fn revoke_user_sessions(&self, user_id: Uuid) -> AppResult<u64>;
+
+    /// Applies every write in `uow` in one transaction.
+    async fn commit(&self, uow: UnitOfWork) -> AppResult<Committed>;
 
-    async fn create_schedule(&self, ns: NewSchedule) -> AppResult<Schedule>;
-    async fn list_schedules_for_user(&self, user_id: Uuid) -> AppResult<Vec<ScheduleWithRole>>;
+    /// Creates the schedule with its creator as the first admin.
+    async fn create_schedule(&self, ns: NewSchedule) -> AppResult<Schedule> {
+        let created_by = ns.created_by;
+        let mut uow = UnitOfWork::new();
+        let id = uow.create_schedule(ns);
+        uow.add_member(id, created_by, ScheduleRole::Admin);
+        self.commit(uow)
+            .await?
+            .schedules
+            .pop()
+            .ok_or(AppError::Internal)
+    }
+    async fn list_schedules_for_user(
+        &self,
+        user_id: Uuid,
+        include_archived: bool,
+    ) -> AppResult<Vec<ScheduleWithRole>>;
     async fn get_schedule(&self, schedule_id: Uuid) -> AppResult<Option<Schedule>>;
+    async fn update_schedule(&self, schedule_id: Uuid, us: UpdateSchedule) -> AppResult<Schedule>;
+    /// Archives the schedule (keeping an earlier `archived_at`), or restores it.
+    async fn set_schedule_archived(&self, schedule_id: Uuid, archived: bool)
+        -> AppResult<Schedule>;
+    /// Deletes the schedule and everything that cascades from it, counting
+    /// what went with it.
+    async fn delete_schedule(&self, schedule_id: Uuid) -> AppResult<ScheduleDeletion>;
     async fn get_schedule_role(
         &self,
         schedule_id: Uuid,
@@ -78,14 +585,38 @@ pub trait Repo: Send + Sync {
         user_id: Uuid,
         role: ScheduleRole,
     ) -> AppResult<()>;
+    /// Fails with a conflict when demoting the schedule's last admin.
     async fn set_member_role(
         &self,
         schedule_id: Uuid,
         user_id: Uuid,
         role: ScheduleRole,
     ) -> AppResult<()>;
+    async fn remove_member(&self, schedule_id: Uuid, user_id: Uuid) -> AppResult<()> {
+        let mut uow = UnitOfWork::new();
+        uow.remove_member(schedule_id, user_id);
+        self.commit(uow).await.map(|_| ())
+    }
 
-    async fn create_shift(&self, ns: NewShift) -> AppResult<Shift>;
+    async fn create_invite(&self, ni: NewInvite) -> AppResult<ScheduleInvite>;
+    /// Invites that can still be accepted, newest first.
+    async fn list_pending_invites(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleInvite>>;
+    async fn get_invite(&self, invite_id: Uuid) -> AppResult<Option<ScheduleInvite>>;
+    async fn find_invite_by_token(&self, token_hash: &str) -> AppResult<Option<ScheduleInvite>>;
+    async fn revoke_invite(&self, invite_id: Uuid) -> AppResult<()>;
+    /// Spends one use of a still-pending invite and adds the user to its
+    /// schedule with the invite's role.
+    async fn accept_invite(&self, invite_id: Uuid, user_id: Uuid) -> AppResult<ScheduleInvite>;
+
+    async fn create_shift(&self, ns: NewShift) -> AppResult<Shift> {
+        let mut uow = UnitOfWork::new();
+        uow.create_shift(ns);
+        self.commit(uow)
+            .await?
+            .shifts
+            .pop()
+            .ok_or(AppError::Internal)
+    }
     async fn list_shifts(
         &self,
         schedule_id: Uuid,
@@ -93,14 +624,233 @@ pub trait Repo: Send + Sync {
         to: DateTime<Utc>,
     ) -> AppResult<Vec<Shift>>;
     async fn get_shift(&self, shift_id: Uuid) -> AppResult<Option<Shift>>;
-    async fn assign_shift(&self, shift_id: Uuid, assigned_user_id: Option<Uuid>) -> AppResult<()>;
+    /// Shifts `user_id` is assigned to across all schedules, by `starts_at`.
+    async fn list_assigned_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>>;
+    async fn update_shift(&self, shift_id: Uuid, us: UpdateShift) -> AppResult<Shift>;
+    async fn delete_shift(&self, shift_id: Uuid) -> AppResult<()>;
+    /// Makes `assigned_user_id` the shift's only assignee, or clears it.
+    async fn assign_shift(&self, shift_id: Uuid, assigned_user_id: Option<Uuid>) -> AppResult<()> {
+        let mut uow = UnitOfWork::new();
+        uow.replace_shift_assignees(shift_id, assigned_user_id);
+        self.commit(uow).await.map(|_| ())
+    }
+    /// Shifts in any schedule `user_id` is assigned to that overlap `[starts_at, ends_at)`,
+    /// excluding `exclude_shift_id`.
+    async fn list_overlapping_shifts(
+        &self,
+        user_id: Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        exclude_shift_id: Uuid,
+    ) -> AppResult<Vec<Shift>>;
 
     async fn add_shift_comment(&self, nc: NewShiftComment) -> AppResult<ShiftComment>;
-    async fn list_shift_comments(&self, shift_id: Uuid) -> AppResult<Vec<ShiftComment>>;
+    async fn list_shift_comments(
+        &self,
+        shift_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<ShiftComment>>;
+    async fn list_comments_for_shifts(&self, shift_ids: &[Uuid]) -> AppResult<Vec<ShiftComment>>;
+    async fn get_shift_comment(&self, comment_id: Uuid) -> AppResult<Option<ShiftComment>>;
+    async fn update_shift_comment(&self, comment_id: Uuid, body: String)
+        -> AppResult<ShiftComment>;
+    async fn delete_shift_comment(&self, comment_id: Uuid) -> AppResult<()>;
 
     async fn create_template(&self, nt: NewTemplate) -> AppResult<RotationTemplate>;
     async fn list_templates(&self, schedule_id: Uuid) -> AppResult<Vec<RotationTemplate>>;
     async fn get_template(&self, template_id: Uuid) -> AppResult<Option<RotationTemplate>>;
+    /// Distinct cycle weeks in `[from, to]` that already have shifts from the template.
+    async fn list_template_weeks(
+        &self,
+        template_id: Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> AppResult<Vec<NaiveDate>>;
+
+    async fn create_swap(&self, ns: NewSwap) -> AppResult<ShiftSwap>;
+    async fn get_swap(&self, swap_id: Uuid) -> AppResult<Option<ShiftSwap>>;
+    /// Swaps in a schedule, newest first, optionally only those `involving`
+    /// a user as requester or counterpart.
+    async fn list_swaps(
+        &self,
+        schedule_id: Uuid,
+        involving: Option<Uuid>,
+        status: Option<SwapStatus>,
+    ) -> AppResult<Vec<ShiftSwap>>;
+    /// Marks pending swaps whose `expires_at` has passed as expired.
+    async fn expire_swaps(&self, now: DateTime<Utc>) -> AppResult<()>;
+
+    /// Unassigned shifts in every schedule `user_id` belongs to, by `starts_at`.
+    async fn list_open_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>>;
+    /// Fails with a conflict if the user already has a pending claim on the shift.
+    async fn create_claim(&self, nc: NewClaim) -> AppResult<ShiftClaim>;
+    async fn get_claim(&self, claim_id: Uuid) -> AppResult<Option<ShiftClaim>>;
+    /// Claims in a schedule, oldest first, optionally only those by `user_id`.
+    async fn list_claims(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+        status: Option<ClaimStatus>,
+    ) -> AppResult<Vec<ShiftClaim>>;
+
+    /// Weekly unavailability in a schedule, optionally for one member.
+    async fn list_weekly_unavailability(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<WeeklyUnavailability>>;
+    async fn get_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+    ) -> AppResult<Option<WeeklyUnavailability>>;
+    async fn create_weekly_unavailability(
+        &self,
+        nw: NewWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability>;
+    async fn update_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+        uw: UpdateWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability>;
+    async fn delete_weekly_unavailability(&self, rule_id: Uuid) -> AppResult<()>;
+    /// Availability exceptions in a schedule, optionally for one member.
+    async fn list_availability_exceptions(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<AvailabilityException>>;
+    async fn get_availability_exception(
+        &self,
+        exception_id: Uuid,
+    ) -> AppResult<Option<AvailabilityException>>;
+    async fn create_availability_exception(
+        &self,
+        ne: NewAvailabilityException,
+    ) -> AppResult<AvailabilityException>;
+    async fn update_availability_exception(
+        &self,
+        exception_id: Uuid,
+        ue: UpdateAvailabilityException,
+    ) -> AppResult<AvailabilityException>;
+    async fn delete_availability_exception(&self, exception_id: Uuid) -> AppResult<()>;
+
+    async fn create_leave_request(&self, nl: NewLeaveRequest) -> AppResult<LeaveRequest>;
+    async fn get_leave_request(&self, leave_id: Uuid) -> AppResult<Option<LeaveRequest>>;
+    /// Leave requests in a schedule by `starts_on`, optionally for one member.
+    async fn list_leave_requests(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+        status: Option<LeaveStatus>,
+    ) -> AppResult<Vec<LeaveRequest>>;
+
+    /// Totals for shifts starting in `[from, to)`, grouped by assignee, period
+    /// and whether they start on a Saturday or Sunday in `zone`. Each free
+    /// place on a shift counts as one unassigned (`user_id: None`) shift.
+    async fn tally_shifts(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        zone: Tz,
+    ) -> AppResult<Vec<ShiftTally>>;
+
+    /// Coverage rules of a schedule by day and period.
+    async fn list_coverage_rules(&self, schedule_id: Uuid) -> AppResult<Vec<CoverageRule>>;
+    async fn get_coverage_rule(&self, rule_id: Uuid) -> AppResult<Option<CoverageRule>>;
+    async fn create_coverage_rule(&self, nc: NewCoverageRule) -> AppResult<CoverageRule>;
+    async fn update_coverage_rule(
+        &self,
+        rule_id: Uuid,
+        uc: UpdateCoverageRule,
+    ) -> AppResult<CoverageRule>;
+    async fn delete_coverage_rule(&self, rule_id: Uuid) -> AppResult<()>;
+
+    async fn list_attendance_for_shifts(
+        &self,
+        shift_ids: &[Uuid],
+    ) -> AppResult<Vec<ShiftAttendance>>;
+    /// Fails with a conflict if the user already checked in.
+    async fn check_in(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance>;
+    /// Fails with a conflict unless the user checked in before `at` and
+    /// hasn't checked out yet.
+    async fn check_out(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance>;
+    /// Corrections to a shift's attendance, oldest first.
+    async fn list_attendance_corrections(
+        &self,
+        shift_id: Uuid,
+    ) -> AppResult<Vec<AttendanceCorrection>>;
+
+    /// A schedule's checklist, timed tasks first.
+    async fn list_schedule_tasks(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleTask>>;
+    async fn get_schedule_task(&self, task_id: Uuid) -> AppResult<Option<ScheduleTask>>;
+    async fn create_schedule_task(&self, nt: NewScheduleTask) -> AppResult<ScheduleTask>;
+    async fn update_schedule_task(
+        &self,
+        task_id: Uuid,
+        ut: UpdateScheduleTask,
+    ) -> AppResult<ScheduleTask>;
+    async fn delete_schedule_task(&self, task_id: Uuid) -> AppResult<()>;
+
+    /// A shift's checklist in order.
+    async fn list_shift_tasks(&self, shift_id: Uuid) -> AppResult<Vec<ShiftTask>>;
+    async fn get_shift_task(&self, task_id: Uuid) -> AppResult<Option<ShiftTask>>;
+    /// Appends a task to the end of the shift's checklist.
+    async fn add_shift_task(&self, shift_id: Uuid, nt: NewShiftTask) -> AppResult<ShiftTask>;
+    /// Ticks a task off as done by `done_by`, or with `None` reopens it.
+    async fn set_shift_task_done(
+        &self,
+        task_id: Uuid,
+        done_by: Option<Uuid>,
+    ) -> AppResult<ShiftTask>;
+    async fn delete_shift_task(&self, task_id: Uuid) -> AppResult<()>;
+    /// Tasks not yet done on shifts starting in `[from, to)`, by shift start
+    /// and then checklist order.
+    async fn list_incomplete_tasks(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<ShiftTask>>;
+
+    /// Reserves `key` for `user_id`. Reusing a key for a different `scope`
+    /// is a conflict.
+    async fn begin_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        scope: &str,
+    ) -> AppResult<IdempotentRequest>;
+    async fn finish_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        status: u16,
+        body: serde_json::Value,
+    ) -> AppResult<()>;
+    /// Releases a reservation after a failed request so it can be retried.
+    async fn abort_idempotent_request(&self, user_id: Uuid, key: &str) -> AppResult<()>;
 }
 
 pub struct PgRepo {
@@ -113,13 +863,373 @@ impl PgRepo {
     }
 }
 
-#[async_trait]
-impl Repo for PgRepo {
-    async fn count_users(&self) -> AppResult<i64> {
-        let row = sqlx::query("select count(*)::bigint as c from app_user")
-            .fetch_one(&self.pool)
-            .await
-            .map_err(|_| AppError::Internal)?;
+fn schedule_from_row(r: &PgRow) -> AppResult<Schedule> {
+    let policy_str: String = r.get("claim_policy");
+    let claim_policy =
+        ClaimPolicy::try_from(policy_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(Schedule {
+        id: r.get("id"),
+        name: r.get("name"),
+        subject_type: r.get("subject_type"),
+        subject_name: r.get("subject_name"),
+        timezone: r.get("timezone"),
+        swap_requires_approval: r.get("swap_requires_approval"),
+        claim_policy,
+        check_in_grace_minutes: r.get("check_in_grace_minutes"),
+        archived_at: r.get("archived_at"),
+        created_by: r.get("created_by"),
+        created_at: r.get("created_at"),
+    })
+}
+
+/// Reads a row of `shift_with_assignees`.
+fn shift_from_row(r: &PgRow) -> AppResult<Shift> {
+    let period_str: String = r.get("period");
+    let period = Period::try_from(period_str.as_str()).map_err(|_| AppError::Internal)?;
+    let ids: Vec<Uuid> = r.get("assignee_ids");
+    let leads: Vec<bool> = r.get("assignee_leads");
+    let assigned_at: Vec<DateTime<Utc>> = r.get("assignee_assigned_at");
+    let assignees = ids
+        .into_iter()
+        .zip(leads)
+        .zip(assigned_at)
+        .map(|((user_id, lead), assigned_at)| ShiftAssignee {
+            user_id,
+            lead,
+            assigned_at,
+        })
+        .collect();
+    Ok(Shift {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        starts_at: r.get("starts_at"),
+        ends_at: r.get("ends_at"),
+        period,
+        capacity: r.get("capacity"),
+        assignees,
+        template_id: r.get("template_id"),
+        template_week: r.get("template_week"),
+        created_by: r.get("created_by"),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    })
+}
+
+fn comment_from_row(r: &PgRow) -> ShiftComment {
+    ShiftComment {
+        id: r.get("id"),
+        shift_id: r.get("shift_id"),
+        user_id: r.get("user_id"),
+        body: r.get("body"),
+        created_at: r.get("created_at"),
+        edited_at: r.get("edited_at"),
+    }
+}
+
+fn swap_from_row(r: &PgRow) -> AppResult<ShiftSwap> {
+    let status_str: String = r.get("status");
+    let status = SwapStatus::try_from(status_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(ShiftSwap {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        shift_id: r.get("shift_id"),
+        requester_id: r.get("requester_id"),
+        counterpart_id: r.get("counterpart_id"),
+        counterpart_shift_id: r.get("counterpart_shift_id"),
+        message: r.get("message"),
+        status,
+        counterpart_accepted_at: r.get("counterpart_accepted_at"),
+        decided_by: r.get("decided_by"),
+        expires_at: r.get("expires_at"),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    })
+}
+
+fn claim_from_row(r: &PgRow) -> AppResult<ShiftClaim> {
+    let status_str: String = r.get("status");
+    let status = ClaimStatus::try_from(status_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(ShiftClaim {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        shift_id: r.get("shift_id"),
+        user_id: r.get("user_id"),
+        status,
+        decided_by: r.get("decided_by"),
+        created_at: r.get("created_at"),
+        decided_at: r.get("decided_at"),
+    })
+}
+
+fn weekly_unavailability_from_row(r: &PgRow) -> AppResult<WeeklyUnavailability> {
+    let period_str: String = r.get("period");
+    let period = Period::try_from(period_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(WeeklyUnavailability {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        user_id: r.get("user_id"),
+        dow: r.get("dow"),
+        period,
+        note: r.get("note"),
+        created_at: r.get("created_at"),
+    })
+}
+
+fn availability_exception_from_row(r: &PgRow) -> AvailabilityException {
+    AvailabilityException {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        user_id: r.get("user_id"),
+        starts_on: r.get("starts_on"),
+        ends_on: r.get("ends_on"),
+        available: r.get("available"),
+        note: r.get("note"),
+        created_at: r.get("created_at"),
+    }
+}
+
+fn weekly_unavailability_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_unique_violation() {
+            return AppError::Conflict("already unavailable for that day and period".to_string());
+        }
+    }
+    AppError::Internal
+}
+
+fn availability_exception_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_check_violation()
+            && db.constraint() == Some("member_availability_exception_range_ok")
+        {
+            return AppError::BadRequest("ends_on must not be before starts_on".to_string());
+        }
+    }
+    AppError::Internal
+}
+
+fn leave_from_row(r: &PgRow) -> AppResult<LeaveRequest> {
+    let status_str: String = r.get("status");
+    let status = LeaveStatus::try_from(status_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(LeaveRequest {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        user_id: r.get("user_id"),
+        starts_on: r.get("starts_on"),
+        ends_on: r.get("ends_on"),
+        reason: r.get("reason"),
+        status,
+        decided_by: r.get("decided_by"),
+        decision_note: r.get("decision_note"),
+        decided_at: r.get("decided_at"),
+        created_at: r.get("created_at"),
+    })
+}
+
+fn coverage_rule_from_row(r: &PgRow) -> AppResult<CoverageRule> {
+    let period_str: String = r.get("period");
+    let period = Period::try_from(period_str.as_str()).map_err(|_| AppError::Internal)?;
+    Ok(CoverageRule {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        dow: r.get("dow"),
+        period,
+        required: r.get("required"),
+        created_at: r.get("created_at"),
+    })
+}
+
+fn attendance_from_row(r: &PgRow) -> ShiftAttendance {
+    ShiftAttendance {
+        shift_id: r.get("shift_id"),
+        user_id: r.get("user_id"),
+        checked_in_at: r.get("checked_in_at"),
+        checked_out_at: r.get("checked_out_at"),
+        updated_at: r.get("updated_at"),
+    }
+}
+
+fn attendance_correction_from_row(r: &PgRow) -> AttendanceCorrection {
+    AttendanceCorrection {
+        id: r.get("id"),
+        shift_id: r.get("shift_id"),
+        user_id: r.get("user_id"),
+        corrected_by: r.get("corrected_by"),
+        previous_checked_in_at: r.get("previous_checked_in_at"),
+        previous_checked_out_at: r.get("previous_checked_out_at"),
+        checked_in_at: r.get("checked_in_at"),
+        checked_out_at: r.get("checked_out_at"),
+        reason: r.get("reason"),
+        created_at: r.get("created_at"),
+    }
+}
+
+fn user_from_row(r: &PgRow) -> User {
+    User {
+        id: r.get("id"),
+        email: r.get("email"),
+        is_superadmin: r.get("is_superadmin"),
+        email_verified_at: r.get("email_verified_at"),
+        created_at: r.get("created_at"),
+    }
+}
+
+fn invite_from_row(r: &PgRow) -> AppResult<ScheduleInvite> {
+    let role: String = r.get("role");
+    Ok(ScheduleInvite {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        email: r.get("email"),
+        role: ScheduleRole::try_from(role.as_str()).map_err(|_| AppError::Internal)?,
+        max_uses: r.get("max_uses"),
+        uses: r.get("uses"),
+        expires_at: r.get("expires_at"),
+        created_by: r.get("created_by"),
+        created_at: r.get("created_at"),
+        revoked_at: r.get("revoked_at"),
+    })
+}
+
+fn session_from_row(r: &PgRow) -> AuthSession {
+    AuthSession {
+        id: r.get("id"),
+        user_id: r.get("user_id"),
+        created_at: r.get("created_at"),
+        last_refreshed_at: r.get("last_refreshed_at"),
+        revoked_at: r.get("revoked_at"),
+    }
+}
+
+fn schedule_task_from_row(r: &PgRow) -> AppResult<ScheduleTask> {
+    let period = r
+        .get::<Option<String>, _>("period")
+        .map(|p| Period::try_from(p.as_str()).map_err(|_| AppError::Internal))
+        .transpose()?;
+    Ok(ScheduleTask {
+        id: r.get("id"),
+        schedule_id: r.get("schedule_id"),
+        title: r.get("title"),
+        due_time: r.get("due_time"),
+        period,
+        created_at: r.get("created_at"),
+    })
+}
+
+fn shift_task_from_row(r: &PgRow) -> ShiftTask {
+    ShiftTask {
+        id: r.get("id"),
+        shift_id: r.get("shift_id"),
+        schedule_task_id: r.get("schedule_task_id"),
+        title: r.get("title"),
+        due_at: r.get("due_at"),
+        position: r.get("position"),
+        done_at: r.get("done_at"),
+        done_by: r.get("done_by"),
+        created_at: r.get("created_at"),
+    }
+}
+
+fn coverage_rule_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_unique_violation() {
+            return AppError::Conflict(
+                "a coverage rule for that day and period already exists".to_string(),
+            );
+        }
+    }
+    AppError::Internal
+}
+
+fn attendance_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_check_violation() && db.constraint() == Some("shift_attendance_order_ok") {
+            return AppError::BadRequest("checked_out_at must be after checked_in_at".to_string());
+        }
+    }
+    AppError::Internal
+}
+
+fn shift_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_check_violation() && db.constraint() == Some("shift_time_ok") {
+            return AppError::BadRequest("ends_at must be after starts_at".to_string());
+        }
+        if db.is_check_violation() && db.constraint() == Some("shift_capacity_ok") {
+            return AppError::BadRequest("capacity must be at least 1".to_string());
+        }
+    }
+    AppError::Internal
+}
+
+fn assignee_write_error(e: sqlx::Error) -> AppError {
+    if let Some(db) = e.as_database_error() {
+        if db.is_unique_violation() {
+            return AppError::Conflict("user is already assigned to this shift".to_string());
+        }
+    }
+    AppError::Internal
+}
+
+/// Reads a shift back after writing it, with its assignees.
+async fn reload_shift<'e>(db: impl sqlx::PgExecutor<'e>, shift_id: Uuid) -> AppResult<Shift> {
+    let row = sqlx::query(
+        "select id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by, created_at, updated_at, assignee_ids, assignee_leads, assignee_assigned_at from shift_with_assignees where id = $1",
+    )
+    .bind(shift_id)
+    .fetch_optional(db)
+    .await
+    .map_err(|_| AppError::Internal)?
+    .ok_or(AppError::NotFound)?;
+
+    shift_from_row(&row)
+}
+
+/// Locks the shift row so concurrent writers see a consistent head-count.
+async fn lock_shift(tx: &mut sqlx::PgConnection, shift_id: Uuid) -> AppResult<Option<i16>> {
+    let row = sqlx::query("select capacity from shift where id = $1 for update")
+        .bind(shift_id)
+        .fetch_optional(tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+    Ok(row.map(|r| r.get("capacity")))
+}
+
+fn template_changed_error() -> AppError {
+    AppError::Conflict("template was applied concurrently; try again".to_string())
+}
+
+fn last_admin_error() -> AppError {
+    AppError::Conflict("a schedule must keep at least one admin".to_string())
+}
+
+/// Locks the schedule's admin rows and fails if `user_id` is the only one,
+/// so concurrent demotions and removals can't leave it without an admin.
+async fn ensure_another_admin(
+    tx: &mut sqlx::PgConnection,
+    schedule_id: Uuid,
+    user_id: Uuid,
+) -> AppResult<()> {
+    let rows = sqlx::query(
+        "select user_id from schedule_member where schedule_id = $1 and role = 'admin' for update",
+    )
+    .bind(schedule_id)
+    .fetch_all(tx)
+    .await
+    .map_err(|_| AppError::Internal)?;
+    let admins: Vec<Uuid> = rows.iter().map(|r| r.get("user_id")).collect();
+    if admins == [user_id] {
+        return Err(last_admin_error());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Repo for PgRepo {
+    async fn count_users(&self) -> AppResult<i64> {
+        let row = sqlx::query("select count(*)::bigint as c from app_user")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
         Ok(row.get::<i64, _>("c"))
     }
 
@@ -129,7 +1239,7 @@ impl Repo for PgRepo {
             r#"
             insert into app_user (id, email, password_hash, is_superadmin)
             values ($1, $2, $3, $4)
-            returning id, email, is_superadmin, created_at
+            returning id, email, is_superadmin, email_verified_at, created_at
             "#,
         )
         .bind(id)
@@ -147,18 +1257,13 @@ impl Repo for PgRepo {
             AppError::Internal
         })?;
 
-        Ok(User {
-            id: row.get("id"),
-            email: row.get("email"),
-            is_superadmin: row.get("is_superadmin"),
-            created_at: row.get("created_at"),
-        })
+        Ok(user_from_row(&row))
     }
 
     async fn find_user_by_email(&self, email: &str) -> AppResult<Option<(User, String)>> {
         let row = sqlx::query(
             r#"
-            select id, email, password_hash, is_superadmin, created_at
+            select id, email, password_hash, is_superadmin, email_verified_at, created_at
             from app_user
             where email = $1
             "#,
@@ -168,180 +1273,945 @@ impl Repo for PgRepo {
         .await
         .map_err(|_| AppError::Internal)?;
 
-        Ok(row.map(|r| {
-            let u = User {
-                id: r.get("id"),
-                email: r.get("email"),
-                is_superadmin: r.get("is_superadmin"),
-                created_at: r.get("created_at"),
-            };
-            let ph: String = r.get("password_hash");
-            (u, ph)
-        }))
+        Ok(row.map(|r| (user_from_row(&r), r.get("password_hash"))))
     }
 
     async fn get_user(&self, user_id: Uuid) -> AppResult<Option<User>> {
-        let row =
-            sqlx::query("select id, email, is_superadmin, created_at from app_user where id = $1")
-                .bind(user_id)
-                .fetch_optional(&self.pool)
-                .await
-                .map_err(|_| AppError::Internal)?;
+        let row = sqlx::query(
+            "select id, email, is_superadmin, email_verified_at, created_at from app_user where id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
 
-        Ok(row.map(|r| User {
-            id: r.get("id"),
-            email: r.get("email"),
-            is_superadmin: r.get("is_superadmin"),
-            created_at: r.get("created_at"),
-        }))
+        Ok(row.as_ref().map(user_from_row))
     }
 
-    async fn create_schedule(&self, ns: NewSchedule) -> AppResult<Schedule> {
-        let id = Uuid::new_v4();
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: Uuid,
+        token_hash: Option<String>,
+    ) -> AppResult<()> {
+        match token_hash {
+            Some(hash) => sqlx::query(
+                r#"
+                insert into calendar_feed_token (user_id, token_hash)
+                values ($1, $2)
+                on conflict (user_id) do update set token_hash = excluded.token_hash, created_at = now()
+                "#,
+            )
+            .bind(user_id)
+            .bind(hash),
+            None => sqlx::query("delete from calendar_feed_token where user_id = $1").bind(user_id),
+        }
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
+
+    async fn find_user_by_feed_token(&self, token_hash: &str) -> AppResult<Option<User>> {
         let row = sqlx::query(
             r#"
-            insert into schedule (id, name, subject_type, subject_name, created_by)
-            values ($1, $2, $3, $4, $5)
-            returning id, name, subject_type, subject_name, created_by, created_at
+            select u.id, u.email, u.is_superadmin, u.email_verified_at, u.created_at
+            from calendar_feed_token t
+            join app_user u on u.id = t.user_id
+            where t.token_hash = $1
             "#,
         )
-        .bind(id)
-        .bind(&ns.name)
-        .bind(&ns.subject_type)
-        .bind(&ns.subject_name)
-        .bind(ns.created_by)
-        .fetch_one(&self.pool)
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        // creator becomes admin member
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn mark_email_verified(&self, user_id: Uuid) -> AppResult<()> {
         sqlx::query(
-            "insert into schedule_member (schedule_id, user_id, role) values ($1, $2, 'admin')",
+            "update app_user set email_verified_at = coalesce(email_verified_at, now()) where id = $1",
         )
-        .bind(id)
-        .bind(ns.created_by)
+        .bind(user_id)
         .execute(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
 
-        Ok(Schedule {
-            id: row.get("id"),
-            name: row.get("name"),
-            subject_type: row.get("subject_type"),
-            subject_name: row.get("subject_name"),
-            created_by: row.get("created_by"),
-            created_at: row.get("created_at"),
-        })
+    async fn set_password(&self, user_id: Uuid, password_hash: String) -> AppResult<()> {
+        sqlx::query("update app_user set password_hash = $2 where id = $1")
+            .bind(user_id)
+            .bind(password_hash)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        Ok(())
     }
 
-    async fn list_schedules_for_user(&self, user_id: Uuid) -> AppResult<Vec<ScheduleWithRole>> {
-        let rows = sqlx::query(
+    async fn create_account_token(
+        &self,
+        user_id: Uuid,
+        purpose: TokenPurpose,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        sqlx::query(
+            "delete from account_token where user_id = $1 and purpose = $2 and used_at is null",
+        )
+        .bind(user_id)
+        .bind(purpose.as_str())
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        sqlx::query(
             r#"
-            select s.id, s.name, s.subject_type, s.subject_name, s.created_by, s.created_at, sm.role
-            from schedule s
-            join schedule_member sm on sm.schedule_id = s.id
-            where sm.user_id = $1
-            order by s.created_at desc
+            insert into account_token (token_hash, user_id, purpose, expires_at)
+            values ($1, $2, $3, $4)
             "#,
         )
+        .bind(token_hash)
         .bind(user_id)
-        .fetch_all(&self.pool)
+        .bind(purpose.as_str())
+        .bind(expires_at)
+        .execute(&mut *tx)
         .await
         .map_err(|_| AppError::Internal)?;
-
-        let mut out = Vec::with_capacity(rows.len());
-        for r in rows {
-            let role_str: String = r.get("role");
-            let role = ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?;
-            out.push(ScheduleWithRole {
-                schedule: Schedule {
-                    id: r.get("id"),
-                    name: r.get("name"),
-                    subject_type: r.get("subject_type"),
-                    subject_name: r.get("subject_name"),
-                    created_by: r.get("created_by"),
-                    created_at: r.get("created_at"),
-                },
-                role,
-            });
-        }
-        Ok(out)
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(())
     }
 
-    async fn get_schedule(&self, schedule_id: Uuid) -> AppResult<Option<Schedule>> {
+    async fn consume_account_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> AppResult<Option<Uuid>> {
         let row = sqlx::query(
-            "select id, name, subject_type, subject_name, created_by, created_at from schedule where id = $1",
+            r#"
+            update account_token set used_at = now()
+            where token_hash = $1 and purpose = $2 and used_at is null and expires_at > now()
+            returning user_id
+            "#,
         )
-        .bind(schedule_id)
+        .bind(token_hash)
+        .bind(purpose.as_str())
         .fetch_optional(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        Ok(row.map(|r| Schedule {
-            id: r.get("id"),
-            name: r.get("name"),
-            subject_type: r.get("subject_type"),
-            subject_name: r.get("subject_name"),
-            created_by: r.get("created_by"),
-            created_at: r.get("created_at"),
-        }))
+        Ok(row.map(|r| r.get("user_id")))
     }
 
-    async fn get_schedule_role(
+    async fn create_session(
         &self,
-        schedule_id: Uuid,
         user_id: Uuid,
-    ) -> AppResult<Option<ScheduleRole>> {
-        let row =
-            sqlx::query("select role from schedule_member where schedule_id = $1 and user_id = $2")
-                .bind(schedule_id)
-                .bind(user_id)
-                .fetch_optional(&self.pool)
-                .await
-                .map_err(|_| AppError::Internal)?;
+        refresh_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        let row = sqlx::query(
+            r#"
+            insert into auth_session (id, user_id) values ($1, $2)
+            returning id, user_id, created_at, last_refreshed_at, revoked_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        let session = session_from_row(&row);
+        sqlx::query(
+            "insert into refresh_token (token_hash, session_id, expires_at) values ($1, $2, $3)",
+        )
+        .bind(refresh_hash)
+        .bind(session.id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(session)
+    }
 
-        Ok(match row {
-            None => None,
-            Some(r) => {
-                let role_str: String = r.get("role");
-                Some(ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?)
-            }
-        })
+    async fn get_session(&self, session_id: Uuid) -> AppResult<Option<AuthSession>> {
+        let row = sqlx::query(
+            "select id, user_id, created_at, last_refreshed_at, revoked_at from auth_session where id = $1",
+        )
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(row.as_ref().map(session_from_row))
     }
 
-    async fn list_schedule_members(
+    async fn rotate_refresh_token(
         &self,
-        schedule_id: Uuid,
-    ) -> AppResult<Vec<(User, ScheduleRole)>> {
-        let rows = sqlx::query(
+        token_hash: &str,
+        next_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        let row = sqlx::query(
             r#"
-            select u.id, u.email, u.is_superadmin, u.created_at, sm.role
-            from schedule_member sm
-            join app_user u on u.id = sm.user_id
-            where sm.schedule_id = $1
-            order by sm.created_at
+            select t.session_id, t.expires_at, t.used_at, s.revoked_at
+            from refresh_token t
+            join auth_session s on s.id = t.session_id
+            where t.token_hash = $1
+            for update
             "#,
         )
-        .bind(schedule_id)
-        .fetch_all(&self.pool)
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::Unauthorized)?;
+        let session_id: Uuid = row.get("session_id");
+        let expires: DateTime<Utc> = row.get("expires_at");
+        let used_at: Option<DateTime<Utc>> = row.get("used_at");
+        let revoked_at: Option<DateTime<Utc>> = row.get("revoked_at");
+        if revoked_at.is_some() || expires <= Utc::now() {
+            return Err(AppError::Unauthorized);
+        }
+        if used_at.is_some() {
+            sqlx::query("update auth_session set revoked_at = now() where id = $1")
+                .bind(session_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(|_| AppError::Internal)?;
+            tx.commit().await.map_err(|_| AppError::Internal)?;
+            return Err(AppError::Unauthorized);
+        }
+
+        sqlx::query("update refresh_token set used_at = now() where token_hash = $1")
+            .bind(token_hash)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        sqlx::query(
+            "insert into refresh_token (token_hash, session_id, expires_at) values ($1, $2, $3)",
+        )
+        .bind(next_hash)
+        .bind(session_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        let row = sqlx::query(
+            r#"
+            update auth_session set last_refreshed_at = now() where id = $1
+            returning id, user_id, created_at, last_refreshed_at, revoked_at
+            "#,
+        )
+        .bind(session_id)
+        .fetch_one(&mut *tx)
         .await
         .map_err(|_| AppError::Internal)?;
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(session_from_row(&row))
+    }
 
-        let mut out = Vec::with_capacity(rows.len());
-        for r in rows {
-            let role_str: String = r.get("role");
-            let role = ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?;
-            out.push((
-                User {
-                    id: r.get("id"),
-                    email: r.get("email"),
-                    is_superadmin: r.get("is_superadmin"),
-                    created_at: r.get("created_at"),
-                },
-                role,
-            ));
-        }
-        Ok(out)
+    async fn revoke_session(&self, session_id: Uuid) -> AppResult<()> {
+        sqlx::query(
+            "update auth_session set revoked_at = now() where id = $1 and revoked_at is null",
+        )
+        .bind(session_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: Uuid) -> AppResult<u64> {
+        let res = sqlx::query(
+            "update auth_session set revoked_at = now() where user_id = $1 and revoked_at is null",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(res.rows_affected())
+    }
+
+    async fn commit(&self, uow: UnitOfWork) -> AppResult<Committed> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        let mut out = Committed::default();
+        for write in uow.writes {
+            match write {
+                Write::CreateSchedule { id, ns } => {
+                    let row = sqlx::query(
+                        r#"
+                        insert into schedule (id, name, subject_type, subject_name, timezone, created_by)
+                        values ($1, $2, $3, $4, $5, $6)
+                        returning id, name, subject_type, subject_name, timezone, swap_requires_approval, claim_policy, check_in_grace_minutes, archived_at, created_by, created_at
+                        "#,
+                    )
+                    .bind(id)
+                    .bind(&ns.name)
+                    .bind(&ns.subject_type)
+                    .bind(&ns.subject_name)
+                    .bind(&ns.timezone)
+                    .bind(ns.created_by)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    out.schedules.push(schedule_from_row(&row)?);
+                }
+                Write::RemoveMember {
+                    schedule_id,
+                    user_id,
+                } => {
+                    ensure_another_admin(&mut tx, schedule_id, user_id).await?;
+                    let res = sqlx::query(
+                        "delete from schedule_member where schedule_id = $1 and user_id = $2",
+                    )
+                    .bind(schedule_id)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::NotFound);
+                    }
+                }
+                Write::AddMember {
+                    schedule_id,
+                    user_id,
+                    role,
+                } => {
+                    sqlx::query(
+                        "insert into schedule_member (schedule_id, user_id, role) values ($1, $2, $3)",
+                    )
+                    .bind(schedule_id)
+                    .bind(user_id)
+                    .bind(role.as_str())
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        if let Some(db) = e.as_database_error() {
+                            if db.is_unique_violation() {
+                                return AppError::Conflict("user already in schedule".to_string());
+                            }
+                        }
+                        AppError::Internal
+                    })?;
+                }
+                Write::CreateShift { id, ns } => {
+                    sqlx::query(
+                        r#"
+                        insert into shift (id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by)
+                        values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                        "#,
+                    )
+                    .bind(id)
+                    .bind(ns.schedule_id)
+                    .bind(ns.starts_at)
+                    .bind(ns.ends_at)
+                    .bind(ns.period.as_str())
+                    .bind(ns.capacity)
+                    .bind(ns.template_id)
+                    .bind(ns.template_week)
+                    .bind(ns.created_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(shift_write_error)?;
+                    if let Some(user_id) = ns.assigned_user_id {
+                        sqlx::query(
+                            "insert into shift_assignee (shift_id, user_id) values ($1, $2)",
+                        )
+                        .bind(id)
+                        .bind(user_id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(assignee_write_error)?;
+                    }
+                    for (position, task) in ns.tasks.into_iter().enumerate() {
+                        sqlx::query(
+                            r#"
+                            insert into shift_task (id, shift_id, schedule_task_id, title, due_at, position)
+                            values ($1, $2, $3, $4, $5, $6)
+                            "#,
+                        )
+                        .bind(Uuid::new_v4())
+                        .bind(id)
+                        .bind(task.schedule_task_id)
+                        .bind(task.title)
+                        .bind(task.due_at)
+                        .bind(position as i32)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|_| AppError::Internal)?;
+                    }
+                    out.shifts.push(reload_shift(&mut *tx, id).await?);
+                }
+                Write::LockTemplate {
+                    template_id,
+                    rotation_state,
+                } => {
+                    let current: serde_json::Value = sqlx::query_scalar(
+                        "select rotation_state from rotation_template where id = $1 for update",
+                    )
+                    .bind(template_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?
+                    .ok_or(AppError::NotFound)?;
+                    if current != rotation_state {
+                        return Err(template_changed_error());
+                    }
+                }
+                Write::EnsureTemplateWeeksUnapplied { template_id, weeks } => {
+                    let applied: bool = sqlx::query_scalar(
+                        "select exists(select 1 from shift where template_id = $1 and template_week = any($2))",
+                    )
+                    .bind(template_id)
+                    .bind(weeks)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if applied {
+                        return Err(template_changed_error());
+                    }
+                }
+                Write::DeleteTemplateWeeks { template_id, weeks } => {
+                    sqlx::query(
+                        "delete from shift where template_id = $1 and template_week = any($2)",
+                    )
+                    .bind(template_id)
+                    .bind(weeks)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                }
+                Write::SetTemplateRotationState {
+                    template_id,
+                    rotation_state,
+                } => {
+                    let res = sqlx::query(
+                        "update rotation_template set rotation_state = $2 where id = $1",
+                    )
+                    .bind(template_id)
+                    .bind(rotation_state)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::NotFound);
+                    }
+                }
+                Write::ReassignShift { shift_id, from, to } => {
+                    let changed = || AppError::Conflict("shift assignment has changed".to_string());
+                    let Some(capacity) = lock_shift(&mut tx, shift_id).await? else {
+                        return Err(changed());
+                    };
+                    match (from, to) {
+                        (Some(from), to) => {
+                            let res = match to {
+                                Some(to) => sqlx::query(
+                                    "update shift_assignee set user_id = $3 where shift_id = $1 and user_id = $2",
+                                )
+                                .bind(shift_id)
+                                .bind(from)
+                                .bind(to),
+                                None => sqlx::query(
+                                    "delete from shift_assignee where shift_id = $1 and user_id = $2",
+                                )
+                                .bind(shift_id)
+                                .bind(from),
+                            }
+                            .execute(&mut *tx)
+                            .await
+                            .map_err(assignee_write_error)?;
+                            if res.rows_affected() == 0 {
+                                return Err(changed());
+                            }
+                        }
+                        (None, Some(to)) => {
+                            let row = sqlx::query(
+                                "select count(*) as assigned from shift_assignee where shift_id = $1",
+                            )
+                            .bind(shift_id)
+                            .fetch_one(&mut *tx)
+                            .await
+                            .map_err(|_| AppError::Internal)?;
+                            let assigned: i64 = row.get("assigned");
+                            if assigned >= i64::from(capacity) {
+                                return Err(AppError::Conflict("shift is full".to_string()));
+                            }
+                            sqlx::query(
+                                "insert into shift_assignee (shift_id, user_id) values ($1, $2)",
+                            )
+                            .bind(shift_id)
+                            .bind(to)
+                            .execute(&mut *tx)
+                            .await
+                            .map_err(assignee_write_error)?;
+                        }
+                        (None, None) => {}
+                    }
+                }
+                Write::ReplaceShiftAssignees { shift_id, user_id } => {
+                    if lock_shift(&mut tx, shift_id).await?.is_none() {
+                        return Err(AppError::NotFound);
+                    }
+                    sqlx::query("delete from shift_assignee where shift_id = $1")
+                        .bind(shift_id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|_| AppError::Internal)?;
+                    if let Some(user_id) = user_id {
+                        sqlx::query(
+                            "insert into shift_assignee (shift_id, user_id) values ($1, $2)",
+                        )
+                        .bind(shift_id)
+                        .bind(user_id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(assignee_write_error)?;
+                    }
+                }
+                Write::SetShiftLead { shift_id, user_id } => {
+                    // Two statements: the one-lead index is checked row by row.
+                    sqlx::query(
+                        "update shift_assignee set lead = false where shift_id = $1 and lead and user_id <> $2",
+                    )
+                    .bind(shift_id)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    let res = sqlx::query(
+                        "update shift_assignee set lead = true where shift_id = $1 and user_id = $2",
+                    )
+                    .bind(shift_id)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::Conflict(
+                            "user is not assigned to this shift".to_string(),
+                        ));
+                    }
+                }
+                Write::SetSwapStatus {
+                    swap_id,
+                    status,
+                    decided_by,
+                } => {
+                    let res = sqlx::query(
+                        r#"
+                        update shift_swap set status = $2, decided_by = $3, updated_at = now()
+                        where id = $1 and status = 'pending'
+                        "#,
+                    )
+                    .bind(swap_id)
+                    .bind(status.as_str())
+                    .bind(decided_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::Conflict(
+                            "swap request is no longer pending".to_string(),
+                        ));
+                    }
+                }
+                Write::MarkSwapCounterpartAccepted { swap_id } => {
+                    let res = sqlx::query(
+                        r#"
+                        update shift_swap set counterpart_accepted_at = now(), updated_at = now()
+                        where id = $1 and status = 'pending' and counterpart_accepted_at is null
+                        "#,
+                    )
+                    .bind(swap_id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::Conflict(
+                            "swap request is no longer awaiting the counterpart".to_string(),
+                        ));
+                    }
+                }
+                Write::SetClaimStatus {
+                    claim_id,
+                    status,
+                    decided_by,
+                } => {
+                    let res = sqlx::query(
+                        r#"
+                        update shift_claim set status = $2, decided_by = $3, decided_at = now()
+                        where id = $1 and status = 'pending'
+                        "#,
+                    )
+                    .bind(claim_id)
+                    .bind(status.as_str())
+                    .bind(decided_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::Conflict(
+                            "claim has already been decided".to_string(),
+                        ));
+                    }
+                }
+                Write::RejectPendingClaims {
+                    shift_id,
+                    decided_by,
+                } => {
+                    sqlx::query(
+                        r#"
+                        update shift_claim set status = 'rejected', decided_by = $2, decided_at = now()
+                        where shift_id = $1 and status = 'pending'
+                        "#,
+                    )
+                    .bind(shift_id)
+                    .bind(decided_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                }
+                Write::CloseMemberRequests {
+                    schedule_id,
+                    user_id,
+                    decided_by,
+                } => {
+                    sqlx::query(
+                        r#"
+                        update shift_claim set status = 'rejected', decided_by = $3, decided_at = now()
+                        where schedule_id = $1 and user_id = $2 and status = 'pending'
+                        "#,
+                    )
+                    .bind(schedule_id)
+                    .bind(user_id)
+                    .bind(decided_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    sqlx::query(
+                        r#"
+                        update shift_swap set status = 'cancelled', decided_by = $3, updated_at = now()
+                        where schedule_id = $1 and status = 'pending'
+                          and (requester_id = $2 or counterpart_id = $2)
+                        "#,
+                    )
+                    .bind(schedule_id)
+                    .bind(user_id)
+                    .bind(decided_by)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                }
+                Write::AddShiftComment { id, nc } => {
+                    let row = sqlx::query(
+                        r#"
+                        insert into shift_comment (id, shift_id, user_id, body)
+                        values ($1, $2, $3, $4)
+                        returning id, shift_id, user_id, body, created_at, edited_at
+                        "#,
+                    )
+                    .bind(id)
+                    .bind(nc.shift_id)
+                    .bind(nc.user_id)
+                    .bind(nc.body)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    out.comments.push(comment_from_row(&row));
+                }
+                Write::SetLeaveStatus {
+                    leave_id,
+                    status,
+                    decided_by,
+                    note,
+                } => {
+                    let res = sqlx::query(
+                        r#"
+                        update leave_request
+                        set status = $2, decided_by = $3, decision_note = $4, decided_at = now()
+                        where id = $1 and status = 'pending'
+                        "#,
+                    )
+                    .bind(leave_id)
+                    .bind(status.as_str())
+                    .bind(decided_by)
+                    .bind(note)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    if res.rows_affected() == 0 {
+                        return Err(AppError::Conflict(
+                            "leave request is no longer pending".to_string(),
+                        ));
+                    }
+                }
+                Write::CorrectAttendance { id, nc } => {
+                    let previous = sqlx::query(
+                        "select checked_in_at, checked_out_at from shift_attendance where shift_id = $1 and user_id = $2 for update",
+                    )
+                    .bind(nc.shift_id)
+                    .bind(nc.user_id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    let (previous_in, previous_out): (
+                        Option<DateTime<Utc>>,
+                        Option<DateTime<Utc>>,
+                    ) = match &previous {
+                        Some(r) => (r.get("checked_in_at"), r.get("checked_out_at")),
+                        None => (None, None),
+                    };
+                    sqlx::query(
+                        r#"
+                        insert into shift_attendance (shift_id, user_id, checked_in_at, checked_out_at)
+                        values ($1, $2, $3, $4)
+                        on conflict (shift_id, user_id) do update
+                        set checked_in_at = excluded.checked_in_at, checked_out_at = excluded.checked_out_at, updated_at = now()
+                        "#,
+                    )
+                    .bind(nc.shift_id)
+                    .bind(nc.user_id)
+                    .bind(nc.checked_in_at)
+                    .bind(nc.checked_out_at)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(attendance_write_error)?;
+                    let row = sqlx::query(
+                        r#"
+                        insert into shift_attendance_correction
+                          (id, shift_id, user_id, corrected_by, previous_checked_in_at, previous_checked_out_at, checked_in_at, checked_out_at, reason)
+                        values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                        returning id, shift_id, user_id, corrected_by, previous_checked_in_at, previous_checked_out_at, checked_in_at, checked_out_at, reason, created_at
+                        "#,
+                    )
+                    .bind(id)
+                    .bind(nc.shift_id)
+                    .bind(nc.user_id)
+                    .bind(nc.corrected_by)
+                    .bind(previous_in)
+                    .bind(previous_out)
+                    .bind(nc.checked_in_at)
+                    .bind(nc.checked_out_at)
+                    .bind(nc.reason)
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(|_| AppError::Internal)?;
+                    out.attendance_corrections
+                        .push(attendance_correction_from_row(&row));
+                }
+            }
+        }
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(out)
+    }
+
+    async fn list_schedules_for_user(
+        &self,
+        user_id: Uuid,
+        include_archived: bool,
+    ) -> AppResult<Vec<ScheduleWithRole>> {
+        let rows = sqlx::query(
+            r#"
+            select s.id, s.name, s.subject_type, s.subject_name, s.timezone, s.swap_requires_approval, s.claim_policy, s.check_in_grace_minutes, s.archived_at, s.created_by, s.created_at, sm.role
+            from schedule s
+            join schedule_member sm on sm.schedule_id = s.id
+            where sm.user_id = $1 and ($2 or s.archived_at is null)
+            order by s.created_at desc
+            "#,
+        )
+        .bind(user_id)
+        .bind(include_archived)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        let mut out = Vec::with_capacity(rows.len());
+        for r in rows {
+            let role_str: String = r.get("role");
+            let role = ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?;
+            out.push(ScheduleWithRole {
+                schedule: schedule_from_row(&r)?,
+                role,
+            });
+        }
+        Ok(out)
+    }
+
+    async fn get_schedule(&self, schedule_id: Uuid) -> AppResult<Option<Schedule>> {
+        let row = sqlx::query(
+            "select id, name, subject_type, subject_name, timezone, swap_requires_approval, claim_policy, check_in_grace_minutes, archived_at, created_by, created_at from schedule where id = $1",
+        )
+        .bind(schedule_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(schedule_from_row).transpose()
+    }
+
+    async fn update_schedule(&self, schedule_id: Uuid, us: UpdateSchedule) -> AppResult<Schedule> {
+        let row = sqlx::query(
+            r#"
+            update schedule
+            set name = $2, subject_type = $3, subject_name = $4, timezone = $5,
+                swap_requires_approval = $6, claim_policy = $7, check_in_grace_minutes = $8
+            where id = $1
+            returning id, name, subject_type, subject_name, timezone, swap_requires_approval, claim_policy, check_in_grace_minutes, archived_at, created_by, created_at
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(&us.name)
+        .bind(&us.subject_type)
+        .bind(&us.subject_name)
+        .bind(&us.timezone)
+        .bind(us.swap_requires_approval)
+        .bind(us.claim_policy.as_str())
+        .bind(us.check_in_grace_minutes)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::NotFound)?;
+
+        schedule_from_row(&row)
+    }
+
+    async fn set_schedule_archived(
+        &self,
+        schedule_id: Uuid,
+        archived: bool,
+    ) -> AppResult<Schedule> {
+        let row = sqlx::query(
+            r#"
+            update schedule
+            set archived_at = case when $2 then coalesce(archived_at, now()) end
+            where id = $1
+            returning id, name, subject_type, subject_name, timezone, swap_requires_approval, claim_policy, check_in_grace_minutes, archived_at, created_by, created_at
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(archived)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::NotFound)?;
+
+        schedule_from_row(&row)
+    }
+
+    async fn delete_schedule(&self, schedule_id: Uuid) -> AppResult<ScheduleDeletion> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        // Locking the row holds off inserts that reference it until we're done.
+        sqlx::query("select id from schedule where id = $1 for update")
+            .bind(schedule_id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|_| AppError::Internal)?
+            .ok_or(AppError::NotFound)?;
+        let r = sqlx::query(
+            r#"
+            select
+              (select count(*) from schedule_member where schedule_id = $1) as members,
+              (select count(*) from shift where schedule_id = $1) as shifts,
+              (select count(*) from shift_comment c join shift s on s.id = c.shift_id
+                 where s.schedule_id = $1) as comments,
+              (select count(*) from rotation_template where schedule_id = $1) as templates,
+              (select count(*) from shift_swap where schedule_id = $1) as swaps,
+              (select count(*) from shift_claim where schedule_id = $1) as claims,
+              (select count(*) from member_unavailability where schedule_id = $1)
+                + (select count(*) from member_availability_exception where schedule_id = $1)
+                as availability,
+              (select count(*) from leave_request where schedule_id = $1) as leave_requests,
+              (select count(*) from coverage_rule where schedule_id = $1) as coverage_rules,
+              (select count(*) from shift_attendance a join shift s on s.id = a.shift_id
+                 where s.schedule_id = $1) as attendance,
+              (select count(*) from shift_attendance_correction c join shift s on s.id = c.shift_id
+                 where s.schedule_id = $1) as attendance_corrections,
+              (select count(*) from schedule_task where schedule_id = $1) as schedule_tasks,
+              (select count(*) from shift_task t join shift s on s.id = t.shift_id
+                 where s.schedule_id = $1) as shift_tasks,
+              (select count(*) from schedule_invite where schedule_id = $1) as invites
+            "#,
+        )
+        .bind(schedule_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        let deleted = ScheduleDeletion {
+            members: r.get("members"),
+            shifts: r.get("shifts"),
+            comments: r.get("comments"),
+            templates: r.get("templates"),
+            swaps: r.get("swaps"),
+            claims: r.get("claims"),
+            availability: r.get("availability"),
+            leave_requests: r.get("leave_requests"),
+            coverage_rules: r.get("coverage_rules"),
+            attendance: r.get("attendance"),
+            attendance_corrections: r.get("attendance_corrections"),
+            schedule_tasks: r.get("schedule_tasks"),
+            shift_tasks: r.get("shift_tasks"),
+            invites: r.get("invites"),
+        };
+        sqlx::query("delete from schedule where id = $1")
+            .bind(schedule_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(deleted)
+    }
+
+    async fn get_schedule_role(
+        &self,
+        schedule_id: Uuid,
+        user_id: Uuid,
+    ) -> AppResult<Option<ScheduleRole>> {
+        let row =
+            sqlx::query("select role from schedule_member where schedule_id = $1 and user_id = $2")
+                .bind(schedule_id)
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|_| AppError::Internal)?;
+
+        Ok(match row {
+            None => None,
+            Some(r) => {
+                let role_str: String = r.get("role");
+                Some(ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?)
+            }
+        })
+    }
+
+    async fn list_schedule_members(
+        &self,
+        schedule_id: Uuid,
+    ) -> AppResult<Vec<(User, ScheduleRole)>> {
+        let rows = sqlx::query(
+            r#"
+            select u.id, u.email, u.is_superadmin, u.email_verified_at, u.created_at, sm.role
+            from schedule_member sm
+            join app_user u on u.id = sm.user_id
+            where sm.schedule_id = $1
+            order by sm.created_at
+            "#,
+        )
+        .bind(schedule_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        let mut out = Vec::with_capacity(rows.len());
+        for r in rows {
+            let role_str: String = r.get("role");
+            let role = ScheduleRole::try_from(role_str.as_str()).map_err(|_| AppError::Internal)?;
+            out.push((user_from_row(&r), role));
+        }
+        Ok(out)
     }
 
     async fn add_member(
@@ -374,194 +2244,389 @@ impl Repo for PgRepo {
         user_id: Uuid,
         role: ScheduleRole,
     ) -> AppResult<()> {
-        let role = role.as_str();
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
+        if role != ScheduleRole::Admin {
+            ensure_another_admin(&mut tx, schedule_id, user_id).await?;
+        }
         let res = sqlx::query(
             "update schedule_member set role = $3 where schedule_id = $1 and user_id = $2",
         )
         .bind(schedule_id)
         .bind(user_id)
-        .bind(role)
-        .execute(&self.pool)
+        .bind(role.as_str())
+        .execute(&mut *tx)
         .await
         .map_err(|_| AppError::Internal)?;
         if res.rows_affected() == 0 {
             return Err(AppError::NotFound);
         }
+        tx.commit().await.map_err(|_| AppError::Internal)?;
         Ok(())
     }
 
-    async fn create_shift(&self, ns: NewShift) -> AppResult<Shift> {
-        let id = Uuid::new_v4();
+    async fn create_invite(&self, ni: NewInvite) -> AppResult<ScheduleInvite> {
         let row = sqlx::query(
             r#"
-            insert into shift (id, schedule_id, starts_at, ends_at, period, created_by)
-            values ($1, $2, $3, $4, $5, $6)
-            returning id, schedule_id, starts_at, ends_at, period, assigned_user_id, created_by, created_at
+            insert into schedule_invite (id, schedule_id, email, token_hash, role, max_uses, expires_at, created_by)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)
+            returning id, schedule_id, email, role, max_uses, uses, expires_at, created_by, created_at, revoked_at
             "#,
         )
-        .bind(id)
-        .bind(ns.schedule_id)
-        .bind(ns.starts_at)
-        .bind(ns.ends_at)
-        .bind(ns.period.as_str())
-        .bind(ns.created_by)
+        .bind(Uuid::new_v4())
+        .bind(ni.schedule_id)
+        .bind(ni.email)
+        .bind(ni.token_hash)
+        .bind(ni.role.as_str())
+        .bind(ni.max_uses)
+        .bind(ni.expires_at)
+        .bind(ni.created_by)
         .fetch_one(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        let period_str: String = row.get("period");
-        let period = Period::try_from(period_str.as_str()).map_err(|_| AppError::Internal)?;
-        Ok(Shift {
-            id: row.get("id"),
-            schedule_id: row.get("schedule_id"),
-            starts_at: row.get("starts_at"),
-            ends_at: row.get("ends_at"),
-            period,
-            assigned_user_id: row.get("assigned_user_id"),
-            created_by: row.get("created_by"),
-            created_at: row.get("created_at"),
-        })
+        invite_from_row(&row)
     }
 
-    async fn list_shifts(
-        &self,
-        schedule_id: Uuid,
-        from: DateTime<Utc>,
-        to: DateTime<Utc>,
-    ) -> AppResult<Vec<Shift>> {
+    async fn list_pending_invites(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleInvite>> {
         let rows = sqlx::query(
             r#"
-            select id, schedule_id, starts_at, ends_at, period, assigned_user_id, created_by, created_at
-            from shift
-            where schedule_id = $1 and starts_at >= $2 and starts_at < $3
-            order by starts_at asc
+            select id, schedule_id, email, role, max_uses, uses, expires_at, created_by, created_at, revoked_at
+            from schedule_invite
+            where schedule_id = $1 and revoked_at is null and expires_at > now()
+              and (max_uses is null or uses < max_uses)
+            order by created_at desc
             "#,
         )
         .bind(schedule_id)
-        .bind(from)
-        .bind(to)
         .fetch_all(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        let mut out = Vec::with_capacity(rows.len());
-        for r in rows {
-            let period_str: String = r.get("period");
-            let period = Period::try_from(period_str.as_str()).map_err(|_| AppError::Internal)?;
-            out.push(Shift {
-                id: r.get("id"),
-                schedule_id: r.get("schedule_id"),
-                starts_at: r.get("starts_at"),
-                ends_at: r.get("ends_at"),
-                period,
-                assigned_user_id: r.get("assigned_user_id"),
-                created_by: r.get("created_by"),
-                created_at: r.get("created_at"),
-            });
-        }
-        Ok(out)
+        rows.iter().map(invite_from_row).collect()
     }
 
-    async fn get_shift(&self, shift_id: Uuid) -> AppResult<Option<Shift>> {
+    async fn get_invite(&self, invite_id: Uuid) -> AppResult<Option<ScheduleInvite>> {
         let row = sqlx::query(
-            "select id, schedule_id, starts_at, ends_at, period, assigned_user_id, created_by, created_at from shift where id = $1",
+            r#"
+            select id, schedule_id, email, role, max_uses, uses, expires_at, created_by, created_at, revoked_at
+            from schedule_invite
+            where id = $1
+            "#,
         )
-        .bind(shift_id)
+        .bind(invite_id)
         .fetch_optional(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        Ok(row.map(|r| {
-            let period_str: String = r.get("period");
-            let period = Period::try_from(period_str.as_str()).unwrap_or(Period::Morning);
-            Shift {
-                id: r.get("id"),
-                schedule_id: r.get("schedule_id"),
-                starts_at: r.get("starts_at"),
-                ends_at: r.get("ends_at"),
-                period,
-                assigned_user_id: r.get("assigned_user_id"),
-                created_by: r.get("created_by"),
-                created_at: r.get("created_at"),
-            }
-        }))
-    }
-
-    async fn assign_shift(&self, shift_id: Uuid, assigned_user_id: Option<Uuid>) -> AppResult<()> {
-        let res = sqlx::query("update shift set assigned_user_id = $2 where id = $1")
-            .bind(shift_id)
-            .bind(assigned_user_id)
-            .execute(&self.pool)
-            .await
-            .map_err(|_| AppError::Internal)?;
-        if res.rows_affected() == 0 {
-            return Err(AppError::NotFound);
-        }
-        Ok(())
+        row.as_ref().map(invite_from_row).transpose()
     }
 
-    async fn add_shift_comment(&self, nc: NewShiftComment) -> AppResult<ShiftComment> {
-        let id = Uuid::new_v4();
+    async fn find_invite_by_token(&self, token_hash: &str) -> AppResult<Option<ScheduleInvite>> {
         let row = sqlx::query(
             r#"
-            insert into shift_comment (id, shift_id, user_id, body)
-            values ($1, $2, $3, $4)
-            returning id, shift_id, user_id, body, created_at
+            select id, schedule_id, email, role, max_uses, uses, expires_at, created_by, created_at, revoked_at
+            from schedule_invite
+            where token_hash = $1
             "#,
         )
-        .bind(id)
-        .bind(nc.shift_id)
-        .bind(nc.user_id)
-        .bind(nc.body)
-        .fetch_one(&self.pool)
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
-        Ok(ShiftComment {
-            id: row.get("id"),
-            shift_id: row.get("shift_id"),
-            user_id: row.get("user_id"),
-            body: row.get("body"),
-            created_at: row.get("created_at"),
-        })
+        row.as_ref().map(invite_from_row).transpose()
     }
 
-    async fn list_shift_comments(&self, shift_id: Uuid) -> AppResult<Vec<ShiftComment>> {
-        let rows = sqlx::query(
-            "select id, shift_id, user_id, body, created_at from shift_comment where shift_id = $1 order by created_at asc",
+    async fn revoke_invite(&self, invite_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query(
+            "update schedule_invite set revoked_at = now() where id = $1 and revoked_at is null",
         )
-        .bind(shift_id)
-        .fetch_all(&self.pool)
+        .bind(invite_id)
+        .execute(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
-
-        Ok(rows
-            .into_iter()
-            .map(|r| ShiftComment {
-                id: r.get("id"),
-                shift_id: r.get("shift_id"),
-                user_id: r.get("user_id"),
-                body: r.get("body"),
-                created_at: r.get("created_at"),
-            })
-            .collect())
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
     }
 
-    async fn create_template(&self, nt: NewTemplate) -> AppResult<RotationTemplate> {
-        let id = Uuid::new_v4();
+    async fn accept_invite(&self, invite_id: Uuid, user_id: Uuid) -> AppResult<ScheduleInvite> {
+        let mut tx = self.pool.begin().await.map_err(|_| AppError::Internal)?;
         let row = sqlx::query(
             r#"
-            insert into rotation_template (id, schedule_id, name, definition, created_by)
-            values ($1, $2, $3, $4, $5)
-            returning id, schedule_id, name, definition, created_by, created_at
+            update schedule_invite set uses = uses + 1
+            where id = $1 and revoked_at is null and expires_at > now()
+              and (max_uses is null or uses < max_uses)
+            returning id, schedule_id, email, role, max_uses, uses, expires_at, created_by, created_at, revoked_at
             "#,
         )
-        .bind(id)
-        .bind(nt.schedule_id)
-        .bind(nt.name)
-        .bind(nt.definition)
-        .bind(nt.created_by)
-        .fetch_one(&self.pool)
+        .bind(invite_id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(|| AppError::Conflict("invite is no longer valid".to_string()))?;
+        let invite = invite_from_row(&row)?;
+        sqlx::query("insert into schedule_member (schedule_id, user_id, role) values ($1, $2, $3)")
+            .bind(invite.schedule_id)
+            .bind(user_id)
+            .bind(invite.role.as_str())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                if let Some(db) = e.as_database_error() {
+                    if db.is_unique_violation() {
+                        return AppError::Conflict("user already in schedule".to_string());
+                    }
+                }
+                AppError::Internal
+            })?;
+        tx.commit().await.map_err(|_| AppError::Internal)?;
+        Ok(invite)
+    }
+
+    async fn list_shifts(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by, created_at, updated_at, assignee_ids, assignee_leads, assignee_assigned_at
+            from shift_with_assignees
+            where schedule_id = $1 and starts_at >= $2 and starts_at < $3
+            order by starts_at asc
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(shift_from_row).collect()
+    }
+
+    async fn get_shift(&self, shift_id: Uuid) -> AppResult<Option<Shift>> {
+        let row = sqlx::query(
+            "select id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by, created_at, updated_at, assignee_ids, assignee_leads, assignee_assigned_at from shift_with_assignees where id = $1",
+        )
+        .bind(shift_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(shift_from_row).transpose()
+    }
+
+    async fn list_assigned_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by, created_at, updated_at, assignee_ids, assignee_leads, assignee_assigned_at
+            from shift_with_assignees
+            where id in (select shift_id from shift_assignee where user_id = $1)
+              and starts_at >= $2 and starts_at < $3
+            order by starts_at asc
+            "#,
+        )
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(shift_from_row).collect()
+    }
+
+    async fn update_shift(&self, shift_id: Uuid, us: UpdateShift) -> AppResult<Shift> {
+        let res = sqlx::query(
+            "update shift set starts_at = $2, ends_at = $3, period = $4, capacity = $5, updated_at = now() where id = $1",
+        )
+        .bind(shift_id)
+        .bind(us.starts_at)
+        .bind(us.ends_at)
+        .bind(us.period.as_str())
+        .bind(us.capacity)
+        .execute(&self.pool)
+        .await
+        .map_err(shift_write_error)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+
+        reload_shift(&self.pool, shift_id).await
+    }
+
+    async fn delete_shift(&self, shift_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from shift where id = $1")
+            .bind(shift_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_overlapping_shifts(
+        &self,
+        user_id: Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        exclude_shift_id: Uuid,
+    ) -> AppResult<Vec<Shift>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, starts_at, ends_at, period, capacity, template_id, template_week, created_by, created_at, updated_at, assignee_ids, assignee_leads, assignee_assigned_at
+            from shift_with_assignees
+            where id in (select shift_id from shift_assignee where user_id = $1)
+              and starts_at < $3 and ends_at > $2 and id <> $4
+            order by starts_at asc
+            "#,
+        )
+        .bind(user_id)
+        .bind(starts_at)
+        .bind(ends_at)
+        .bind(exclude_shift_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(shift_from_row).collect()
+    }
+
+    async fn add_shift_comment(&self, nc: NewShiftComment) -> AppResult<ShiftComment> {
+        let id = Uuid::new_v4();
+        let row = sqlx::query(
+            r#"
+            insert into shift_comment (id, shift_id, user_id, body)
+            values ($1, $2, $3, $4)
+            returning id, shift_id, user_id, body, created_at, edited_at
+            "#,
+        )
+        .bind(id)
+        .bind(nc.shift_id)
+        .bind(nc.user_id)
+        .bind(nc.body)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(comment_from_row(&row))
+    }
+
+    async fn list_shift_comments(
+        &self,
+        shift_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<ShiftComment>> {
+        let rows = sqlx::query(
+            r#"
+            select id, shift_id, user_id, body, created_at, edited_at
+            from shift_comment
+            where shift_id = $1
+            order by created_at asc, id asc
+            limit $2 offset $3
+            "#,
+        )
+        .bind(shift_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(comment_from_row).collect())
+    }
+
+    async fn list_comments_for_shifts(&self, shift_ids: &[Uuid]) -> AppResult<Vec<ShiftComment>> {
+        let rows = sqlx::query(
+            r#"
+            select id, shift_id, user_id, body, created_at, edited_at
+            from shift_comment
+            where shift_id = any($1)
+            order by created_at asc, id asc
+            "#,
+        )
+        .bind(shift_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(comment_from_row).collect())
+    }
+
+    async fn get_shift_comment(&self, comment_id: Uuid) -> AppResult<Option<ShiftComment>> {
+        let row = sqlx::query(
+            "select id, shift_id, user_id, body, created_at, edited_at from shift_comment where id = $1",
+        )
+        .bind(comment_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(row.as_ref().map(comment_from_row))
+    }
+
+    async fn update_shift_comment(
+        &self,
+        comment_id: Uuid,
+        body: String,
+    ) -> AppResult<ShiftComment> {
+        let row = sqlx::query(
+            r#"
+            update shift_comment set body = $2, edited_at = now()
+            where id = $1
+            returning id, shift_id, user_id, body, created_at, edited_at
+            "#,
+        )
+        .bind(comment_id)
+        .bind(body)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::NotFound)?;
+
+        Ok(comment_from_row(&row))
+    }
+
+    async fn delete_shift_comment(&self, comment_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from shift_comment where id = $1")
+            .bind(comment_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_template(&self, nt: NewTemplate) -> AppResult<RotationTemplate> {
+        let id = Uuid::new_v4();
+        let row = sqlx::query(
+            r#"
+            insert into rotation_template (id, schedule_id, name, definition, created_by)
+            values ($1, $2, $3, $4, $5)
+            returning id, schedule_id, name, definition, rotation_state, created_by, created_at
+            "#,
+        )
+        .bind(id)
+        .bind(nt.schedule_id)
+        .bind(nt.name)
+        .bind(nt.definition)
+        .bind(nt.created_by)
+        .fetch_one(&self.pool)
         .await
         .map_err(|_| AppError::Internal)?;
 
@@ -570,6 +2635,7 @@ impl Repo for PgRepo {
             schedule_id: row.get("schedule_id"),
             name: row.get("name"),
             definition: row.get("definition"),
+            rotation_state: row.get("rotation_state"),
             created_by: row.get("created_by"),
             created_at: row.get("created_at"),
         })
@@ -577,7 +2643,7 @@ impl Repo for PgRepo {
 
     async fn list_templates(&self, schedule_id: Uuid) -> AppResult<Vec<RotationTemplate>> {
         let rows = sqlx::query(
-            "select id, schedule_id, name, definition, created_by, created_at from rotation_template where schedule_id = $1 order by created_at desc",
+            "select id, schedule_id, name, definition, rotation_state, created_by, created_at from rotation_template where schedule_id = $1 order by created_at desc",
         )
         .bind(schedule_id)
         .fetch_all(&self.pool)
@@ -591,6 +2657,7 @@ impl Repo for PgRepo {
                 schedule_id: r.get("schedule_id"),
                 name: r.get("name"),
                 definition: r.get("definition"),
+                rotation_state: r.get("rotation_state"),
                 created_by: r.get("created_by"),
                 created_at: r.get("created_at"),
             })
@@ -599,7 +2666,7 @@ impl Repo for PgRepo {
 
     async fn get_template(&self, template_id: Uuid) -> AppResult<Option<RotationTemplate>> {
         let row = sqlx::query(
-            "select id, schedule_id, name, definition, created_by, created_at from rotation_template where id = $1",
+            "select id, schedule_id, name, definition, rotation_state, created_by, created_at from rotation_template where id = $1",
         )
         .bind(template_id)
         .fetch_optional(&self.pool)
@@ -611,284 +2678,3122 @@ impl Repo for PgRepo {
             schedule_id: r.get("schedule_id"),
             name: r.get("name"),
             definition: r.get("definition"),
+            rotation_state: r.get("rotation_state"),
             created_by: r.get("created_by"),
             created_at: r.get("created_at"),
         }))
     }
-}
 
-#[derive(Default)]
-struct MemState {
-    users: HashMap<Uuid, (User, String)>,
-    schedules: HashMap<Uuid, Schedule>,
-    members: HashMap<(Uuid, Uuid), ScheduleRole>,
-    shifts: HashMap<Uuid, Shift>,
-    comments: HashMap<Uuid, Vec<ShiftComment>>,
-    templates: HashMap<Uuid, RotationTemplate>,
-}
+    async fn list_template_weeks(
+        &self,
+        template_id: Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> AppResult<Vec<NaiveDate>> {
+        let rows = sqlx::query(
+            r#"
+            select distinct template_week from shift
+            where template_id = $1 and template_week between $2 and $3
+            order by template_week
+            "#,
+        )
+        .bind(template_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(rows.iter().map(|r| r.get("template_week")).collect())
+    }
 
-#[derive(Clone, Default)]
-pub struct MemRepo {
-    state: Arc<RwLock<MemState>>,
-}
+    async fn create_swap(&self, ns: NewSwap) -> AppResult<ShiftSwap> {
+        let row = sqlx::query(
+            r#"
+            insert into shift_swap (id, schedule_id, shift_id, requester_id, counterpart_id, counterpart_shift_id, message, expires_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)
+            returning id, schedule_id, shift_id, requester_id, counterpart_id, counterpart_shift_id, message, status, counterpart_accepted_at, decided_by, expires_at, created_at, updated_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(ns.schedule_id)
+        .bind(ns.shift_id)
+        .bind(ns.requester_id)
+        .bind(ns.counterpart_id)
+        .bind(ns.counterpart_shift_id)
+        .bind(ns.message)
+        .bind(ns.expires_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
 
-impl MemRepo {
-    pub fn new() -> Self {
-        Self::default()
+        swap_from_row(&row)
     }
-}
 
-#[async_trait]
-impl Repo for MemRepo {
-    async fn count_users(&self) -> AppResult<i64> {
-        Ok(self.state.read().unwrap().users.len() as i64)
+    async fn get_swap(&self, swap_id: Uuid) -> AppResult<Option<ShiftSwap>> {
+        let row = sqlx::query(
+            "select id, schedule_id, shift_id, requester_id, counterpart_id, counterpart_shift_id, message, status, counterpart_accepted_at, decided_by, expires_at, created_at, updated_at from shift_swap where id = $1",
+        )
+        .bind(swap_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(swap_from_row).transpose()
     }
 
-    async fn create_user(&self, nu: NewUser) -> AppResult<User> {
-        let mut s = self.state.write().unwrap();
-        if s.users.values().any(|(u, _)| u.email == nu.email) {
-            return Err(AppError::Conflict("email already exists".to_string()));
-        }
-        let id = Uuid::new_v4();
-        let user = User {
-            id,
-            email: nu.email,
-            is_superadmin: nu.is_superadmin,
-            created_at: Utc::now(),
-        };
-        s.users.insert(id, (user.clone(), nu.password_hash));
-        Ok(user)
+    async fn list_swaps(
+        &self,
+        schedule_id: Uuid,
+        involving: Option<Uuid>,
+        status: Option<SwapStatus>,
+    ) -> AppResult<Vec<ShiftSwap>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, shift_id, requester_id, counterpart_id, counterpart_shift_id, message, status, counterpart_accepted_at, decided_by, expires_at, created_at, updated_at
+            from shift_swap
+            where schedule_id = $1
+              and ($2::uuid is null or requester_id = $2 or counterpart_id = $2)
+              and ($3::text is null or status = $3)
+            order by created_at desc
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(involving)
+        .bind(status.map(SwapStatus::as_str))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(swap_from_row).collect()
     }
 
-    async fn find_user_by_email(&self, email: &str) -> AppResult<Option<(User, String)>> {
-        let s = self.state.read().unwrap();
-        Ok(s.users
+    async fn expire_swaps(&self, now: DateTime<Utc>) -> AppResult<()> {
+        sqlx::query(
+            "update shift_swap set status = 'expired', updated_at = now() where status = 'pending' and expires_at <= $1",
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
+
+    async fn list_open_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let rows = sqlx::query(
+            r#"
+            select s.id, s.schedule_id, s.starts_at, s.ends_at, s.period, s.capacity, s.template_id, s.template_week, s.created_by, s.created_at, s.updated_at, s.assignee_ids, s.assignee_leads, s.assignee_assigned_at
+            from shift_with_assignees s
+            join schedule_member sm on sm.schedule_id = s.schedule_id
+            where sm.user_id = $1
+              and cardinality(s.assignee_ids) < s.capacity
+              and not ($1 = any(s.assignee_ids))
+              and s.starts_at >= $2 and s.starts_at < $3
+            order by s.starts_at asc
+            "#,
+        )
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(shift_from_row).collect()
+    }
+
+    async fn create_claim(&self, nc: NewClaim) -> AppResult<ShiftClaim> {
+        let row = sqlx::query(
+            r#"
+            insert into shift_claim (id, schedule_id, shift_id, user_id)
+            values ($1, $2, $3, $4)
+            returning id, schedule_id, shift_id, user_id, status, decided_by, created_at, decided_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(nc.schedule_id)
+        .bind(nc.shift_id)
+        .bind(nc.user_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            if let Some(db) = e.as_database_error() {
+                if db.is_unique_violation() {
+                    return AppError::Conflict("you already claimed this shift".to_string());
+                }
+            }
+            AppError::Internal
+        })?;
+
+        claim_from_row(&row)
+    }
+
+    async fn get_claim(&self, claim_id: Uuid) -> AppResult<Option<ShiftClaim>> {
+        let row = sqlx::query(
+            "select id, schedule_id, shift_id, user_id, status, decided_by, created_at, decided_at from shift_claim where id = $1",
+        )
+        .bind(claim_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(claim_from_row).transpose()
+    }
+
+    async fn list_claims(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+        status: Option<ClaimStatus>,
+    ) -> AppResult<Vec<ShiftClaim>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, shift_id, user_id, status, decided_by, created_at, decided_at
+            from shift_claim
+            where schedule_id = $1
+              and ($2::uuid is null or user_id = $2)
+              and ($3::text is null or status = $3)
+            order by created_at asc
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .bind(status.map(ClaimStatus::as_str))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(claim_from_row).collect()
+    }
+
+    async fn list_weekly_unavailability(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<WeeklyUnavailability>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, user_id, dow, period, note, created_at
+            from member_unavailability
+            where schedule_id = $1 and ($2::uuid is null or user_id = $2)
+            order by user_id, dow, period
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(weekly_unavailability_from_row).collect()
+    }
+
+    async fn get_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+    ) -> AppResult<Option<WeeklyUnavailability>> {
+        let row = sqlx::query(
+            "select id, schedule_id, user_id, dow, period, note, created_at from member_unavailability where id = $1",
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(weekly_unavailability_from_row).transpose()
+    }
+
+    async fn create_weekly_unavailability(
+        &self,
+        nw: NewWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability> {
+        let row = sqlx::query(
+            r#"
+            insert into member_unavailability (id, schedule_id, user_id, dow, period, note)
+            values ($1, $2, $3, $4, $5, $6)
+            returning id, schedule_id, user_id, dow, period, note, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(nw.schedule_id)
+        .bind(nw.user_id)
+        .bind(nw.dow)
+        .bind(nw.period.as_str())
+        .bind(nw.note)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(weekly_unavailability_write_error)?;
+
+        weekly_unavailability_from_row(&row)
+    }
+
+    async fn update_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+        uw: UpdateWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability> {
+        let row = sqlx::query(
+            r#"
+            update member_unavailability set dow = $2, period = $3, note = $4
+            where id = $1
+            returning id, schedule_id, user_id, dow, period, note, created_at
+            "#,
+        )
+        .bind(rule_id)
+        .bind(uw.dow)
+        .bind(uw.period.as_str())
+        .bind(uw.note)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(weekly_unavailability_write_error)?
+        .ok_or(AppError::NotFound)?;
+
+        weekly_unavailability_from_row(&row)
+    }
+
+    async fn delete_weekly_unavailability(&self, rule_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from member_unavailability where id = $1")
+            .bind(rule_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_availability_exceptions(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<AvailabilityException>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, user_id, starts_on, ends_on, available, note, created_at
+            from member_availability_exception
+            where schedule_id = $1 and ($2::uuid is null or user_id = $2)
+            order by starts_on, user_id
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(availability_exception_from_row).collect())
+    }
+
+    async fn get_availability_exception(
+        &self,
+        exception_id: Uuid,
+    ) -> AppResult<Option<AvailabilityException>> {
+        let row = sqlx::query(
+            "select id, schedule_id, user_id, starts_on, ends_on, available, note, created_at from member_availability_exception where id = $1",
+        )
+        .bind(exception_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(row.as_ref().map(availability_exception_from_row))
+    }
+
+    async fn create_availability_exception(
+        &self,
+        ne: NewAvailabilityException,
+    ) -> AppResult<AvailabilityException> {
+        let row = sqlx::query(
+            r#"
+            insert into member_availability_exception (id, schedule_id, user_id, starts_on, ends_on, available, note)
+            values ($1, $2, $3, $4, $5, $6, $7)
+            returning id, schedule_id, user_id, starts_on, ends_on, available, note, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(ne.schedule_id)
+        .bind(ne.user_id)
+        .bind(ne.starts_on)
+        .bind(ne.ends_on)
+        .bind(ne.available)
+        .bind(ne.note)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(availability_exception_write_error)?;
+
+        Ok(availability_exception_from_row(&row))
+    }
+
+    async fn update_availability_exception(
+        &self,
+        exception_id: Uuid,
+        ue: UpdateAvailabilityException,
+    ) -> AppResult<AvailabilityException> {
+        let row = sqlx::query(
+            r#"
+            update member_availability_exception
+            set starts_on = $2, ends_on = $3, available = $4, note = $5
+            where id = $1
+            returning id, schedule_id, user_id, starts_on, ends_on, available, note, created_at
+            "#,
+        )
+        .bind(exception_id)
+        .bind(ue.starts_on)
+        .bind(ue.ends_on)
+        .bind(ue.available)
+        .bind(ue.note)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(availability_exception_write_error)?
+        .ok_or(AppError::NotFound)?;
+
+        Ok(availability_exception_from_row(&row))
+    }
+
+    async fn delete_availability_exception(&self, exception_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from member_availability_exception where id = $1")
+            .bind(exception_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_leave_request(&self, nl: NewLeaveRequest) -> AppResult<LeaveRequest> {
+        let row = sqlx::query(
+            r#"
+            insert into leave_request (id, schedule_id, user_id, starts_on, ends_on, reason)
+            values ($1, $2, $3, $4, $5, $6)
+            returning id, schedule_id, user_id, starts_on, ends_on, reason, status, decided_by, decision_note, decided_at, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(nl.schedule_id)
+        .bind(nl.user_id)
+        .bind(nl.starts_on)
+        .bind(nl.ends_on)
+        .bind(nl.reason)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            if let Some(db) = e.as_database_error() {
+                if db.is_check_violation() && db.constraint() == Some("leave_request_range_ok") {
+                    return AppError::BadRequest(
+                        "ends_on must not be before starts_on".to_string(),
+                    );
+                }
+            }
+            AppError::Internal
+        })?;
+
+        leave_from_row(&row)
+    }
+
+    async fn get_leave_request(&self, leave_id: Uuid) -> AppResult<Option<LeaveRequest>> {
+        let row = sqlx::query(
+            "select id, schedule_id, user_id, starts_on, ends_on, reason, status, decided_by, decision_note, decided_at, created_at from leave_request where id = $1",
+        )
+        .bind(leave_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(leave_from_row).transpose()
+    }
+
+    async fn list_leave_requests(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+        status: Option<LeaveStatus>,
+    ) -> AppResult<Vec<LeaveRequest>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, user_id, starts_on, ends_on, reason, status, decided_by, decision_note, decided_at, created_at
+            from leave_request
+            where schedule_id = $1
+              and ($2::uuid is null or user_id = $2)
+              and ($3::text is null or status = $3)
+            order by starts_on asc, created_at asc
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(user_id)
+        .bind(status.map(LeaveStatus::as_str))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(leave_from_row).collect()
+    }
+
+    async fn tally_shifts(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        zone: Tz,
+    ) -> AppResult<Vec<ShiftTally>> {
+        let rows = sqlx::query(
+            r#"
+            select a.user_id,
+                   s.period,
+                   extract(isodow from s.starts_at at time zone $4) >= 6 as weekend,
+                   count(*) as shifts,
+                   sum(extract(epoch from s.ends_at - s.starts_at) / 60)::bigint as minutes
+            from shift s
+            join shift_assignee a on a.shift_id = s.id
+            where s.schedule_id = $1 and s.starts_at >= $2 and s.starts_at < $3
+            group by 1, 2, 3
+            union all
+            select null,
+                   s.period,
+                   extract(isodow from s.starts_at at time zone $4) >= 6,
+                   sum(s.capacity - f.assigned)::bigint,
+                   sum((s.capacity - f.assigned) * extract(epoch from s.ends_at - s.starts_at) / 60)::bigint
+            from shift s
+            cross join lateral (
+              select count(*) as assigned from shift_assignee a where a.shift_id = s.id
+            ) f
+            where s.schedule_id = $1 and s.starts_at >= $2 and s.starts_at < $3
+              and f.assigned < s.capacity
+            group by 2, 3
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(from)
+        .bind(to)
+        .bind(zone.name())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter()
+            .map(|r| {
+                let period_str: String = r.get("period");
+                Ok(ShiftTally {
+                    user_id: r.get("user_id"),
+                    period: Period::try_from(period_str.as_str())
+                        .map_err(|_| AppError::Internal)?,
+                    weekend: r.get("weekend"),
+                    shifts: r.get("shifts"),
+                    minutes: r.get("minutes"),
+                })
+            })
+            .collect()
+    }
+
+    async fn list_coverage_rules(&self, schedule_id: Uuid) -> AppResult<Vec<CoverageRule>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, dow, period, required, created_at
+            from coverage_rule
+            where schedule_id = $1
+            order by dow, period
+            "#,
+        )
+        .bind(schedule_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(coverage_rule_from_row).collect()
+    }
+
+    async fn get_coverage_rule(&self, rule_id: Uuid) -> AppResult<Option<CoverageRule>> {
+        let row = sqlx::query(
+            "select id, schedule_id, dow, period, required, created_at from coverage_rule where id = $1",
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(coverage_rule_from_row).transpose()
+    }
+
+    async fn create_coverage_rule(&self, nc: NewCoverageRule) -> AppResult<CoverageRule> {
+        let row = sqlx::query(
+            r#"
+            insert into coverage_rule (id, schedule_id, dow, period, required)
+            values ($1, $2, $3, $4, $5)
+            returning id, schedule_id, dow, period, required, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(nc.schedule_id)
+        .bind(nc.dow)
+        .bind(nc.period.as_str())
+        .bind(nc.required)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(coverage_rule_write_error)?;
+
+        coverage_rule_from_row(&row)
+    }
+
+    async fn update_coverage_rule(
+        &self,
+        rule_id: Uuid,
+        uc: UpdateCoverageRule,
+    ) -> AppResult<CoverageRule> {
+        let row = sqlx::query(
+            r#"
+            update coverage_rule set dow = $2, period = $3, required = $4
+            where id = $1
+            returning id, schedule_id, dow, period, required, created_at
+            "#,
+        )
+        .bind(rule_id)
+        .bind(uc.dow)
+        .bind(uc.period.as_str())
+        .bind(uc.required)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(coverage_rule_write_error)?
+        .ok_or(AppError::NotFound)?;
+
+        coverage_rule_from_row(&row)
+    }
+
+    async fn delete_coverage_rule(&self, rule_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from coverage_rule where id = $1")
+            .bind(rule_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_attendance_for_shifts(
+        &self,
+        shift_ids: &[Uuid],
+    ) -> AppResult<Vec<ShiftAttendance>> {
+        let rows = sqlx::query(
+            r#"
+            select shift_id, user_id, checked_in_at, checked_out_at, updated_at
+            from shift_attendance
+            where shift_id = any($1)
+            order by shift_id, checked_in_at asc nulls last, user_id
+            "#,
+        )
+        .bind(shift_ids)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(attendance_from_row).collect())
+    }
+
+    async fn check_in(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance> {
+        // A row without a check-in can be left behind by a correction.
+        let row = sqlx::query(
+            r#"
+            insert into shift_attendance (shift_id, user_id, checked_in_at)
+            values ($1, $2, $3)
+            on conflict (shift_id, user_id) do update
+            set checked_in_at = excluded.checked_in_at, updated_at = now()
+            where shift_attendance.checked_in_at is null
+            returning shift_id, user_id, checked_in_at, checked_out_at, updated_at
+            "#,
+        )
+        .bind(shift_id)
+        .bind(user_id)
+        .bind(at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(|| AppError::Conflict("already checked in".to_string()))?;
+
+        Ok(attendance_from_row(&row))
+    }
+
+    async fn check_out(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance> {
+        let row = sqlx::query(
+            r#"
+            update shift_attendance set checked_out_at = $3, updated_at = now()
+            where shift_id = $1 and user_id = $2
+              and checked_in_at < $3 and checked_out_at is null
+            returning shift_id, user_id, checked_in_at, checked_out_at, updated_at
+            "#,
+        )
+        .bind(shift_id)
+        .bind(user_id)
+        .bind(at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or_else(|| AppError::Conflict("not checked in, or already checked out".to_string()))?;
+
+        Ok(attendance_from_row(&row))
+    }
+
+    async fn list_attendance_corrections(
+        &self,
+        shift_id: Uuid,
+    ) -> AppResult<Vec<AttendanceCorrection>> {
+        let rows = sqlx::query(
+            r#"
+            select id, shift_id, user_id, corrected_by, previous_checked_in_at, previous_checked_out_at, checked_in_at, checked_out_at, reason, created_at
+            from shift_attendance_correction
+            where shift_id = $1
+            order by created_at asc
+            "#,
+        )
+        .bind(shift_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(attendance_correction_from_row).collect())
+    }
+
+    async fn list_schedule_tasks(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleTask>> {
+        let rows = sqlx::query(
+            r#"
+            select id, schedule_id, title, due_time, period, created_at
+            from schedule_task
+            where schedule_id = $1
+            order by due_time asc nulls last, created_at asc
+            "#,
+        )
+        .bind(schedule_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        rows.iter().map(schedule_task_from_row).collect()
+    }
+
+    async fn get_schedule_task(&self, task_id: Uuid) -> AppResult<Option<ScheduleTask>> {
+        let row = sqlx::query(
+            "select id, schedule_id, title, due_time, period, created_at from schedule_task where id = $1",
+        )
+        .bind(task_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        row.as_ref().map(schedule_task_from_row).transpose()
+    }
+
+    async fn create_schedule_task(&self, nt: NewScheduleTask) -> AppResult<ScheduleTask> {
+        let row = sqlx::query(
+            r#"
+            insert into schedule_task (id, schedule_id, title, due_time, period)
+            values ($1, $2, $3, $4, $5)
+            returning id, schedule_id, title, due_time, period, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(nt.schedule_id)
+        .bind(nt.title)
+        .bind(nt.due_time)
+        .bind(nt.period.map(Period::as_str))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        schedule_task_from_row(&row)
+    }
+
+    async fn update_schedule_task(
+        &self,
+        task_id: Uuid,
+        ut: UpdateScheduleTask,
+    ) -> AppResult<ScheduleTask> {
+        let row = sqlx::query(
+            r#"
+            update schedule_task set title = $2, due_time = $3, period = $4
+            where id = $1
+            returning id, schedule_id, title, due_time, period, created_at
+            "#,
+        )
+        .bind(task_id)
+        .bind(ut.title)
+        .bind(ut.due_time)
+        .bind(ut.period.map(Period::as_str))
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::NotFound)?;
+
+        schedule_task_from_row(&row)
+    }
+
+    async fn delete_schedule_task(&self, task_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from schedule_task where id = $1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_shift_tasks(&self, shift_id: Uuid) -> AppResult<Vec<ShiftTask>> {
+        let rows = sqlx::query(
+            r#"
+            select id, shift_id, schedule_task_id, title, due_at, position, done_at, done_by, created_at
+            from shift_task
+            where shift_id = $1
+            order by position asc
+            "#,
+        )
+        .bind(shift_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(shift_task_from_row).collect())
+    }
+
+    async fn get_shift_task(&self, task_id: Uuid) -> AppResult<Option<ShiftTask>> {
+        let row = sqlx::query(
+            "select id, shift_id, schedule_task_id, title, due_at, position, done_at, done_by, created_at from shift_task where id = $1",
+        )
+        .bind(task_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(row.as_ref().map(shift_task_from_row))
+    }
+
+    async fn add_shift_task(&self, shift_id: Uuid, nt: NewShiftTask) -> AppResult<ShiftTask> {
+        let row = sqlx::query(
+            r#"
+            insert into shift_task (id, shift_id, schedule_task_id, title, due_at, position)
+            select $1, $2, $3, $4, $5, coalesce(max(position) + 1, 0)
+            from shift_task where shift_id = $2
+            returning id, shift_id, schedule_task_id, title, due_at, position, done_at, done_by, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(shift_id)
+        .bind(nt.schedule_task_id)
+        .bind(nt.title)
+        .bind(nt.due_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(shift_task_from_row(&row))
+    }
+
+    async fn set_shift_task_done(
+        &self,
+        task_id: Uuid,
+        done_by: Option<Uuid>,
+    ) -> AppResult<ShiftTask> {
+        let row = sqlx::query(
+            r#"
+            update shift_task
+            set done_at = case when $2::uuid is null then null else now() end, done_by = $2
+            where id = $1
+            returning id, shift_id, schedule_task_id, title, due_at, position, done_at, done_by, created_at
+            "#,
+        )
+        .bind(task_id)
+        .bind(done_by)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        .ok_or(AppError::NotFound)?;
+
+        Ok(shift_task_from_row(&row))
+    }
+
+    async fn delete_shift_task(&self, task_id: Uuid) -> AppResult<()> {
+        let res = sqlx::query("delete from shift_task where id = $1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        if res.rows_affected() == 0 {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_incomplete_tasks(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<ShiftTask>> {
+        let rows = sqlx::query(
+            r#"
+            select t.id, t.shift_id, t.schedule_task_id, t.title, t.due_at, t.position, t.done_at, t.done_by, t.created_at
+            from shift_task t
+            join shift s on s.id = t.shift_id
+            where s.schedule_id = $1 and s.starts_at >= $2 and s.starts_at < $3
+              and t.done_at is null
+            order by s.starts_at asc, t.shift_id, t.position asc
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        Ok(rows.iter().map(shift_task_from_row).collect())
+    }
+
+    async fn begin_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        scope: &str,
+    ) -> AppResult<IdempotentRequest> {
+        sqlx::query(
+            "delete from idempotency_key where user_id = $1 and key = $2 and created_at < now() - make_interval(hours => $3)",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(IDEMPOTENCY_TTL_HOURS as i32)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+
+        let inserted = sqlx::query(
+            "insert into idempotency_key (user_id, key, scope) values ($1, $2, $3) on conflict do nothing",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(scope)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        if inserted.rows_affected() == 1 {
+            return Ok(IdempotentRequest::New);
+        }
+
+        let row = sqlx::query(
+            "select scope, response_status, response_body from idempotency_key where user_id = $1 and key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?
+        // Aborted between our insert and select; let the client retry.
+        .ok_or(AppError::Conflict("request with this Idempotency-Key is in progress".to_string()))?;
+        if row.get::<String, _>("scope") != scope {
+            return Err(AppError::Conflict(
+                "Idempotency-Key was already used for a different request".to_string(),
+            ));
+        }
+        let status: Option<i32> = row.get("response_status");
+        Ok(match status {
+            None => IdempotentRequest::InProgress,
+            Some(status) => IdempotentRequest::Completed {
+                status: status as u16,
+                body: row.get("response_body"),
+            },
+        })
+    }
+
+    async fn finish_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        status: u16,
+        body: serde_json::Value,
+    ) -> AppResult<()> {
+        sqlx::query(
+            "update idempotency_key set response_status = $3, response_body = $4 where user_id = $1 and key = $2",
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(status as i32)
+        .bind(body)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
+
+    async fn abort_idempotent_request(&self, user_id: Uuid, key: &str) -> AppResult<()> {
+        sqlx::query("delete from idempotency_key where user_id = $1 and key = $2")
+            .bind(user_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| AppError::Internal)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+struct MemState {
+    users: HashMap<Uuid, (User, String)>,
+    schedules: HashMap<Uuid, Schedule>,
+    members: HashMap<(Uuid, Uuid), ScheduleRole>,
+    shifts: HashMap<Uuid, Shift>,
+    comments: HashMap<Uuid, Vec<ShiftComment>>,
+    templates: HashMap<Uuid, RotationTemplate>,
+    feed_tokens: HashMap<Uuid, String>,
+    sessions: HashMap<Uuid, AuthSession>,
+    refresh_tokens: HashMap<String, MemRefreshToken>,
+    account_tokens: HashMap<String, MemAccountToken>,
+    invites: HashMap<Uuid, ScheduleInvite>,
+    invite_tokens: HashMap<String, Uuid>,
+    idempotency: HashMap<(Uuid, String), MemIdempotency>,
+    swaps: HashMap<Uuid, ShiftSwap>,
+    claims: HashMap<Uuid, ShiftClaim>,
+    weekly_unavailability: HashMap<Uuid, WeeklyUnavailability>,
+    availability_exceptions: HashMap<Uuid, AvailabilityException>,
+    leave_requests: HashMap<Uuid, LeaveRequest>,
+    coverage_rules: HashMap<Uuid, CoverageRule>,
+    attendance: HashMap<(Uuid, Uuid), ShiftAttendance>,
+    attendance_corrections: HashMap<Uuid, Vec<AttendanceCorrection>>,
+    schedule_tasks: HashMap<Uuid, ScheduleTask>,
+    shift_tasks: HashMap<Uuid, ShiftTask>,
+}
+
+impl MemState {
+    /// Mirrors `ensure_another_admin`: fails if `user_id` is the schedule's
+    /// only admin.
+    fn ensure_another_admin(&self, schedule_id: Uuid, user_id: Uuid) -> AppResult<()> {
+        let admins: Vec<Uuid> = self
+            .members
+            .iter()
+            .filter(|((sid, _), role)| *sid == schedule_id && **role == ScheduleRole::Admin)
+            .map(|((_, uid), _)| *uid)
+            .collect();
+        if admins == [user_id] {
+            return Err(last_admin_error());
+        }
+        Ok(())
+    }
+
+    /// Removes a membership and, mirroring `on delete cascade`, the member's
+    /// availability and leave in that schedule.
+    fn remove_member(&mut self, schedule_id: Uuid, user_id: Uuid) -> bool {
+        if self.members.remove(&(schedule_id, user_id)).is_none() {
+            return false;
+        }
+        let theirs = |sid: Uuid, uid: Uuid| sid == schedule_id && uid == user_id;
+        self.weekly_unavailability
+            .retain(|_, x| !theirs(x.schedule_id, x.user_id));
+        self.availability_exceptions
+            .retain(|_, x| !theirs(x.schedule_id, x.user_id));
+        self.leave_requests
+            .retain(|_, x| !theirs(x.schedule_id, x.user_id));
+        true
+    }
+
+    /// Removes a schedule and, mirroring `on delete cascade`, everything
+    /// that hangs off it, counting as `delete_schedule` does.
+    fn remove_schedule(&mut self, schedule_id: Uuid) -> Option<ScheduleDeletion> {
+        self.schedules.remove(&schedule_id)?;
+        let mut deleted = ScheduleDeletion::default();
+        let count = |n: usize| n as i64;
+
+        let members: Vec<Uuid> = self
+            .members
+            .keys()
+            .filter(|(sid, _)| *sid == schedule_id)
+            .map(|(_, uid)| *uid)
+            .collect();
+        deleted.members = count(members.len());
+        deleted.availability = count(
+            self.weekly_unavailability
+                .values()
+                .filter(|x| x.schedule_id == schedule_id)
+                .count()
+                + self
+                    .availability_exceptions
+                    .values()
+                    .filter(|x| x.schedule_id == schedule_id)
+                    .count(),
+        );
+        deleted.leave_requests = count(
+            self.leave_requests
+                .values()
+                .filter(|x| x.schedule_id == schedule_id)
+                .count(),
+        );
+        for user_id in members {
+            self.remove_member(schedule_id, user_id);
+        }
+
+        // Swaps and claims cascade from the schedule as well as its shifts.
+        let swaps = self.swaps.len();
+        self.swaps.retain(|_, x| x.schedule_id != schedule_id);
+        deleted.swaps = count(swaps - self.swaps.len());
+        let claims = self.claims.len();
+        self.claims.retain(|_, x| x.schedule_id != schedule_id);
+        deleted.claims = count(claims - self.claims.len());
+
+        let shifts: Vec<Uuid> = self
+            .shifts
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .map(|x| x.id)
+            .collect();
+        deleted.shifts = count(shifts.len());
+        for shift_id in shifts {
+            deleted.comments += count(self.comments.get(&shift_id).map_or(0, Vec::len));
+            deleted.attendance += count(
+                self.attendance
+                    .keys()
+                    .filter(|(sid, _)| *sid == shift_id)
+                    .count(),
+            );
+            deleted.attendance_corrections += count(
+                self.attendance_corrections
+                    .get(&shift_id)
+                    .map_or(0, Vec::len),
+            );
+            deleted.shift_tasks += count(
+                self.shift_tasks
+                    .values()
+                    .filter(|x| x.shift_id == shift_id)
+                    .count(),
+            );
+            self.remove_shift(shift_id);
+        }
+
+        let templates = self.templates.len();
+        self.templates.retain(|_, x| x.schedule_id != schedule_id);
+        deleted.templates = count(templates - self.templates.len());
+        let rules = self.coverage_rules.len();
+        self.coverage_rules
+            .retain(|_, x| x.schedule_id != schedule_id);
+        deleted.coverage_rules = count(rules - self.coverage_rules.len());
+        let tasks = self.schedule_tasks.len();
+        self.schedule_tasks
+            .retain(|_, x| x.schedule_id != schedule_id);
+        deleted.schedule_tasks = count(tasks - self.schedule_tasks.len());
+        let invites = self.invites.len();
+        self.invites.retain(|_, x| x.schedule_id != schedule_id);
+        deleted.invites = count(invites - self.invites.len());
+        let invites = &self.invites;
+        self.invite_tokens.retain(|_, id| invites.contains_key(id));
+        Some(deleted)
+    }
+
+    /// Removes a shift and, mirroring `on delete cascade`, its comments,
+    /// swaps, claims, attendance and tasks.
+    fn remove_shift(&mut self, shift_id: Uuid) -> Option<Shift> {
+        let shift = self.shifts.remove(&shift_id)?;
+        self.comments.remove(&shift_id);
+        self.shift_tasks.retain(|_, x| x.shift_id != shift_id);
+        self.attendance.retain(|(sid, _), _| *sid != shift_id);
+        self.attendance_corrections.remove(&shift_id);
+        self.swaps
+            .retain(|_, x| x.shift_id != shift_id && x.counterpart_shift_id != Some(shift_id));
+        self.claims.retain(|_, x| x.shift_id != shift_id);
+        Some(shift)
+    }
+}
+
+#[derive(Clone)]
+struct MemAccountToken {
+    user_id: Uuid,
+    purpose: TokenPurpose,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+#[derive(Clone)]
+struct MemRefreshToken {
+    session_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used: bool,
+}
+
+#[derive(Clone)]
+struct MemIdempotency {
+    scope: String,
+    response: Option<(u16, serde_json::Value)>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Default)]
+pub struct MemRepo {
+    state: Arc<RwLock<MemState>>,
+}
+
+impl MemRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Mirrors the `shift_time_ok` check constraint.
+fn check_shift_time(starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> AppResult<()> {
+    if ends_at <= starts_at {
+        return Err(AppError::BadRequest(
+            "ends_at must be after starts_at".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors the `shift_capacity_ok` check constraint.
+fn check_capacity(capacity: i16) -> AppResult<()> {
+    if capacity < 1 {
+        return Err(AppError::BadRequest(
+            "capacity must be at least 1".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors the `member_availability_exception_range_ok` and
+/// `leave_request_range_ok` check constraints.
+fn check_exception_range(starts_on: NaiveDate, ends_on: NaiveDate) -> AppResult<()> {
+    if ends_on < starts_on {
+        return Err(AppError::BadRequest(
+            "ends_on must not be before starts_on".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Mirrors the `shift_attendance_order_ok` check constraint.
+fn check_attendance_order(
+    checked_in_at: Option<DateTime<Utc>>,
+    checked_out_at: Option<DateTime<Utc>>,
+) -> AppResult<()> {
+    if let Some(out) = checked_out_at {
+        if checked_in_at.is_none_or(|at| out <= at) {
+            return Err(AppError::BadRequest(
+                "checked_out_at must be after checked_in_at".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Mirrors the `unique (schedule_id, dow, period)` constraint on `coverage_rule`.
+fn check_coverage_rule_unique(
+    s: &MemState,
+    rule_id: Option<Uuid>,
+    schedule_id: Uuid,
+    dow: i16,
+    period: Period,
+) -> AppResult<()> {
+    if s.coverage_rules.values().any(|x| {
+        Some(x.id) != rule_id && x.schedule_id == schedule_id && x.dow == dow && x.period == period
+    }) {
+        return Err(AppError::Conflict(
+            "a coverage rule for that day and period already exists".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Repo for MemRepo {
+    async fn count_users(&self) -> AppResult<i64> {
+        Ok(self.state.read().unwrap().users.len() as i64)
+    }
+
+    async fn create_user(&self, nu: NewUser) -> AppResult<User> {
+        let mut s = self.state.write().unwrap();
+        if s.users.values().any(|(u, _)| u.email == nu.email) {
+            return Err(AppError::Conflict("email already exists".to_string()));
+        }
+        let id = Uuid::new_v4();
+        let user = User {
+            id,
+            email: nu.email,
+            is_superadmin: nu.is_superadmin,
+            email_verified_at: None,
+            created_at: Utc::now(),
+        };
+        s.users.insert(id, (user.clone(), nu.password_hash));
+        Ok(user)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> AppResult<Option<(User, String)>> {
+        let s = self.state.read().unwrap();
+        Ok(s.users
+            .values()
+            .find(|(u, _)| u.email == email)
+            .map(|(u, ph)| (u.clone(), ph.clone())))
+    }
+
+    async fn get_user(&self, user_id: Uuid) -> AppResult<Option<User>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .users
+            .get(&user_id)
+            .map(|(u, _)| u.clone()))
+    }
+
+    async fn set_calendar_feed_token(
+        &self,
+        user_id: Uuid,
+        token_hash: Option<String>,
+    ) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        match token_hash {
+            Some(hash) => s.feed_tokens.insert(user_id, hash),
+            None => s.feed_tokens.remove(&user_id),
+        };
+        Ok(())
+    }
+
+    async fn find_user_by_feed_token(&self, token_hash: &str) -> AppResult<Option<User>> {
+        let s = self.state.read().unwrap();
+        Ok(s.feed_tokens
+            .iter()
+            .find(|(_, h)| h.as_str() == token_hash)
+            .and_then(|(uid, _)| s.users.get(uid))
+            .map(|(u, _)| u.clone()))
+    }
+
+    async fn mark_email_verified(&self, user_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if let Some((user, _)) = s.users.get_mut(&user_id) {
+            user.email_verified_at.get_or_insert_with(Utc::now);
+        }
+        Ok(())
+    }
+
+    async fn set_password(&self, user_id: Uuid, password_hash: String) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if let Some((_, hash)) = s.users.get_mut(&user_id) {
+            *hash = password_hash;
+        }
+        Ok(())
+    }
+
+    async fn create_account_token(
+        &self,
+        user_id: Uuid,
+        purpose: TokenPurpose,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        s.account_tokens
+            .retain(|_, t| t.used || t.user_id != user_id || t.purpose != purpose);
+        s.account_tokens.insert(
+            token_hash,
+            MemAccountToken {
+                user_id,
+                purpose,
+                expires_at,
+                used: false,
+            },
+        );
+        Ok(())
+    }
+
+    async fn consume_account_token(
+        &self,
+        token_hash: &str,
+        purpose: TokenPurpose,
+    ) -> AppResult<Option<Uuid>> {
+        let mut s = self.state.write().unwrap();
+        let now = Utc::now();
+        Ok(s.account_tokens
+            .get_mut(token_hash)
+            .filter(|t| t.purpose == purpose && !t.used && t.expires_at > now)
+            .map(|t| {
+                t.used = true;
+                t.user_id
+            }))
+    }
+
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        refresh_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession> {
+        let mut s = self.state.write().unwrap();
+        let now = Utc::now();
+        let session = AuthSession {
+            id: Uuid::new_v4(),
+            user_id,
+            created_at: now,
+            last_refreshed_at: now,
+            revoked_at: None,
+        };
+        s.sessions.insert(session.id, session.clone());
+        s.refresh_tokens.insert(
+            refresh_hash,
+            MemRefreshToken {
+                session_id: session.id,
+                expires_at,
+                used: false,
+            },
+        );
+        Ok(session)
+    }
+
+    async fn get_session(&self, session_id: Uuid) -> AppResult<Option<AuthSession>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .sessions
+            .get(&session_id)
+            .cloned())
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        next_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> AppResult<AuthSession> {
+        let mut s = self.state.write().unwrap();
+        let now = Utc::now();
+        let token = s
+            .refresh_tokens
+            .get(token_hash)
+            .cloned()
+            .ok_or(AppError::Unauthorized)?;
+        let session = s
+            .sessions
+            .get_mut(&token.session_id)
+            .ok_or(AppError::Unauthorized)?;
+        if session.revoked_at.is_some() || token.expires_at <= now {
+            return Err(AppError::Unauthorized);
+        }
+        if token.used {
+            session.revoked_at = Some(now);
+            return Err(AppError::Unauthorized);
+        }
+        session.last_refreshed_at = now;
+        let session = session.clone();
+        if let Some(t) = s.refresh_tokens.get_mut(token_hash) {
+            t.used = true;
+        }
+        s.refresh_tokens.insert(
+            next_hash,
+            MemRefreshToken {
+                session_id: session.id,
+                expires_at,
+                used: false,
+            },
+        );
+        Ok(session)
+    }
+
+    async fn revoke_session(&self, session_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if let Some(session) = s.sessions.get_mut(&session_id) {
+            session.revoked_at.get_or_insert_with(Utc::now);
+        }
+        Ok(())
+    }
+
+    async fn revoke_user_sessions(&self, user_id: Uuid) -> AppResult<u64> {
+        let mut s = self.state.write().unwrap();
+        let now = Utc::now();
+        let mut revoked = 0;
+        for session in s.sessions.values_mut() {
+            if session.user_id == user_id && session.revoked_at.is_none() {
+                session.revoked_at = Some(now);
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    async fn commit(&self, uow: UnitOfWork) -> AppResult<Committed> {
+        let mut s = self.state.write().unwrap();
+        // Work on a copy so a failing write leaves the state untouched.
+        let mut next = s.clone();
+        let mut out = Committed::default();
+        let now = Utc::now();
+        for write in uow.writes {
+            match write {
+                Write::CreateSchedule { id, ns } => {
+                    let schedule = Schedule {
+                        id,
+                        name: ns.name,
+                        subject_type: ns.subject_type,
+                        subject_name: ns.subject_name,
+                        timezone: ns.timezone,
+                        swap_requires_approval: false,
+                        claim_policy: ClaimPolicy::Open,
+                        check_in_grace_minutes: DEFAULT_CHECK_IN_GRACE_MINUTES,
+                        archived_at: None,
+                        created_by: ns.created_by,
+                        created_at: now,
+                    };
+                    next.schedules.insert(id, schedule.clone());
+                    out.schedules.push(schedule);
+                }
+                Write::RemoveMember {
+                    schedule_id,
+                    user_id,
+                } => {
+                    next.ensure_another_admin(schedule_id, user_id)?;
+                    if !next.remove_member(schedule_id, user_id) {
+                        return Err(AppError::NotFound);
+                    }
+                }
+                Write::AddMember {
+                    schedule_id,
+                    user_id,
+                    role,
+                } => {
+                    let key = (schedule_id, user_id);
+                    if next.members.contains_key(&key) {
+                        return Err(AppError::Conflict("user already in schedule".to_string()));
+                    }
+                    next.members.insert(key, role);
+                }
+                Write::CreateShift { id, ns } => {
+                    check_shift_time(ns.starts_at, ns.ends_at)?;
+                    check_capacity(ns.capacity)?;
+                    let shift = Shift {
+                        id,
+                        schedule_id: ns.schedule_id,
+                        starts_at: ns.starts_at,
+                        ends_at: ns.ends_at,
+                        period: ns.period,
+                        capacity: ns.capacity,
+                        assignees: ns
+                            .assigned_user_id
+                            .map(|user_id| ShiftAssignee {
+                                user_id,
+                                lead: false,
+                                assigned_at: now,
+                            })
+                            .into_iter()
+                            .collect(),
+                        template_id: ns.template_id,
+                        template_week: ns.template_week,
+                        created_by: ns.created_by,
+                        created_at: now,
+                        updated_at: now,
+                    };
+                    for (position, task) in ns.tasks.into_iter().enumerate() {
+                        let task = ShiftTask {
+                            id: Uuid::new_v4(),
+                            shift_id: id,
+                            schedule_task_id: task.schedule_task_id,
+                            title: task.title,
+                            due_at: task.due_at,
+                            position: position as i32,
+                            done_at: None,
+                            done_by: None,
+                            created_at: now,
+                        };
+                        next.shift_tasks.insert(task.id, task);
+                    }
+                    next.shifts.insert(id, shift.clone());
+                    out.shifts.push(shift);
+                }
+                Write::LockTemplate {
+                    template_id,
+                    rotation_state,
+                } => {
+                    let Some(t) = next.templates.get(&template_id) else {
+                        return Err(AppError::NotFound);
+                    };
+                    if t.rotation_state != rotation_state {
+                        return Err(template_changed_error());
+                    }
+                }
+                Write::EnsureTemplateWeeksUnapplied { template_id, weeks } => {
+                    if next.shifts.values().any(|x| {
+                        x.template_id == Some(template_id)
+                            && x.template_week.is_some_and(|w| weeks.contains(&w))
+                    }) {
+                        return Err(template_changed_error());
+                    }
+                }
+                Write::DeleteTemplateWeeks { template_id, weeks } => {
+                    let removed: Vec<Uuid> = next
+                        .shifts
+                        .values()
+                        .filter(|x| {
+                            x.template_id == Some(template_id)
+                                && x.template_week.is_some_and(|w| weeks.contains(&w))
+                        })
+                        .map(|x| x.id)
+                        .collect();
+                    for id in removed {
+                        next.remove_shift(id);
+                    }
+                }
+                Write::SetTemplateRotationState {
+                    template_id,
+                    rotation_state,
+                } => {
+                    let Some(t) = next.templates.get_mut(&template_id) else {
+                        return Err(AppError::NotFound);
+                    };
+                    t.rotation_state = rotation_state;
+                }
+                Write::ReassignShift { shift_id, from, to } => {
+                    let changed = || AppError::Conflict("shift assignment has changed".to_string());
+                    let Some(shift) = next.shifts.get_mut(&shift_id) else {
+                        return Err(changed());
+                    };
+                    if from.is_none() && to.is_some() && shift.free_places() == 0 {
+                        return Err(AppError::Conflict("shift is full".to_string()));
+                    }
+                    if to.is_some_and(|to| shift.is_assigned(to)) {
+                        return Err(AppError::Conflict(
+                            "user is already assigned to this shift".to_string(),
+                        ));
+                    }
+                    match (from, to) {
+                        (Some(from), to) => {
+                            let Some(pos) = shift.assignees.iter().position(|a| a.user_id == from)
+                            else {
+                                return Err(changed());
+                            };
+                            match to {
+                                Some(to) => shift.assignees[pos].user_id = to,
+                                None => {
+                                    shift.assignees.remove(pos);
+                                }
+                            }
+                        }
+                        (None, Some(to)) => shift.assignees.push(ShiftAssignee {
+                            user_id: to,
+                            lead: false,
+                            assigned_at: now,
+                        }),
+                        (None, None) => {}
+                    }
+                }
+                Write::ReplaceShiftAssignees { shift_id, user_id } => {
+                    let Some(shift) = next.shifts.get_mut(&shift_id) else {
+                        return Err(AppError::NotFound);
+                    };
+                    shift.assignees = user_id
+                        .map(|user_id| ShiftAssignee {
+                            user_id,
+                            lead: false,
+                            assigned_at: now,
+                        })
+                        .into_iter()
+                        .collect();
+                }
+                Write::SetShiftLead { shift_id, user_id } => {
+                    let Some(shift) = next
+                        .shifts
+                        .get_mut(&shift_id)
+                        .filter(|x| x.is_assigned(user_id))
+                    else {
+                        return Err(AppError::Conflict(
+                            "user is not assigned to this shift".to_string(),
+                        ));
+                    };
+                    for a in &mut shift.assignees {
+                        a.lead = a.user_id == user_id;
+                    }
+                    shift
+                        .assignees
+                        .sort_by_key(|a| (!a.lead, a.assigned_at, a.user_id));
+                }
+                Write::SetSwapStatus {
+                    swap_id,
+                    status,
+                    decided_by,
+                } => {
+                    let Some(swap) = next
+                        .swaps
+                        .get_mut(&swap_id)
+                        .filter(|x| x.status == SwapStatus::Pending)
+                    else {
+                        return Err(AppError::Conflict(
+                            "swap request is no longer pending".to_string(),
+                        ));
+                    };
+                    swap.status = status;
+                    swap.decided_by = decided_by;
+                    swap.updated_at = now;
+                }
+                Write::MarkSwapCounterpartAccepted { swap_id } => {
+                    let Some(swap) = next.swaps.get_mut(&swap_id).filter(|x| {
+                        x.status == SwapStatus::Pending && x.counterpart_accepted_at.is_none()
+                    }) else {
+                        return Err(AppError::Conflict(
+                            "swap request is no longer awaiting the counterpart".to_string(),
+                        ));
+                    };
+                    swap.counterpart_accepted_at = Some(now);
+                    swap.updated_at = now;
+                }
+                Write::SetClaimStatus {
+                    claim_id,
+                    status,
+                    decided_by,
+                } => {
+                    let Some(claim) = next
+                        .claims
+                        .get_mut(&claim_id)
+                        .filter(|x| x.status == ClaimStatus::Pending)
+                    else {
+                        return Err(AppError::Conflict(
+                            "claim has already been decided".to_string(),
+                        ));
+                    };
+                    claim.status = status;
+                    claim.decided_by = Some(decided_by);
+                    claim.decided_at = Some(now);
+                }
+                Write::RejectPendingClaims {
+                    shift_id,
+                    decided_by,
+                } => {
+                    for claim in next.claims.values_mut() {
+                        if claim.shift_id == shift_id && claim.status == ClaimStatus::Pending {
+                            claim.status = ClaimStatus::Rejected;
+                            claim.decided_by = Some(decided_by);
+                            claim.decided_at = Some(now);
+                        }
+                    }
+                }
+                Write::CloseMemberRequests {
+                    schedule_id,
+                    user_id,
+                    decided_by,
+                } => {
+                    for claim in next.claims.values_mut() {
+                        if claim.schedule_id == schedule_id
+                            && claim.user_id == user_id
+                            && claim.status == ClaimStatus::Pending
+                        {
+                            claim.status = ClaimStatus::Rejected;
+                            claim.decided_by = Some(decided_by);
+                            claim.decided_at = Some(now);
+                        }
+                    }
+                    for swap in next.swaps.values_mut() {
+                        if swap.schedule_id == schedule_id
+                            && (swap.requester_id == user_id || swap.counterpart_id == user_id)
+                            && swap.status == SwapStatus::Pending
+                        {
+                            swap.status = SwapStatus::Cancelled;
+                            swap.decided_by = Some(decided_by);
+                            swap.updated_at = now;
+                        }
+                    }
+                }
+                Write::AddShiftComment { id, nc } => {
+                    let c = ShiftComment {
+                        id,
+                        shift_id: nc.shift_id,
+                        user_id: nc.user_id,
+                        body: nc.body,
+                        created_at: now,
+                        edited_at: None,
+                    };
+                    next.comments
+                        .entry(nc.shift_id)
+                        .or_default()
+                        .push(c.clone());
+                    out.comments.push(c);
+                }
+                Write::SetLeaveStatus {
+                    leave_id,
+                    status,
+                    decided_by,
+                    note,
+                } => {
+                    let Some(leave) = next
+                        .leave_requests
+                        .get_mut(&leave_id)
+                        .filter(|x| x.status == LeaveStatus::Pending)
+                    else {
+                        return Err(AppError::Conflict(
+                            "leave request is no longer pending".to_string(),
+                        ));
+                    };
+                    leave.status = status;
+                    leave.decided_by = Some(decided_by);
+                    leave.decision_note = note;
+                    leave.decided_at = Some(now);
+                }
+                Write::CorrectAttendance { id, nc } => {
+                    check_attendance_order(nc.checked_in_at, nc.checked_out_at)?;
+                    let entry = next.attendance.entry((nc.shift_id, nc.user_id)).or_insert(
+                        ShiftAttendance {
+                            shift_id: nc.shift_id,
+                            user_id: nc.user_id,
+                            checked_in_at: None,
+                            checked_out_at: None,
+                            updated_at: now,
+                        },
+                    );
+                    let correction = AttendanceCorrection {
+                        id,
+                        shift_id: nc.shift_id,
+                        user_id: nc.user_id,
+                        corrected_by: Some(nc.corrected_by),
+                        previous_checked_in_at: entry.checked_in_at,
+                        previous_checked_out_at: entry.checked_out_at,
+                        checked_in_at: nc.checked_in_at,
+                        checked_out_at: nc.checked_out_at,
+                        reason: nc.reason,
+                        created_at: now,
+                    };
+                    entry.checked_in_at = nc.checked_in_at;
+                    entry.checked_out_at = nc.checked_out_at;
+                    entry.updated_at = now;
+                    next.attendance_corrections
+                        .entry(nc.shift_id)
+                        .or_default()
+                        .push(correction.clone());
+                    out.attendance_corrections.push(correction);
+                }
+            }
+        }
+        *s = next;
+        Ok(out)
+    }
+
+    async fn list_schedules_for_user(
+        &self,
+        user_id: Uuid,
+        include_archived: bool,
+    ) -> AppResult<Vec<ScheduleWithRole>> {
+        let s = self.state.read().unwrap();
+        let mut out = Vec::new();
+        for ((schedule_id, uid), role) in s.members.iter() {
+            if *uid != user_id {
+                continue;
+            }
+            if let Some(schedule) = s
+                .schedules
+                .get(schedule_id)
+                .filter(|x| include_archived || x.archived_at.is_none())
+            {
+                out.push(ScheduleWithRole {
+                    schedule: schedule.clone(),
+                    role: *role,
+                });
+            }
+        }
+        out.sort_by_key(|x| x.schedule.created_at);
+        out.reverse();
+        Ok(out)
+    }
+
+    async fn get_schedule(&self, schedule_id: Uuid) -> AppResult<Option<Schedule>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .schedules
+            .get(&schedule_id)
+            .cloned())
+    }
+
+    async fn update_schedule(&self, schedule_id: Uuid, us: UpdateSchedule) -> AppResult<Schedule> {
+        let mut s = self.state.write().unwrap();
+        let Some(schedule) = s.schedules.get_mut(&schedule_id) else {
+            return Err(AppError::NotFound);
+        };
+        schedule.name = us.name;
+        schedule.subject_type = us.subject_type;
+        schedule.subject_name = us.subject_name;
+        schedule.timezone = us.timezone;
+        schedule.swap_requires_approval = us.swap_requires_approval;
+        schedule.claim_policy = us.claim_policy;
+        schedule.check_in_grace_minutes = us.check_in_grace_minutes;
+        Ok(schedule.clone())
+    }
+
+    async fn set_schedule_archived(
+        &self,
+        schedule_id: Uuid,
+        archived: bool,
+    ) -> AppResult<Schedule> {
+        let mut s = self.state.write().unwrap();
+        let Some(schedule) = s.schedules.get_mut(&schedule_id) else {
+            return Err(AppError::NotFound);
+        };
+        schedule.archived_at = match archived {
+            true => schedule.archived_at.or(Some(Utc::now())),
+            false => None,
+        };
+        Ok(schedule.clone())
+    }
+
+    async fn delete_schedule(&self, schedule_id: Uuid) -> AppResult<ScheduleDeletion> {
+        let mut s = self.state.write().unwrap();
+        s.remove_schedule(schedule_id).ok_or(AppError::NotFound)
+    }
+
+    async fn get_schedule_role(
+        &self,
+        schedule_id: Uuid,
+        user_id: Uuid,
+    ) -> AppResult<Option<ScheduleRole>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .members
+            .get(&(schedule_id, user_id))
+            .copied())
+    }
+
+    async fn list_schedule_members(
+        &self,
+        schedule_id: Uuid,
+    ) -> AppResult<Vec<(User, ScheduleRole)>> {
+        let s = self.state.read().unwrap();
+        let mut out = Vec::new();
+        for ((sid, uid), role) in s.members.iter() {
+            if *sid != schedule_id {
+                continue;
+            }
+            if let Some((user, _)) = s.users.get(uid) {
+                out.push((user.clone(), *role));
+            }
+        }
+        Ok(out)
+    }
+
+    async fn add_member(
+        &self,
+        schedule_id: Uuid,
+        user_id: Uuid,
+        role: ScheduleRole,
+    ) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        let key = (schedule_id, user_id);
+        if s.members.contains_key(&key) {
+            return Err(AppError::Conflict("user already in schedule".to_string()));
+        }
+        s.members.insert(key, role);
+        Ok(())
+    }
+
+    async fn set_member_role(
+        &self,
+        schedule_id: Uuid,
+        user_id: Uuid,
+        role: ScheduleRole,
+    ) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        let key = (schedule_id, user_id);
+        if !s.members.contains_key(&key) {
+            return Err(AppError::NotFound);
+        }
+        if role != ScheduleRole::Admin {
+            s.ensure_another_admin(schedule_id, user_id)?;
+        }
+        s.members.insert(key, role);
+        Ok(())
+    }
+
+    async fn create_invite(&self, ni: NewInvite) -> AppResult<ScheduleInvite> {
+        let mut s = self.state.write().unwrap();
+        let invite = ScheduleInvite {
+            id: Uuid::new_v4(),
+            schedule_id: ni.schedule_id,
+            email: ni.email,
+            role: ni.role,
+            max_uses: ni.max_uses,
+            uses: 0,
+            expires_at: ni.expires_at,
+            created_by: ni.created_by,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        s.invite_tokens.insert(ni.token_hash, invite.id);
+        s.invites.insert(invite.id, invite.clone());
+        Ok(invite)
+    }
+
+    async fn list_pending_invites(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleInvite>> {
+        let s = self.state.read().unwrap();
+        let now = Utc::now();
+        let mut out: Vec<_> = s
+            .invites
+            .values()
+            .filter(|x| x.schedule_id == schedule_id && x.is_pending(now))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| std::cmp::Reverse(x.created_at));
+        Ok(out)
+    }
+
+    async fn get_invite(&self, invite_id: Uuid) -> AppResult<Option<ScheduleInvite>> {
+        Ok(self.state.read().unwrap().invites.get(&invite_id).cloned())
+    }
+
+    async fn find_invite_by_token(&self, token_hash: &str) -> AppResult<Option<ScheduleInvite>> {
+        let s = self.state.read().unwrap();
+        Ok(s.invite_tokens
+            .get(token_hash)
+            .and_then(|id| s.invites.get(id))
+            .cloned())
+    }
+
+    async fn revoke_invite(&self, invite_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        match s.invites.get_mut(&invite_id) {
+            Some(invite) if invite.revoked_at.is_none() => {
+                invite.revoked_at = Some(Utc::now());
+                Ok(())
+            }
+            _ => Err(AppError::NotFound),
+        }
+    }
+
+    async fn accept_invite(&self, invite_id: Uuid, user_id: Uuid) -> AppResult<ScheduleInvite> {
+        let mut s = self.state.write().unwrap();
+        let Some(invite) = s
+            .invites
+            .get(&invite_id)
+            .filter(|x| x.is_pending(Utc::now()))
+            .cloned()
+        else {
+            return Err(AppError::Conflict("invite is no longer valid".to_string()));
+        };
+        let key = (invite.schedule_id, user_id);
+        if s.members.contains_key(&key) {
+            return Err(AppError::Conflict("user already in schedule".to_string()));
+        }
+        s.members.insert(key, invite.role);
+        let invite = s.invites.get_mut(&invite_id).ok_or(AppError::Internal)?;
+        invite.uses += 1;
+        Ok(invite.clone())
+    }
+
+    async fn list_shifts(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .shifts
+            .values()
+            .filter(|x| x.schedule_id == schedule_id && x.starts_at >= from && x.starts_at < to)
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.starts_at);
+        Ok(out)
+    }
+
+    async fn get_shift(&self, shift_id: Uuid) -> AppResult<Option<Shift>> {
+        Ok(self.state.read().unwrap().shifts.get(&shift_id).cloned())
+    }
+
+    async fn list_assigned_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .shifts
+            .values()
+            .filter(|x| x.is_assigned(user_id) && x.starts_at >= from && x.starts_at < to)
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.starts_at);
+        Ok(out)
+    }
+
+    async fn update_shift(&self, shift_id: Uuid, us: UpdateShift) -> AppResult<Shift> {
+        check_shift_time(us.starts_at, us.ends_at)?;
+        check_capacity(us.capacity)?;
+        let mut s = self.state.write().unwrap();
+        let Some(shift) = s.shifts.get_mut(&shift_id) else {
+            return Err(AppError::NotFound);
+        };
+        shift.starts_at = us.starts_at;
+        shift.ends_at = us.ends_at;
+        shift.period = us.period;
+        shift.capacity = us.capacity;
+        shift.updated_at = Utc::now();
+        Ok(shift.clone())
+    }
+
+    async fn delete_shift(&self, shift_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if s.remove_shift(shift_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_overlapping_shifts(
+        &self,
+        user_id: Uuid,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        exclude_shift_id: Uuid,
+    ) -> AppResult<Vec<Shift>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .shifts
             .values()
-            .find(|(u, _)| u.email == email)
-            .map(|(u, ph)| (u.clone(), ph.clone())))
+            .filter(|x| {
+                x.is_assigned(user_id)
+                    && x.id != exclude_shift_id
+                    && x.starts_at < ends_at
+                    && x.ends_at > starts_at
+            })
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.starts_at);
+        Ok(out)
     }
 
-    async fn get_user(&self, user_id: Uuid) -> AppResult<Option<User>> {
+    async fn add_shift_comment(&self, nc: NewShiftComment) -> AppResult<ShiftComment> {
+        let mut s = self.state.write().unwrap();
+        let c = ShiftComment {
+            id: Uuid::new_v4(),
+            shift_id: nc.shift_id,
+            user_id: nc.user_id,
+            body: nc.body,
+            created_at: Utc::now(),
+            edited_at: None,
+        };
+        s.comments.entry(nc.shift_id).or_default().push(c.clone());
+        Ok(c)
+    }
+
+    async fn list_shift_comments(
+        &self,
+        shift_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> AppResult<Vec<ShiftComment>> {
+        let s = self.state.read().unwrap();
+        Ok(s.comments
+            .get(&shift_id)
+            .map(|cs| {
+                cs.iter()
+                    .skip(offset.max(0) as usize)
+                    .take(limit.max(0) as usize)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn list_comments_for_shifts(&self, shift_ids: &[Uuid]) -> AppResult<Vec<ShiftComment>> {
+        let s = self.state.read().unwrap();
+        Ok(shift_ids
+            .iter()
+            .filter_map(|id| s.comments.get(id))
+            .flatten()
+            .cloned()
+            .collect())
+    }
+
+    async fn get_shift_comment(&self, comment_id: Uuid) -> AppResult<Option<ShiftComment>> {
+        let s = self.state.read().unwrap();
+        Ok(s.comments
+            .values()
+            .flatten()
+            .find(|c| c.id == comment_id)
+            .cloned())
+    }
+
+    async fn update_shift_comment(
+        &self,
+        comment_id: Uuid,
+        body: String,
+    ) -> AppResult<ShiftComment> {
+        let mut s = self.state.write().unwrap();
+        let Some(c) = s
+            .comments
+            .values_mut()
+            .flatten()
+            .find(|c| c.id == comment_id)
+        else {
+            return Err(AppError::NotFound);
+        };
+        c.body = body;
+        c.edited_at = Some(Utc::now());
+        Ok(c.clone())
+    }
+
+    async fn delete_shift_comment(&self, comment_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        for cs in s.comments.values_mut() {
+            if let Some(pos) = cs.iter().position(|c| c.id == comment_id) {
+                cs.remove(pos);
+                return Ok(());
+            }
+        }
+        Err(AppError::NotFound)
+    }
+
+    async fn create_template(&self, nt: NewTemplate) -> AppResult<RotationTemplate> {
+        let mut s = self.state.write().unwrap();
+        let t = RotationTemplate {
+            id: Uuid::new_v4(),
+            schedule_id: nt.schedule_id,
+            name: nt.name,
+            definition: nt.definition,
+            rotation_state: serde_json::json!({}),
+            created_by: nt.created_by,
+            created_at: Utc::now(),
+        };
+        s.templates.insert(t.id, t.clone());
+        Ok(t)
+    }
+
+    async fn list_templates(&self, schedule_id: Uuid) -> AppResult<Vec<RotationTemplate>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .templates
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.created_at);
+        out.reverse();
+        Ok(out)
+    }
+
+    async fn get_template(&self, template_id: Uuid) -> AppResult<Option<RotationTemplate>> {
         Ok(self
             .state
             .read()
             .unwrap()
-            .users
-            .get(&user_id)
-            .map(|(u, _)| u.clone()))
+            .templates
+            .get(&template_id)
+            .cloned())
     }
 
-    async fn create_schedule(&self, ns: NewSchedule) -> AppResult<Schedule> {
+    async fn list_template_weeks(
+        &self,
+        template_id: Uuid,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> AppResult<Vec<NaiveDate>> {
+        let s = self.state.read().unwrap();
+        let mut weeks: Vec<_> = s
+            .shifts
+            .values()
+            .filter(|x| x.template_id == Some(template_id))
+            .filter_map(|x| x.template_week)
+            .filter(|w| *w >= from && *w <= to)
+            .collect();
+        weeks.sort();
+        weeks.dedup();
+        Ok(weeks)
+    }
+
+    async fn create_swap(&self, ns: NewSwap) -> AppResult<ShiftSwap> {
         let mut s = self.state.write().unwrap();
-        let id = Uuid::new_v4();
-        let schedule = Schedule {
-            id,
-            name: ns.name,
-            subject_type: ns.subject_type,
-            subject_name: ns.subject_name,
-            created_by: ns.created_by,
-            created_at: Utc::now(),
+        let now = Utc::now();
+        let swap = ShiftSwap {
+            id: Uuid::new_v4(),
+            schedule_id: ns.schedule_id,
+            shift_id: ns.shift_id,
+            requester_id: ns.requester_id,
+            counterpart_id: ns.counterpart_id,
+            counterpart_shift_id: ns.counterpart_shift_id,
+            message: ns.message,
+            status: SwapStatus::Pending,
+            counterpart_accepted_at: None,
+            decided_by: None,
+            expires_at: ns.expires_at,
+            created_at: now,
+            updated_at: now,
         };
-        s.schedules.insert(id, schedule.clone());
-        s.members.insert((id, ns.created_by), ScheduleRole::Admin);
-        Ok(schedule)
+        s.swaps.insert(swap.id, swap.clone());
+        Ok(swap)
+    }
+
+    async fn get_swap(&self, swap_id: Uuid) -> AppResult<Option<ShiftSwap>> {
+        Ok(self.state.read().unwrap().swaps.get(&swap_id).cloned())
     }
 
-    async fn list_schedules_for_user(&self, user_id: Uuid) -> AppResult<Vec<ScheduleWithRole>> {
+    async fn list_swaps(
+        &self,
+        schedule_id: Uuid,
+        involving: Option<Uuid>,
+        status: Option<SwapStatus>,
+    ) -> AppResult<Vec<ShiftSwap>> {
         let s = self.state.read().unwrap();
-        let mut out = Vec::new();
-        for ((schedule_id, uid), role) in s.members.iter() {
-            if *uid != user_id {
-                continue;
-            }
-            if let Some(schedule) = s.schedules.get(schedule_id) {
-                out.push(ScheduleWithRole {
-                    schedule: schedule.clone(),
-                    role: *role,
-                });
+        let mut out: Vec<_> = s
+            .swaps
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .filter(|x| involving.is_none_or(|u| x.requester_id == u || x.counterpart_id == u))
+            .filter(|x| status.is_none_or(|st| x.status == st))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.created_at);
+        out.reverse();
+        Ok(out)
+    }
+
+    async fn expire_swaps(&self, now: DateTime<Utc>) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        for swap in s.swaps.values_mut() {
+            if swap.status == SwapStatus::Pending && swap.expires_at <= now {
+                swap.status = SwapStatus::Expired;
+                swap.updated_at = now;
             }
         }
-        out.sort_by_key(|x| x.schedule.created_at);
-        out.reverse();
-        Ok(out)
+        Ok(())
+    }
+
+    async fn list_open_shifts(
+        &self,
+        user_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<Shift>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .shifts
+            .values()
+            .filter(|x| {
+                x.free_places() > 0
+                    && !x.is_assigned(user_id)
+                    && x.starts_at >= from
+                    && x.starts_at < to
+                    && s.members.contains_key(&(x.schedule_id, user_id))
+            })
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.starts_at);
+        Ok(out)
+    }
+
+    async fn create_claim(&self, nc: NewClaim) -> AppResult<ShiftClaim> {
+        let mut s = self.state.write().unwrap();
+        if s.claims.values().any(|x| {
+            x.shift_id == nc.shift_id && x.user_id == nc.user_id && x.status == ClaimStatus::Pending
+        }) {
+            return Err(AppError::Conflict(
+                "you already claimed this shift".to_string(),
+            ));
+        }
+        let claim = ShiftClaim {
+            id: Uuid::new_v4(),
+            schedule_id: nc.schedule_id,
+            shift_id: nc.shift_id,
+            user_id: nc.user_id,
+            status: ClaimStatus::Pending,
+            decided_by: None,
+            created_at: Utc::now(),
+            decided_at: None,
+        };
+        s.claims.insert(claim.id, claim.clone());
+        Ok(claim)
+    }
+
+    async fn get_claim(&self, claim_id: Uuid) -> AppResult<Option<ShiftClaim>> {
+        Ok(self.state.read().unwrap().claims.get(&claim_id).cloned())
+    }
+
+    async fn list_claims(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+        status: Option<ClaimStatus>,
+    ) -> AppResult<Vec<ShiftClaim>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .claims
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .filter(|x| user_id.is_none_or(|u| x.user_id == u))
+            .filter(|x| status.is_none_or(|st| x.status == st))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| x.created_at);
+        Ok(out)
+    }
+
+    async fn list_weekly_unavailability(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<WeeklyUnavailability>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .weekly_unavailability
+            .values()
+            .filter(|x| x.schedule_id == schedule_id && user_id.is_none_or(|u| x.user_id == u))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| (x.user_id, x.dow, x.period.as_str()));
+        Ok(out)
+    }
+
+    async fn get_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+    ) -> AppResult<Option<WeeklyUnavailability>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .weekly_unavailability
+            .get(&rule_id)
+            .cloned())
+    }
+
+    async fn create_weekly_unavailability(
+        &self,
+        nw: NewWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability> {
+        let mut s = self.state.write().unwrap();
+        if s.weekly_unavailability.values().any(|x| {
+            x.schedule_id == nw.schedule_id
+                && x.user_id == nw.user_id
+                && x.dow == nw.dow
+                && x.period == nw.period
+        }) {
+            return Err(AppError::Conflict(
+                "already unavailable for that day and period".to_string(),
+            ));
+        }
+        let rule = WeeklyUnavailability {
+            id: Uuid::new_v4(),
+            schedule_id: nw.schedule_id,
+            user_id: nw.user_id,
+            dow: nw.dow,
+            period: nw.period,
+            note: nw.note,
+            created_at: Utc::now(),
+        };
+        s.weekly_unavailability.insert(rule.id, rule.clone());
+        Ok(rule)
+    }
+
+    async fn update_weekly_unavailability(
+        &self,
+        rule_id: Uuid,
+        uw: UpdateWeeklyUnavailability,
+    ) -> AppResult<WeeklyUnavailability> {
+        let mut s = self.state.write().unwrap();
+        let Some(current) = s.weekly_unavailability.get(&rule_id) else {
+            return Err(AppError::NotFound);
+        };
+        let (schedule_id, user_id) = (current.schedule_id, current.user_id);
+        if s.weekly_unavailability.values().any(|x| {
+            x.id != rule_id
+                && x.schedule_id == schedule_id
+                && x.user_id == user_id
+                && x.dow == uw.dow
+                && x.period == uw.period
+        }) {
+            return Err(AppError::Conflict(
+                "already unavailable for that day and period".to_string(),
+            ));
+        }
+        let rule = s
+            .weekly_unavailability
+            .get_mut(&rule_id)
+            .expect("checked above");
+        rule.dow = uw.dow;
+        rule.period = uw.period;
+        rule.note = uw.note;
+        Ok(rule.clone())
+    }
+
+    async fn delete_weekly_unavailability(&self, rule_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if s.weekly_unavailability.remove(&rule_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_availability_exceptions(
+        &self,
+        schedule_id: Uuid,
+        user_id: Option<Uuid>,
+    ) -> AppResult<Vec<AvailabilityException>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .availability_exceptions
+            .values()
+            .filter(|x| x.schedule_id == schedule_id && user_id.is_none_or(|u| x.user_id == u))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| (x.starts_on, x.user_id));
+        Ok(out)
+    }
+
+    async fn get_availability_exception(
+        &self,
+        exception_id: Uuid,
+    ) -> AppResult<Option<AvailabilityException>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .availability_exceptions
+            .get(&exception_id)
+            .cloned())
+    }
+
+    async fn create_availability_exception(
+        &self,
+        ne: NewAvailabilityException,
+    ) -> AppResult<AvailabilityException> {
+        check_exception_range(ne.starts_on, ne.ends_on)?;
+        let mut s = self.state.write().unwrap();
+        let exception = AvailabilityException {
+            id: Uuid::new_v4(),
+            schedule_id: ne.schedule_id,
+            user_id: ne.user_id,
+            starts_on: ne.starts_on,
+            ends_on: ne.ends_on,
+            available: ne.available,
+            note: ne.note,
+            created_at: Utc::now(),
+        };
+        s.availability_exceptions
+            .insert(exception.id, exception.clone());
+        Ok(exception)
+    }
+
+    async fn update_availability_exception(
+        &self,
+        exception_id: Uuid,
+        ue: UpdateAvailabilityException,
+    ) -> AppResult<AvailabilityException> {
+        check_exception_range(ue.starts_on, ue.ends_on)?;
+        let mut s = self.state.write().unwrap();
+        let Some(exception) = s.availability_exceptions.get_mut(&exception_id) else {
+            return Err(AppError::NotFound);
+        };
+        exception.starts_on = ue.starts_on;
+        exception.ends_on = ue.ends_on;
+        exception.available = ue.available;
+        exception.note = ue.note;
+        Ok(exception.clone())
+    }
+
+    async fn delete_availability_exception(&self, exception_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if s.availability_exceptions.remove(&exception_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
     }
 
-    async fn get_schedule(&self, schedule_id: Uuid) -> AppResult<Option<Schedule>> {
+    async fn create_leave_request(&self, nl: NewLeaveRequest) -> AppResult<LeaveRequest> {
+        check_exception_range(nl.starts_on, nl.ends_on)?;
+        let mut s = self.state.write().unwrap();
+        let leave = LeaveRequest {
+            id: Uuid::new_v4(),
+            schedule_id: nl.schedule_id,
+            user_id: nl.user_id,
+            starts_on: nl.starts_on,
+            ends_on: nl.ends_on,
+            reason: nl.reason,
+            status: LeaveStatus::Pending,
+            decided_by: None,
+            decision_note: None,
+            decided_at: None,
+            created_at: Utc::now(),
+        };
+        s.leave_requests.insert(leave.id, leave.clone());
+        Ok(leave)
+    }
+
+    async fn get_leave_request(&self, leave_id: Uuid) -> AppResult<Option<LeaveRequest>> {
         Ok(self
             .state
             .read()
             .unwrap()
-            .schedules
-            .get(&schedule_id)
+            .leave_requests
+            .get(&leave_id)
             .cloned())
     }
 
-    async fn get_schedule_role(
+    async fn list_leave_requests(
         &self,
         schedule_id: Uuid,
-        user_id: Uuid,
-    ) -> AppResult<Option<ScheduleRole>> {
-        Ok(self
-            .state
-            .read()
-            .unwrap()
-            .members
-            .get(&(schedule_id, user_id))
-            .copied())
+        user_id: Option<Uuid>,
+        status: Option<LeaveStatus>,
+    ) -> AppResult<Vec<LeaveRequest>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .leave_requests
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .filter(|x| user_id.is_none_or(|u| x.user_id == u))
+            .filter(|x| status.is_none_or(|st| x.status == st))
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| (x.starts_on, x.created_at));
+        Ok(out)
     }
 
-    async fn list_schedule_members(
+    async fn tally_shifts(
         &self,
         schedule_id: Uuid,
-    ) -> AppResult<Vec<(User, ScheduleRole)>> {
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        zone: Tz,
+    ) -> AppResult<Vec<ShiftTally>> {
         let s = self.state.read().unwrap();
-        let mut out = Vec::new();
-        for ((sid, uid), role) in s.members.iter() {
-            if *sid != schedule_id {
-                continue;
+        let mut groups: HashMap<(Option<Uuid>, Period, bool), (i64, i64)> = HashMap::new();
+        for shift in s
+            .shifts
+            .values()
+            .filter(|x| x.schedule_id == schedule_id && x.starts_at >= from && x.starts_at < to)
+        {
+            let weekend = shift
+                .starts_at
+                .with_timezone(&zone)
+                .weekday()
+                .number_from_monday()
+                >= 6;
+            let minutes = (shift.ends_at - shift.starts_at).num_minutes();
+            for a in &shift.assignees {
+                let group = groups
+                    .entry((Some(a.user_id), shift.period, weekend))
+                    .or_default();
+                group.0 += 1;
+                group.1 += minutes;
             }
-            if let Some((user, _)) = s.users.get(uid) {
-                out.push((user.clone(), *role));
+            let free = shift.free_places() as i64;
+            if free > 0 {
+                let group = groups.entry((None, shift.period, weekend)).or_default();
+                group.0 += free;
+                group.1 += free * minutes;
             }
         }
+        Ok(groups
+            .into_iter()
+            .map(
+                |((user_id, period, weekend), (shifts, minutes))| ShiftTally {
+                    user_id,
+                    period,
+                    weekend,
+                    shifts,
+                    minutes,
+                },
+            )
+            .collect())
+    }
+
+    async fn list_coverage_rules(&self, schedule_id: Uuid) -> AppResult<Vec<CoverageRule>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .coverage_rules
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| (x.dow, x.period.as_str()));
         Ok(out)
     }
 
-    async fn add_member(
-        &self,
-        schedule_id: Uuid,
-        user_id: Uuid,
-        role: ScheduleRole,
-    ) -> AppResult<()> {
+    async fn get_coverage_rule(&self, rule_id: Uuid) -> AppResult<Option<CoverageRule>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .coverage_rules
+            .get(&rule_id)
+            .cloned())
+    }
+
+    async fn create_coverage_rule(&self, nc: NewCoverageRule) -> AppResult<CoverageRule> {
         let mut s = self.state.write().unwrap();
-        let key = (schedule_id, user_id);
-        if s.members.contains_key(&key) {
-            return Err(AppError::Conflict("user already in schedule".to_string()));
-        }
-        s.members.insert(key, role);
-        Ok(())
+        check_coverage_rule_unique(&s, None, nc.schedule_id, nc.dow, nc.period)?;
+        let rule = CoverageRule {
+            id: Uuid::new_v4(),
+            schedule_id: nc.schedule_id,
+            dow: nc.dow,
+            period: nc.period,
+            required: nc.required,
+            created_at: Utc::now(),
+        };
+        s.coverage_rules.insert(rule.id, rule.clone());
+        Ok(rule)
     }
 
-    async fn set_member_role(
+    async fn update_coverage_rule(
         &self,
-        schedule_id: Uuid,
-        user_id: Uuid,
-        role: ScheduleRole,
-    ) -> AppResult<()> {
+        rule_id: Uuid,
+        uc: UpdateCoverageRule,
+    ) -> AppResult<CoverageRule> {
         let mut s = self.state.write().unwrap();
-        let key = (schedule_id, user_id);
-        if !s.members.contains_key(&key) {
+        let Some(current) = s.coverage_rules.get(&rule_id) else {
             return Err(AppError::NotFound);
-        }
-        s.members.insert(key, role);
-        Ok(())
+        };
+        let schedule_id = current.schedule_id;
+        check_coverage_rule_unique(&s, Some(rule_id), schedule_id, uc.dow, uc.period)?;
+        let rule = s.coverage_rules.get_mut(&rule_id).expect("checked above");
+        rule.dow = uc.dow;
+        rule.period = uc.period;
+        rule.required = uc.required;
+        Ok(rule.clone())
     }
 
-    async fn create_shift(&self, ns: NewShift) -> AppResult<Shift> {
+    async fn delete_coverage_rule(&self, rule_id: Uuid) -> AppResult<()> {
         let mut s = self.state.write().unwrap();
-        let id = Uuid::new_v4();
-        let shift = Shift {
-            id,
-            schedule_id: ns.schedule_id,
-            starts_at: ns.starts_at,
-            ends_at: ns.ends_at,
-            period: ns.period,
-            assigned_user_id: None,
-            created_by: ns.created_by,
-            created_at: Utc::now(),
-        };
-        s.shifts.insert(id, shift.clone());
-        Ok(shift)
+        if s.coverage_rules.remove(&rule_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
     }
 
-    async fn list_shifts(
+    async fn list_attendance_for_shifts(
         &self,
-        schedule_id: Uuid,
-        from: DateTime<Utc>,
-        to: DateTime<Utc>,
-    ) -> AppResult<Vec<Shift>> {
+        shift_ids: &[Uuid],
+    ) -> AppResult<Vec<ShiftAttendance>> {
         let s = self.state.read().unwrap();
         let mut out: Vec<_> = s
-            .shifts
+            .attendance
             .values()
-            .filter(|x| x.schedule_id == schedule_id && x.starts_at >= from && x.starts_at < to)
+            .filter(|x| shift_ids.contains(&x.shift_id))
             .cloned()
             .collect();
-        out.sort_by_key(|x| x.starts_at);
+        out.sort_by_key(|x| {
+            (
+                x.shift_id,
+                x.checked_in_at.is_none(),
+                x.checked_in_at,
+                x.user_id,
+            )
+        });
         Ok(out)
     }
 
-    async fn get_shift(&self, shift_id: Uuid) -> AppResult<Option<Shift>> {
-        Ok(self.state.read().unwrap().shifts.get(&shift_id).cloned())
-    }
-
-    async fn assign_shift(&self, shift_id: Uuid, assigned_user_id: Option<Uuid>) -> AppResult<()> {
+    async fn check_in(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance> {
         let mut s = self.state.write().unwrap();
-        let Some(shift) = s.shifts.get_mut(&shift_id) else {
-            return Err(AppError::NotFound);
-        };
-        shift.assigned_user_id = assigned_user_id;
-        Ok(())
+        let entry = s
+            .attendance
+            .entry((shift_id, user_id))
+            .or_insert(ShiftAttendance {
+                shift_id,
+                user_id,
+                checked_in_at: None,
+                checked_out_at: None,
+                updated_at: at,
+            });
+        if entry.checked_in_at.is_some() {
+            return Err(AppError::Conflict("already checked in".to_string()));
+        }
+        entry.checked_in_at = Some(at);
+        entry.updated_at = Utc::now();
+        Ok(entry.clone())
     }
 
-    async fn add_shift_comment(&self, nc: NewShiftComment) -> AppResult<ShiftComment> {
+    async fn check_out(
+        &self,
+        shift_id: Uuid,
+        user_id: Uuid,
+        at: DateTime<Utc>,
+    ) -> AppResult<ShiftAttendance> {
         let mut s = self.state.write().unwrap();
-        let c = ShiftComment {
-            id: Uuid::new_v4(),
-            shift_id: nc.shift_id,
-            user_id: nc.user_id,
-            body: nc.body,
-            created_at: Utc::now(),
+        let Some(entry) = s
+            .attendance
+            .get_mut(&(shift_id, user_id))
+            .filter(|x| x.checked_in_at.is_some_and(|t| t < at) && x.checked_out_at.is_none())
+        else {
+            return Err(AppError::Conflict(
+                "not checked in, or already checked out".to_string(),
+            ));
         };
-        s.comments.entry(nc.shift_id).or_default().push(c.clone());
-        Ok(c)
+        entry.checked_out_at = Some(at);
+        entry.updated_at = Utc::now();
+        Ok(entry.clone())
     }
 
-    async fn list_shift_comments(&self, shift_id: Uuid) -> AppResult<Vec<ShiftComment>> {
+    async fn list_attendance_corrections(
+        &self,
+        shift_id: Uuid,
+    ) -> AppResult<Vec<AttendanceCorrection>> {
         Ok(self
             .state
             .read()
             .unwrap()
-            .comments
+            .attendance_corrections
             .get(&shift_id)
             .cloned()
             .unwrap_or_default())
     }
 
-    async fn create_template(&self, nt: NewTemplate) -> AppResult<RotationTemplate> {
+    async fn list_schedule_tasks(&self, schedule_id: Uuid) -> AppResult<Vec<ScheduleTask>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .schedule_tasks
+            .values()
+            .filter(|x| x.schedule_id == schedule_id)
+            .cloned()
+            .collect();
+        out.sort_by_key(|x| (x.due_time.is_none(), x.due_time, x.created_at));
+        Ok(out)
+    }
+
+    async fn get_schedule_task(&self, task_id: Uuid) -> AppResult<Option<ScheduleTask>> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .schedule_tasks
+            .get(&task_id)
+            .cloned())
+    }
+
+    async fn create_schedule_task(&self, nt: NewScheduleTask) -> AppResult<ScheduleTask> {
         let mut s = self.state.write().unwrap();
-        let t = RotationTemplate {
+        let task = ScheduleTask {
             id: Uuid::new_v4(),
             schedule_id: nt.schedule_id,
-            name: nt.name,
-            definition: nt.definition,
-            created_by: nt.created_by,
+            title: nt.title,
+            due_time: nt.due_time,
+            period: nt.period,
             created_at: Utc::now(),
         };
-        s.templates.insert(t.id, t.clone());
-        Ok(t)
+        s.schedule_tasks.insert(task.id, task.clone());
+        Ok(task)
     }
 
-    async fn list_templates(&self, schedule_id: Uuid) -> AppResult<Vec<RotationTemplate>> {
+    async fn update_schedule_task(
+        &self,
+        task_id: Uuid,
+        ut: UpdateScheduleTask,
+    ) -> AppResult<ScheduleTask> {
+        let mut s = self.state.write().unwrap();
+        let Some(task) = s.schedule_tasks.get_mut(&task_id) else {
+            return Err(AppError::NotFound);
+        };
+        task.title = ut.title;
+        task.due_time = ut.due_time;
+        task.period = ut.period;
+        Ok(task.clone())
+    }
+
+    async fn delete_schedule_task(&self, task_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if s.schedule_tasks.remove(&task_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        // Mirrors `on delete set null` on `shift_task.schedule_task_id`.
+        for task in s.shift_tasks.values_mut() {
+            if task.schedule_task_id == Some(task_id) {
+                task.schedule_task_id = None;
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_shift_tasks(&self, shift_id: Uuid) -> AppResult<Vec<ShiftTask>> {
         let s = self.state.read().unwrap();
         let mut out: Vec<_> = s
-            .templates
+            .shift_tasks
             .values()
-            .filter(|x| x.schedule_id == schedule_id)
+            .filter(|x| x.shift_id == shift_id)
             .cloned()
             .collect();
-        out.sort_by_key(|x| x.created_at);
-        out.reverse();
+        out.sort_by_key(|x| x.position);
         Ok(out)
     }
 
-    async fn get_template(&self, template_id: Uuid) -> AppResult<Option<RotationTemplate>> {
+    async fn get_shift_task(&self, task_id: Uuid) -> AppResult<Option<ShiftTask>> {
         Ok(self
             .state
             .read()
             .unwrap()
-            .templates
-            .get(&template_id)
+            .shift_tasks
+            .get(&task_id)
             .cloned())
     }
+
+    async fn add_shift_task(&self, shift_id: Uuid, nt: NewShiftTask) -> AppResult<ShiftTask> {
+        let mut s = self.state.write().unwrap();
+        let position = s
+            .shift_tasks
+            .values()
+            .filter(|x| x.shift_id == shift_id)
+            .map(|x| x.position + 1)
+            .max()
+            .unwrap_or(0);
+        let task = ShiftTask {
+            id: Uuid::new_v4(),
+            shift_id,
+            schedule_task_id: nt.schedule_task_id,
+            title: nt.title,
+            due_at: nt.due_at,
+            position,
+            done_at: None,
+            done_by: None,
+            created_at: Utc::now(),
+        };
+        s.shift_tasks.insert(task.id, task.clone());
+        Ok(task)
+    }
+
+    async fn set_shift_task_done(
+        &self,
+        task_id: Uuid,
+        done_by: Option<Uuid>,
+    ) -> AppResult<ShiftTask> {
+        let mut s = self.state.write().unwrap();
+        let Some(task) = s.shift_tasks.get_mut(&task_id) else {
+            return Err(AppError::NotFound);
+        };
+        task.done_at = done_by.map(|_| Utc::now());
+        task.done_by = done_by;
+        Ok(task.clone())
+    }
+
+    async fn delete_shift_task(&self, task_id: Uuid) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if s.shift_tasks.remove(&task_id).is_none() {
+            return Err(AppError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_incomplete_tasks(
+        &self,
+        schedule_id: Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<Vec<ShiftTask>> {
+        let s = self.state.read().unwrap();
+        let mut out: Vec<_> = s
+            .shift_tasks
+            .values()
+            .filter(|x| x.done_at.is_none())
+            .filter_map(|x| {
+                let shift = s.shifts.get(&x.shift_id)?;
+                (shift.schedule_id == schedule_id
+                    && shift.starts_at >= from
+                    && shift.starts_at < to)
+                    .then(|| (shift.starts_at, x.clone()))
+            })
+            .collect();
+        out.sort_by_key(|(starts_at, x)| (*starts_at, x.shift_id, x.position));
+        Ok(out.into_iter().map(|(_, x)| x).collect())
+    }
+
+    async fn begin_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        scope: &str,
+    ) -> AppResult<IdempotentRequest> {
+        let mut s = self.state.write().unwrap();
+        let map_key = (user_id, key.to_string());
+        let cutoff = Utc::now() - chrono::Duration::hours(IDEMPOTENCY_TTL_HOURS);
+        if s.idempotency
+            .get(&map_key)
+            .is_some_and(|e| e.created_at < cutoff)
+        {
+            s.idempotency.remove(&map_key);
+        }
+        let Some(entry) = s.idempotency.get(&map_key) else {
+            s.idempotency.insert(
+                map_key,
+                MemIdempotency {
+                    scope: scope.to_string(),
+                    response: None,
+                    created_at: Utc::now(),
+                },
+            );
+            return Ok(IdempotentRequest::New);
+        };
+        if entry.scope != scope {
+            return Err(AppError::Conflict(
+                "Idempotency-Key was already used for a different request".to_string(),
+            ));
+        }
+        Ok(match &entry.response {
+            None => IdempotentRequest::InProgress,
+            Some((status, body)) => IdempotentRequest::Completed {
+                status: *status,
+                body: body.clone(),
+            },
+        })
+    }
+
+    async fn finish_idempotent_request(
+        &self,
+        user_id: Uuid,
+        key: &str,
+        status: u16,
+        body: serde_json::Value,
+    ) -> AppResult<()> {
+        let mut s = self.state.write().unwrap();
+        if let Some(entry) = s.idempotency.get_mut(&(user_id, key.to_string())) {
+            entry.response = Some((status, body));
+        }
+        Ok(())
+    }
+
+    async fn abort_idempotent_request(&self, user_id: Uuid, key: &str) -> AppResult<()> {
+        self.state
+            .write()
+            .unwrap()
+            .idempotency
+            .remove(&(user_id, key.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mem_commit_is_all_or_nothing() {
+        let repo = MemRepo::new();
+        let user = repo
+            .create_user(NewUser {
+                email: "a@example.com".to_string(),
+                password_hash: String::new(),
+                is_superadmin: false,
+            })
+            .await
+            .unwrap();
+        let schedule = repo
+            .create_schedule(NewSchedule {
+                name: "Care".to_string(),
+                subject_type: "person".to_string(),
+                subject_name: "Grandma".to_string(),
+                timezone: "UTC".to_string(),
+                created_by: user.id,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            repo.get_schedule_role(schedule.id, user.id).await.unwrap(),
+            Some(ScheduleRole::Admin)
+        );
+
+        let starts_at = Utc::now();
+        let shift = |ends_at| NewShift {
+            schedule_id: schedule.id,
+            starts_at,
+            ends_at,
+            period: Period::Morning,
+            capacity: 1,
+            assigned_user_id: None,
+            template_id: None,
+            template_week: None,
+            tasks: Vec::new(),
+            created_by: user.id,
+        };
+        let mut uow = UnitOfWork::new();
+        uow.create_shift(shift(starts_at + chrono::Duration::hours(4)));
+        uow.create_shift(shift(starts_at));
+        let err = repo.commit(uow).await.unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(_)));
+
+        let day = chrono::Duration::days(1);
+        let shifts = repo
+            .list_shifts(schedule.id, starts_at - day, starts_at + day)
+            .await
+            .unwrap();
+        assert!(shifts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_template_applications_conflict() {
+        let repo = MemRepo::new();
+        let user = repo
+            .create_user(NewUser {
+                email: "a@example.com".to_string(),
+                password_hash: String::new(),
+                is_superadmin: false,
+            })
+            .await
+            .unwrap();
+        let schedule = repo
+            .create_schedule(NewSchedule {
+                name: "Care".to_string(),
+                subject_type: "person".to_string(),
+                subject_name: "Grandma".to_string(),
+                timezone: "UTC".to_string(),
+                created_by: user.id,
+            })
+            .await
+            .unwrap();
+        let template = repo
+            .create_template(NewTemplate {
+                schedule_id: schedule.id,
+                name: "Mornings".to_string(),
+                definition: serde_json::json!({ "slots": [] }),
+                created_by: user.id,
+            })
+            .await
+            .unwrap();
+
+        // Two applications of the same week, both planned before either ran.
+        let week = NaiveDate::from_ymd_opt(2025, 1, 6).unwrap();
+        let starts_at = week.and_hms_opt(8, 0, 0).unwrap().and_utc();
+        let apply = |rotation_state: serde_json::Value| {
+            let mut uow = UnitOfWork::new();
+            uow.lock_template(template.id, template.rotation_state.clone());
+            uow.ensure_template_weeks_unapplied(template.id, vec![week]);
+            uow.create_shift(NewShift {
+                schedule_id: schedule.id,
+                starts_at,
+                ends_at: starts_at + chrono::Duration::hours(4),
+                period: Period::Morning,
+                capacity: 1,
+                assigned_user_id: None,
+                template_id: Some(template.id),
+                template_week: Some(week),
+                tasks: Vec::new(),
+                created_by: user.id,
+            });
+            uow.set_template_rotation_state(template.id, rotation_state);
+            uow
+        };
+        let first = apply(serde_json::json!({ "0": 1 }));
+        let second = apply(serde_json::json!({ "0": 1 }));
+        repo.commit(first).await.unwrap();
+        let err = repo.commit(second).await.unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+
+        // With the rotation where it is now, the week is still taken.
+        let mut again = UnitOfWork::new();
+        again.lock_template(template.id, serde_json::json!({ "0": 1 }));
+        again.ensure_template_weeks_unapplied(template.id, vec![week]);
+        let err = repo.commit(again).await.unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+    }
 }
@@ -0,0 +1,239 @@
+//! Proposing fair assignments for a schedule's free shift places.
+
+use crate::models::{Period, Shift};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Minimum gap between two shifts worked by the same member, unless the
+/// caller asks for another.
+pub const DEFAULT_MIN_REST_HOURS: i64 = 8;
+
+/// Longest rest a caller can ask for.
+pub const MAX_MIN_REST_HOURS: i64 = 168;
+
+/// Periods shared out on their own, so nobody gets all the nights.
+pub fn is_unpleasant(period: Period) -> bool {
+    matches!(period, Period::Night | Period::Sleep)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Load {
+    pub shifts: u32,
+    pub minutes: i64,
+    /// Night and sleep shifts, also counted in `shifts`.
+    pub unpleasant: u32,
+}
+
+impl Load {
+    pub fn add(&mut self, shift: &Shift) {
+        self.shifts += 1;
+        self.minutes += (shift.ends_at - shift.starts_at).num_minutes();
+        if is_unpleasant(shift.period) {
+            self.unpleasant += 1;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub user_id: Uuid,
+    /// Shifts the member already works, in any schedule.
+    pub busy: Vec<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Work already assigned to the member in the range being filled.
+    pub load: Load,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Assignment {
+    pub shift_id: Uuid,
+    pub user_id: Uuid,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Unfilled {
+    pub shift_id: Uuid,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct Plan {
+    pub assignments: Vec<Assignment>,
+    pub unfilled: Vec<Unfilled>,
+    /// Each member's load once the plan is applied, in input order.
+    pub loads: Vec<(Uuid, Load)>,
+}
+
+/// Whether a shift over `[starts_at, ends_at)` overlaps `shift` or leaves
+/// less than `min_rest` between them.
+pub fn too_close(
+    shift: &Shift,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    min_rest: Duration,
+) -> bool {
+    // Past the end of time, everything is within the rest period.
+    let before = |t: DateTime<Utc>, end: DateTime<Utc>| {
+        end.checked_add_signed(min_rest).is_none_or(|end| t < end)
+    };
+    before(shift.starts_at, ends_at) && before(starts_at, shift.ends_at)
+}
+
+/// Fills the free places on `open` shifts greedily: night and sleep shifts
+/// first, then the rest, each in chronological order. A place goes to the
+/// eligible member with the lightest load, comparing unpleasant shifts first
+/// for night and sleep shifts, then shift count, then minutes. A member is
+/// eligible if the shift neither overlaps nor comes within `min_rest` of
+/// anything they work, and `blocked` returns `None` for them. Ties go to the
+/// earlier member, so the plan is deterministic.
+pub fn propose(
+    open: &[Shift],
+    mut members: Vec<Member>,
+    min_rest: Duration,
+    blocked: impl Fn(Uuid, &Shift) -> Option<String>,
+) -> Plan {
+    let mut order: Vec<&Shift> = open.iter().collect();
+    order.sort_by_key(|s| (!is_unpleasant(s.period), s.starts_at));
+
+    let mut assignments = Vec::new();
+    let mut unfilled = Vec::new();
+    for shift in order {
+        let unpleasant = is_unpleasant(shift.period);
+        for _ in 0..shift.free_places() {
+            let pick = members
+                .iter_mut()
+                .filter(|m| {
+                    !m.busy
+                        .iter()
+                        .any(|&(starts_at, ends_at)| too_close(shift, starts_at, ends_at, min_rest))
+                })
+                .filter(|m| blocked(m.user_id, shift).is_none())
+                .min_by_key(|m| {
+                    let l = m.load;
+                    if unpleasant {
+                        (i64::from(l.unpleasant), i64::from(l.shifts), l.minutes)
+                    } else {
+                        (i64::from(l.shifts), l.minutes, i64::from(l.unpleasant))
+                    }
+                });
+
+            let Some(member) = pick else {
+                unfilled.push(Unfilled {
+                    shift_id: shift.id,
+                    reason: if members.is_empty() {
+                        "no members to assign".to_string()
+                    } else {
+                        "every member is busy, resting or unavailable".to_string()
+                    },
+                });
+                break;
+            };
+            member.busy.push((shift.starts_at, shift.ends_at));
+            member.load.add(shift);
+            assignments.push(Assignment {
+                shift_id: shift.id,
+                user_id: member.user_id,
+            });
+        }
+    }
+
+    assignments.sort_by_key(|a| open.iter().position(|s| s.id == a.shift_id));
+    Plan {
+        assignments,
+        unfilled,
+        loads: members.into_iter().map(|m| (m.user_id, m.load)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shift(starts_at: &str, hours: i64, period: Period) -> Shift {
+        let starts_at = DateTime::parse_from_rfc3339(starts_at)
+            .unwrap()
+            .with_timezone(&Utc);
+        Shift {
+            id: Uuid::new_v4(),
+            schedule_id: Uuid::nil(),
+            starts_at,
+            ends_at: starts_at + Duration::hours(hours),
+            period,
+            capacity: 1,
+            assignees: Vec::new(),
+            template_id: None,
+            template_week: None,
+            created_by: Uuid::nil(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn member(user_id: Uuid) -> Member {
+        Member {
+            user_id,
+            busy: Vec::new(),
+            load: Load::default(),
+        }
+    }
+
+    #[test]
+    fn shares_nights_and_days_separately() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut open = Vec::new();
+        for day in 1..=4 {
+            open.push(shift(
+                &format!("2025-03-0{day}T08:00:00Z"),
+                4,
+                Period::Morning,
+            ));
+            open.push(shift(
+                &format!("2025-03-0{day}T22:00:00Z"),
+                8,
+                Period::Sleep,
+            ));
+        }
+        // `a` already did a night last week.
+        let mut first = member(a);
+        first
+            .load
+            .add(&shift("2025-02-28T22:00:00Z", 8, Period::Sleep));
+
+        let plan = propose(&open, vec![first, member(b)], Duration::hours(8), |_, _| {
+            None
+        });
+        assert!(plan.unfilled.is_empty());
+        let loads: Vec<_> = plan.loads.iter().map(|(_, l)| l).collect();
+        assert_eq!((loads[0].unpleasant, loads[1].unpleasant), (3, 2));
+        assert_eq!((loads[0].shifts, loads[1].shifts), (5, 4));
+        // Each member works a morning and that night, never a morning
+        // straight after a sleep shift.
+        let who: Vec<_> = plan.assignments.iter().map(|x| x.user_id).collect();
+        assert_eq!(who, [b, b, a, a, b, b, a, a]);
+    }
+
+    #[test]
+    fn leaves_shift_open_without_rest_or_availability() {
+        let a = Uuid::new_v4();
+        let night = shift("2025-03-01T18:00:00Z", 6, Period::Night);
+        let morning = shift("2025-03-02T06:00:00Z", 4, Period::Morning);
+        let afternoon = shift("2025-03-02T14:00:00Z", 4, Period::Afternoon);
+        let afternoon_id = afternoon.id;
+
+        let plan = propose(
+            &[night.clone(), morning.clone(), afternoon],
+            vec![member(a)],
+            Duration::hours(8),
+            |_, s| (s.id == afternoon_id).then(|| "away".to_string()),
+        );
+        assert_eq!(
+            plan.assignments,
+            [Assignment {
+                shift_id: night.id,
+                user_id: a
+            }]
+        );
+        let unfilled: Vec<_> = plan.unfilled.iter().map(|u| u.shift_id).collect();
+        assert_eq!(unfilled, [morning.id, afternoon_id]);
+    }
+}
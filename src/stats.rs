@@ -0,0 +1,271 @@
+//! Workload statistics for a schedule, built from shift tallies.
+
+use crate::models::{Period, ShiftTally};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub shifts: i64,
+    pub hours: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct PeriodTotals {
+    pub morning: Totals,
+    pub afternoon: Totals,
+    pub night: Totals,
+    pub sleep: Totals,
+}
+
+impl PeriodTotals {
+    fn get_mut(&mut self, period: Period) -> &mut Totals {
+        match period {
+            Period::Morning => &mut self.morning,
+            Period::Afternoon => &mut self.afternoon,
+            Period::Night => &mut self.night,
+            Period::Sleep => &mut self.sleep,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MemberStats {
+    pub user_id: Uuid,
+    pub email: String,
+    pub shifts: i64,
+    pub hours: f64,
+    pub weekday: Totals,
+    pub weekend: Totals,
+    pub by_period: PeriodTotals,
+    /// Difference from an equal share of the assigned shifts and hours.
+    pub deviation_shifts: f64,
+    pub deviation_hours: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ScheduleStats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Every shift in the range, assigned or not.
+    pub total: Totals,
+    pub unassigned: Totals,
+    /// Assigned shifts and hours divided evenly between the members.
+    pub share_shifts: f64,
+    pub share_hours: f64,
+    pub members: Vec<MemberStats>,
+}
+
+fn hours(minutes: i64) -> f64 {
+    round2(minutes as f64 / 60.0)
+}
+
+fn round2(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
+
+fn add(t: &mut Totals, shifts: i64, minutes: i64) {
+    t.shifts += shifts;
+    t.hours = round2(t.hours + hours(minutes));
+}
+
+/// One row per entry of `members` (user id and email), in order. Tallies for
+/// an assignee missing from `members` are counted only in the totals.
+pub fn build(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    members: Vec<(Uuid, String)>,
+    tallies: &[ShiftTally],
+) -> ScheduleStats {
+    let mut rows: Vec<MemberStats> = members
+        .into_iter()
+        .map(|(user_id, email)| MemberStats {
+            user_id,
+            email,
+            shifts: 0,
+            hours: 0.0,
+            weekday: Totals::default(),
+            weekend: Totals::default(),
+            by_period: PeriodTotals::default(),
+            deviation_shifts: 0.0,
+            deviation_hours: 0.0,
+        })
+        .collect();
+
+    let mut total = Totals::default();
+    let mut unassigned = Totals::default();
+    let (mut assigned_shifts, mut assigned_minutes) = (0, 0);
+    for t in tallies {
+        add(&mut total, t.shifts, t.minutes);
+        let Some(user_id) = t.user_id else {
+            add(&mut unassigned, t.shifts, t.minutes);
+            continue;
+        };
+        assigned_shifts += t.shifts;
+        assigned_minutes += t.minutes;
+        let Some(row) = rows.iter_mut().find(|r| r.user_id == user_id) else {
+            continue;
+        };
+        row.shifts += t.shifts;
+        row.hours = round2(row.hours + hours(t.minutes));
+        let day = if t.weekend {
+            &mut row.weekend
+        } else {
+            &mut row.weekday
+        };
+        add(day, t.shifts, t.minutes);
+        add(row.by_period.get_mut(t.period), t.shifts, t.minutes);
+    }
+
+    let (share_shifts, share_hours) = if rows.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let n = rows.len() as f64;
+        (
+            assigned_shifts as f64 / n,
+            assigned_minutes as f64 / 60.0 / n,
+        )
+    };
+    for row in &mut rows {
+        row.deviation_shifts = round2(row.shifts as f64 - share_shifts);
+        row.deviation_hours = round2(row.hours - share_hours);
+    }
+
+    ScheduleStats {
+        from,
+        to,
+        total,
+        unassigned,
+        share_shifts: round2(share_shifts),
+        share_hours: round2(share_hours),
+        members: rows,
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One line per member, then an `unassigned` line with only the totals.
+pub fn to_csv(stats: &ScheduleStats) -> String {
+    let mut out = String::from(
+        "user_id,email,shifts,hours,weekday_shifts,weekday_hours,weekend_shifts,weekend_hours,\
+         morning_shifts,morning_hours,afternoon_shifts,afternoon_hours,night_shifts,night_hours,\
+         sleep_shifts,sleep_hours,deviation_shifts,deviation_hours\n",
+    );
+    for m in &stats.members {
+        let p = &m.by_period;
+        let _ = write!(
+            out,
+            "{},{},{},{}",
+            m.user_id,
+            csv_field(&m.email),
+            m.shifts,
+            m.hours
+        );
+        for t in [
+            m.weekday,
+            m.weekend,
+            p.morning,
+            p.afternoon,
+            p.night,
+            p.sleep,
+        ] {
+            let _ = write!(out, ",{},{}", t.shifts, t.hours);
+        }
+        let _ = writeln!(out, ",{},{}", m.deviation_shifts, m.deviation_hours);
+    }
+    let _ = writeln!(
+        out,
+        ",unassigned,{},{},,,,,,,,,,,,,,",
+        stats.unassigned.shifts, stats.unassigned.hours
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(
+        user_id: Option<Uuid>,
+        period: Period,
+        weekend: bool,
+        shifts: i64,
+        minutes: i64,
+    ) -> ShiftTally {
+        ShiftTally {
+            user_id,
+            period,
+            weekend,
+            shifts,
+            minutes,
+        }
+    }
+
+    #[test]
+    fn build_reports_deviation_from_equal_share() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let stats = build(
+            now,
+            now,
+            vec![
+                (a, "a@example.com".to_string()),
+                (b, "b,c@example.com".to_string()),
+            ],
+            &[
+                tally(Some(a), Period::Night, false, 2, 480),
+                tally(Some(a), Period::Sleep, true, 1, 600),
+                tally(Some(b), Period::Morning, false, 1, 240),
+                tally(None, Period::Morning, true, 1, 90),
+            ],
+        );
+
+        assert_eq!(
+            stats.total,
+            Totals {
+                shifts: 5,
+                hours: 23.5
+            }
+        );
+        assert_eq!(
+            stats.unassigned,
+            Totals {
+                shifts: 1,
+                hours: 1.5
+            }
+        );
+        assert_eq!((stats.share_shifts, stats.share_hours), (2.0, 11.0));
+        let a_row = &stats.members[0];
+        assert_eq!((a_row.shifts, a_row.hours), (3, 18.0));
+        assert_eq!(
+            a_row.weekend,
+            Totals {
+                shifts: 1,
+                hours: 10.0
+            }
+        );
+        assert_eq!(
+            a_row.by_period.night,
+            Totals {
+                shifts: 2,
+                hours: 8.0
+            }
+        );
+        assert_eq!((a_row.deviation_shifts, a_row.deviation_hours), (1.0, 7.0));
+        assert_eq!(stats.members[1].deviation_hours, -7.0);
+
+        let csv = to_csv(&stats);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with(&format!("{b},\"b,c@example.com\",1,4,")));
+        assert_eq!(lines[0].split(',').count(), lines[3].split(',').count());
+    }
+}
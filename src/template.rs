@@ -0,0 +1,312 @@
+//! Rotation template definitions and their expansion into concrete shifts.
+
+use crate::{
+    checklist::SlotTask,
+    error::{AppError, AppResult},
+    models::Period,
+    tz,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Longest range a template can be applied over in one request.
+pub const MAX_APPLY_DAYS: i64 = 366;
+const MAX_CYCLE_WEEKS: u32 = 52;
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateSlot {
+    pub dow: i64, // 0=Mon..6=Sun, relative to the start of the cycle week
+    /// 0-based week of the cycle this slot belongs to.
+    #[serde(default)]
+    pub week: u32,
+    pub period: Period, // morning/afternoon/night/sleep
+    pub start: String,  // HH:MM
+    pub end: String,    // HH:MM
+    /// Member every shift from this slot is assigned to.
+    pub assignee: Option<Uuid>,
+    /// Members this slot's shifts rotate through, in order.
+    #[serde(default)]
+    pub rotate: Vec<Uuid>,
+    /// Checklist items every shift from this slot gets.
+    #[serde(default)]
+    pub tasks: Vec<SlotTask>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateDef {
+    /// Number of weeks before the rotation repeats.
+    #[serde(default = "default_cycle_weeks")]
+    pub cycle_weeks: u32,
+    /// First day of cycle week 0, a Monday. Defaults to [`default_anchor`].
+    pub anchor: Option<NaiveDate>,
+    pub slots: Vec<TemplateSlot>,
+}
+
+fn default_cycle_weeks() -> u32 {
+    1
+}
+
+#[derive(Clone, Debug)]
+pub struct PlannedShift {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub period: Period,
+    /// Index of the slot this shift came from.
+    pub slot: usize,
+    /// First day of the cycle week this shift belongs to.
+    pub week_start: NaiveDate,
+}
+
+/// Next round-robin position per slot index, persisted on the template.
+pub type RotationState = HashMap<String, usize>;
+
+/// Monday of the week the template was created, in the schedule's zone. It
+/// doesn't move between applications, so applying one week at a time still
+/// walks through every week of the cycle.
+pub fn default_anchor(created_at: DateTime<Utc>, zone: Tz) -> NaiveDate {
+    let day = created_at.with_timezone(&zone).date_naive();
+    day - Duration::days(day.weekday().num_days_from_monday().into())
+}
+
+impl TemplateDef {
+    pub fn parse(definition: serde_json::Value) -> AppResult<Self> {
+        let def: TemplateDef = serde_json::from_value(definition)
+            .map_err(|_| AppError::BadRequest("invalid template definition".to_string()))?;
+        if !(1..=MAX_CYCLE_WEEKS).contains(&def.cycle_weeks) {
+            return Err(AppError::BadRequest(format!(
+                "cycle_weeks must be 1..{MAX_CYCLE_WEEKS}"
+            )));
+        }
+        // Slot days count from Monday, so the cycle must start on one.
+        if def.anchor.is_some_and(|a| a.weekday() != Weekday::Mon) {
+            return Err(AppError::BadRequest("anchor must be a Monday".to_string()));
+        }
+        for slot in &def.slots {
+            if !(0..=6).contains(&slot.dow) {
+                return Err(AppError::BadRequest("slot.dow must be 0..6".to_string()));
+            }
+            if slot.week >= def.cycle_weeks {
+                return Err(AppError::BadRequest(
+                    "slot.week must be below cycle_weeks".to_string(),
+                ));
+            }
+            if slot.assignee.is_some() && !slot.rotate.is_empty() {
+                return Err(AppError::BadRequest(
+                    "slot can have an assignee or a rotate pool, not both".to_string(),
+                ));
+            }
+            for task in &slot.tasks {
+                task.validate()?;
+            }
+        }
+        Ok(def)
+    }
+
+    /// Shifts for every slot whose day falls in `[from, to]` (local dates in
+    /// `zone`), repeating the cycle from `anchor` (or `default_anchor`).
+    pub fn expand(
+        &self,
+        zone: Tz,
+        default_anchor: NaiveDate,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> AppResult<Vec<PlannedShift>> {
+        if to < from {
+            return Err(AppError::BadRequest(
+                "to must not be before from".to_string(),
+            ));
+        }
+        if (to - from).num_days() >= MAX_APPLY_DAYS {
+            return Err(AppError::BadRequest(format!(
+                "range must be at most {MAX_APPLY_DAYS} days"
+            )));
+        }
+        let anchor = self.anchor.unwrap_or(default_anchor);
+
+        let mut times = Vec::with_capacity(self.slots.len());
+        for slot in &self.slots {
+            let start_t = NaiveTime::parse_from_str(&slot.start, "%H:%M")
+                .map_err(|_| AppError::BadRequest("slot.start must be HH:MM".to_string()))?;
+            let end_t = NaiveTime::parse_from_str(&slot.end, "%H:%M")
+                .map_err(|_| AppError::BadRequest("slot.end must be HH:MM".to_string()))?;
+            times.push((start_t, end_t));
+        }
+
+        let mut out = Vec::new();
+        let mut week = (from - anchor).num_days().div_euclid(7);
+        loop {
+            let week_start = anchor + Duration::days(week * 7);
+            if week_start > to {
+                break;
+            }
+            let cycle_week = week.rem_euclid(self.cycle_weeks.into()) as u32;
+            for (idx, (slot, (start_t, end_t))) in self.slots.iter().zip(&times).enumerate() {
+                if slot.week != cycle_week {
+                    continue;
+                }
+                let day = week_start + Duration::days(slot.dow);
+                if day < from || day > to {
+                    continue;
+                }
+
+                let start_naive = day.and_time(*start_t);
+                let mut end_naive = day.and_time(*end_t);
+                if end_naive <= start_naive {
+                    end_naive += Duration::days(1);
+                }
+
+                // Slots are wall-clock times in the schedule's zone; see
+                // `tz::localize` for how DST gaps and overlaps are resolved.
+                let starts_at = tz::localize(zone, start_naive)?;
+                let ends_at = tz::localize(zone, end_naive)?;
+                if ends_at <= starts_at {
+                    return Err(AppError::BadRequest(format!(
+                        "slot {}-{} on {day} collapses across a DST transition",
+                        slot.start, slot.end
+                    )));
+                }
+                out.push(PlannedShift {
+                    starts_at,
+                    ends_at,
+                    period: slot.period,
+                    slot: idx,
+                    week_start,
+                });
+            }
+            week += 1;
+        }
+        out.sort_by_key(|p| p.starts_at);
+        Ok(out)
+    }
+
+    /// Every member named by a slot, for membership checks.
+    pub fn named_members(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.slots
+            .iter()
+            .flat_map(|s| s.assignee.iter().chain(s.rotate.iter()))
+            .copied()
+    }
+
+    /// Assignee for each planned shift: the slot's fixed `assignee`, or the
+    /// next member of its `rotate` pool. Shifts must be in chronological
+    /// order; `positions` is advanced so the next application continues.
+    pub fn assignees(
+        &self,
+        planned: &[PlannedShift],
+        positions: &mut RotationState,
+    ) -> Vec<Option<Uuid>> {
+        planned
+            .iter()
+            .map(|p| {
+                let slot = &self.slots[p.slot];
+                if slot.rotate.is_empty() {
+                    return slot.assignee;
+                }
+                let pos = positions.entry(p.slot.to_string()).or_insert(0);
+                let member = slot.rotate[*pos % slot.rotate.len()];
+                *pos = (*pos + 1) % slot.rotate.len();
+                Some(member)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn expand_repeats_multi_week_cycle() {
+        let def = TemplateDef::parse(serde_json::json!({
+            "cycle_weeks": 2,
+            "anchor": "2025-01-06",
+            "slots": [
+                { "dow": 0, "week": 0, "period": "morning", "start": "08:00", "end": "12:00" },
+                { "dow": 0, "week": 1, "period": "sleep", "start": "22:00", "end": "08:00" }
+            ]
+        }))
+        .unwrap();
+
+        // Start mid-cycle: the week of the 13th is cycle week 1.
+        let shifts = def
+            .expand(
+                Tz::UTC,
+                date("2000-01-01"),
+                date("2025-01-13"),
+                date("2025-02-02"),
+            )
+            .unwrap();
+        let got: Vec<_> = shifts
+            .iter()
+            .map(|s| (s.starts_at.to_rfc3339(), s.period))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                ("2025-01-13T22:00:00+00:00".to_string(), Period::Sleep),
+                ("2025-01-20T08:00:00+00:00".to_string(), Period::Morning),
+                ("2025-01-27T22:00:00+00:00".to_string(), Period::Sleep),
+            ]
+        );
+        assert_eq!(shifts[0].ends_at.to_rfc3339(), "2025-01-14T08:00:00+00:00");
+    }
+
+    #[test]
+    fn default_anchor_is_the_monday_of_creation_in_zone() {
+        let created_at = DateTime::parse_from_rfc3339("2025-01-12T23:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // Still Sunday in UTC, already Monday in Auckland.
+        assert_eq!(default_anchor(created_at, Tz::UTC), date("2025-01-06"));
+        assert_eq!(
+            default_anchor(created_at, "Pacific/Auckland".parse().unwrap()),
+            date("2025-01-13")
+        );
+    }
+
+    #[test]
+    fn parse_rejects_slot_outside_cycle() {
+        let err = TemplateDef::parse(serde_json::json!({
+            "cycle_weeks": 2,
+            "slots": [{ "dow": 0, "week": 2, "period": "morning", "start": "08:00", "end": "12:00" }]
+        }));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn rotation_continues_across_applications() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let def = TemplateDef::parse(serde_json::json!({
+            "slots": [
+                { "dow": 0, "period": "morning", "start": "08:00", "end": "12:00", "rotate": [a, b, c] },
+                { "dow": 1, "period": "night", "start": "18:00", "end": "22:00", "assignee": a }
+            ]
+        }))
+        .unwrap();
+
+        let mut positions = RotationState::new();
+        let mut rota = Vec::new();
+        for week in ["2025-01-06", "2025-01-13", "2025-01-20", "2025-01-27"] {
+            let planned = def
+                .expand(
+                    Tz::UTC,
+                    date(week),
+                    date(week),
+                    date(week) + Duration::days(6),
+                )
+                .unwrap();
+            let assigned = def.assignees(&planned, &mut positions);
+            assert_eq!(assigned[1], Some(a));
+            rota.push(assigned[0].unwrap());
+        }
+        assert_eq!(rota, [a, b, c, a]);
+        assert_eq!(positions["0"], 1);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::{AppError, AppResult};
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+pub fn parse_tz(name: &str) -> AppResult<Tz> {
+    name.parse::<Tz>()
+        .map_err(|_| AppError::BadRequest(format!("unknown time zone: {name}")))
+}
+
+/// Resolves a wall-clock time in `tz` to an instant.
+///
+/// DST transitions are resolved deterministically:
+/// - ambiguous times (clocks going back) pick the earlier instant, i.e. the
+///   first time the wall clock shows that time;
+/// - non-existent times (clocks going forward) are pushed forward by the
+///   length of the gap, so a 02:30 slot on a spring-forward night starts at
+///   03:30 local.
+pub fn localize(tz: Tz, naive: NaiveDateTime) -> AppResult<DateTime<Utc>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => Ok(dt.with_timezone(&Utc)),
+        LocalResult::Ambiguous(earliest, _) => Ok(earliest.with_timezone(&Utc)),
+        LocalResult::None => {
+            // Read the time with the offset in effect just before the gap; on
+            // the post-transition clock that lands `gap` later.
+            let before = tz
+                .offset_from_utc_datetime(&(naive - Duration::days(1)))
+                .fix();
+            Ok(DateTime::<Utc>::from_naive_utc_and_offset(
+                naive - Duration::seconds(before.local_minus_utc().into()),
+                Utc,
+            ))
+        }
+    }
+}
+
+/// Start of `date` (local midnight, or the first valid instant after it) in `tz`.
+pub fn start_of_day(tz: Tz, date: NaiveDate) -> AppResult<DateTime<Utc>> {
+    localize(tz, date.and_hms_opt(0, 0, 0).expect("midnight is valid"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn localize_follows_dst() {
+        let tz = parse_tz("Europe/Berlin").unwrap();
+        // CET in winter, CEST in summer: 08:00 local is 07:00 and 06:00 UTC.
+        assert_eq!(
+            localize(tz, local("2025-01-06 08:00"))
+                .unwrap()
+                .to_rfc3339(),
+            "2025-01-06T07:00:00+00:00"
+        );
+        assert_eq!(
+            localize(tz, local("2025-07-07 08:00"))
+                .unwrap()
+                .to_rfc3339(),
+            "2025-07-07T06:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn localize_resolves_transitions() {
+        let tz = parse_tz("Europe/Berlin").unwrap();
+        // 02:30 on 2025-03-30 does not exist; it becomes 03:30 CEST.
+        assert_eq!(
+            localize(tz, local("2025-03-30 02:30"))
+                .unwrap()
+                .to_rfc3339(),
+            "2025-03-30T01:30:00+00:00"
+        );
+        // 02:30 on 2025-10-26 happens twice; the first (CEST) one wins.
+        assert_eq!(
+            localize(tz, local("2025-10-26 02:30"))
+                .unwrap()
+                .to_rfc3339(),
+            "2025-10-26T00:30:00+00:00"
+        );
+    }
+
+    #[test]
+    fn parse_tz_rejects_unknown_zones() {
+        assert!(parse_tz("Mars/Olympus_Mons").is_err());
+    }
+}
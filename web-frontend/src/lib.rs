@@ -215,5 +215,6 @@ pub fn clear_auth_token() {
         .flatten()
     {
         let _ = storage.remove_item("auth_token");
+        let _ = storage.remove_item("refresh_token");
     }
 }
@@ -101,8 +101,25 @@ function initCanvas2DFallback() {
     });
 }
 
+// Trade the stored refresh token for a new pair; false if the session is gone
+async function refreshSession() {
+    const refreshToken = localStorage.getItem('refresh_token');
+    if (!refreshToken) return false;
+    const response = await fetch('/api/auth/refresh', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ refresh_token: refreshToken }),
+    });
+    if (!response.ok) {
+        clearAuthToken();
+        return false;
+    }
+    setAuthTokens(await response.json());
+    return true;
+}
+
 // API calls with fallback
-async function apiCall(path, method = 'GET', body = null) {
+async function apiCall(path, method = 'GET', body = null, retry = true) {
     const url = `/api${path}`;
     const options = {
         method,
@@ -123,6 +140,11 @@ async function apiCall(path, method = 'GET', body = null) {
 
     try {
         const response = await fetch(url, options);
+
+        // Access tokens are short-lived; renew once and replay the request
+        if (response.status === 401 && retry && !['/auth/login', '/auth/register'].includes(path) && await refreshSession()) {
+            return apiCall(path, method, body, false);
+        }
         
         // Handle 204 No Content (empty response) - return early
         if (response.status === 204) {
@@ -186,6 +208,11 @@ function setAuthToken(token) {
     }
 }
 
+function setAuthTokens(response) {
+    setAuthToken(response.token);
+    localStorage.setItem('refresh_token', response.refresh_token);
+}
+
 function getAuthToken() {
     if (wasmModule) {
         return wasmModule.get_auth_token();
@@ -199,6 +226,7 @@ function clearAuthToken() {
     } else {
         localStorage.removeItem('auth_token');
     }
+    localStorage.removeItem('refresh_token');
 }
 
 // UI State
@@ -223,11 +251,25 @@ async function checkAuth() {
     }
 }
 
+async function acceptPendingInvite() {
+    const token = localStorage.getItem('pending_invite');
+    if (!token) return;
+    localStorage.removeItem('pending_invite');
+    try {
+        const joined = await apiCall(`/invites/${encodeURIComponent(token)}/accept`, 'POST');
+        showSuccess(`Joined ${joined.schedule.name}`);
+    } catch (error) {
+        showError('Could not accept invite: ' + error.message);
+    }
+}
+
 async function loadUser() {
     const user = await apiCall('/me');
     currentUser = user;
     document.getElementById('user-email').textContent = user.email;
     document.getElementById('logout-btn').style.display = 'block';
+    document.getElementById('logout-all-btn').style.display = 'block';
+    await acceptPendingInvite();
     await loadSchedules();
     
     // After loading schedules, check URL for schedule ID
@@ -598,6 +640,7 @@ function showLoginScreen() {
     document.getElementById('register-screen').style.display = 'none';
     document.getElementById('main-screen').style.display = 'none';
     document.getElementById('logout-btn').style.display = 'none';
+    document.getElementById('logout-all-btn').style.display = 'none';
     document.getElementById('user-email').textContent = '';
     currentUser = null;
 }
@@ -643,7 +686,7 @@ document.getElementById('login-form').addEventListener('submit', async (e) => {
     
     try {
         const response = await apiCall('/auth/login', 'POST', { email, password });
-        setAuthToken(response.token);
+        setAuthTokens(response);
         await loadUser();
         showMainScreen();
     } catch (error) {
@@ -658,7 +701,7 @@ document.getElementById('register-form').addEventListener('submit', async (e) =>
     
     try {
         const response = await apiCall('/auth/register', 'POST', { email, password });
-        setAuthToken(response.token);
+        setAuthTokens(response);
         await loadUser();
         showMainScreen();
     } catch (error) {
@@ -676,7 +719,76 @@ document.getElementById('show-login').addEventListener('click', (e) => {
     showLoginScreen();
 });
 
-document.getElementById('logout-btn').addEventListener('click', () => {
+document.getElementById('forgot-password').addEventListener('click', async (e) => {
+    e.preventDefault();
+    const email = prompt('Email address for the reset link:', document.getElementById('login-email').value);
+    if (!email) return;
+    try {
+        await apiCall('/auth/password-reset', 'POST', { email });
+        showSuccess('If that address has an account, a reset link is on its way');
+    } catch (error) {
+        showError('Failed to request a reset: ' + error.message);
+    }
+});
+
+// Links from verification and password reset emails carry a token in the query
+async function handleAccountLink() {
+    const params = new URLSearchParams(window.location.search);
+    const verifyToken = params.get('email_verification');
+    const resetToken = params.get('password_reset');
+    const inviteToken = params.get('invite');
+    if (!verifyToken && !resetToken && !inviteToken) return;
+    history.replaceState(null, '', window.location.pathname + window.location.hash);
+
+    if (inviteToken) {
+        // Accepted once the visitor has logged in or registered
+        localStorage.setItem('pending_invite', inviteToken);
+        try {
+            const invite = await apiCall(`/invites/${encodeURIComponent(inviteToken)}`);
+            showSuccess(`You're invited to ${invite.schedule_name}; log in or register to join`);
+        } catch (error) {
+            localStorage.removeItem('pending_invite');
+            showError('Invite: ' + error.message);
+        }
+        return;
+    }
+
+    try {
+        if (verifyToken) {
+            await apiCall('/auth/verify-email', 'POST', { token: verifyToken });
+            showSuccess('Email address confirmed');
+        } else {
+            const password = prompt('Choose a new password (min 8 chars):');
+            if (!password) return;
+            await apiCall('/auth/password-reset/confirm', 'POST', { token: resetToken, password });
+            clearAuthToken();
+            showSuccess('Password changed; log in with your new password');
+        }
+    } catch (error) {
+        showError(error.message);
+    }
+}
+
+document.getElementById('logout-btn').addEventListener('click', async () => {
+    try {
+        await apiCall('/auth/logout', 'POST');
+    } catch (error) {
+        // The session may already be gone; sign out locally regardless
+    }
+    clearAuthToken();
+    currentUser = null;
+    schedules = [];
+    showLoginScreen();
+});
+
+document.getElementById('logout-all-btn').addEventListener('click', async () => {
+    if (!confirm('Sign out on every device?')) return;
+    try {
+        await apiCall('/auth/logout-all', 'POST');
+    } catch (error) {
+        showError('Failed to sign out everywhere: ' + error.message);
+        return;
+    }
     clearAuthToken();
     currentUser = null;
     schedules = [];
@@ -742,7 +854,8 @@ if (newScheduleForm) {
         const schedule = await apiCall('/schedules', 'POST', {
             name,
             subject_type: subjectType,
-            subject_name: subjectName
+            subject_name: subjectName,
+            timezone: Intl.DateTimeFormat().resolvedOptions().timeZone || 'UTC'
         });
         hideNewScheduleModal();
         await loadSchedules();
@@ -806,6 +919,7 @@ if (themeToggleBtn) {
     await checkWasmSupport();
     await loadWasm();
     await initRenderer();
+    await handleAccountLink();
     await checkAuth();
     
     // Calendar navigation - set up event delegation since buttons are in schedule-view
@@ -883,8 +997,10 @@ if (themeToggleBtn) {
                     // First update times if changed
                     if (new Date(editingShift.starts_at).getTime() !== startsAt.getTime() ||
                         new Date(editingShift.ends_at).getTime() !== endsAt.getTime()) {
-                        // Note: API doesn't have update endpoint, so we'd need to delete and recreate
-                        // For now, we'll just update assignment and add comment
+                        await apiCall(`/shifts/${editingShift.id}`, 'PATCH', {
+                            starts_at: startsAt.toISOString(),
+                            ends_at: endsAt.toISOString()
+                        });
                     }
                     
                     // Update assignment
@@ -937,10 +1053,7 @@ if (themeToggleBtn) {
             if (!confirm('Are you sure you want to delete this shift?')) return;
             
             try {
-                // Note: API doesn't have delete endpoint, so we'll just unassign
-                await apiCall(`/shifts/${editingShift.id}/assign`, 'POST', {
-                    assigned_user_id: null
-                });
+                await apiCall(`/shifts/${editingShift.id}`, 'DELETE');
                 closeShiftEditModal();
                 await loadShifts(currentScheduleId, currentWeekStart);
             } catch (error) {